@@ -5,17 +5,67 @@
  */
 pub use self::a2buffering_bloom_filter::A2BufferingBloomFilter;
 pub use self::bigenerational_bloom_filter::BigenerationalBloomFilter;
+pub use self::binary_fuse_filter::BinaryFuseFilter;
+pub use self::blocked_bloom_filter::BlockedBloomFilter;
 pub use self::bloom_filter::BloomFilter;
+pub use self::builder::Builder;
+pub use self::builder::FilterParameters;
+pub use self::builder::Sizing;
+pub use self::clock::Clock;
+pub use self::clock::ManualClock;
+pub use self::clock::SystemClock;
 pub use self::counting_bloom_filter::CountingBloomFilter;
+pub use self::cuckoo_filter::CuckooFilter;
+pub use self::error::Error;
+pub use self::error::Result;
+pub use self::filter::DeletableFilter;
+pub use self::filter::Filter;
 pub use self::generational_bloom_filter::GenerationalBloomFilter;
 pub use self::generations::Generation;
 pub use self::generations::GenerationValues;
+pub use self::hashing::BloomHasher;
+pub use self::hashing::HashFunction;
+pub use self::hashing::IndexingScheme;
+pub use self::hashing::Murmur3Hasher;
+pub use self::hashing::SipHasher;
+pub use self::hashing::Xxh3Hasher;
+
+pub(crate) use self::hashing::HashIndices;
+pub use self::item::Item;
+pub use self::mmap_bloom_filter::MmapBloomFilter;
+pub use self::partitioned_bloom_filter::PartitionedBloomFilter;
+pub use self::quotient_filter::QuotientFilter;
+pub use self::ribbon_filter::RibbonFilter;
+pub use self::scalable_bloom_filter::ScalableBloomFilter;
 pub use self::scope_decay_bloom_filter::ScopeDecayBloomFilter;
+pub use self::stable_bloom_filter::StableBloomFilter;
+pub use self::statistics::FillStatistics;
+pub use self::statistics::SimilarityEstimate;
 
 mod a2buffering_bloom_filter;
 mod bigenerational_bloom_filter;
+mod binary_fuse_filter;
+mod blocked_bloom_filter;
 mod bloom_filter;
+mod builder;
+mod clock;
 mod counting_bloom_filter;
+mod cuckoo_filter;
+mod error;
+mod filter;
 mod generational_bloom_filter;
 mod generations;
+mod hashing;
+mod item;
+mod mmap_bloom_filter;
+pub mod params;
+mod partitioned_bloom_filter;
+mod persistence;
+mod quotient_filter;
+mod ribbon_filter;
+mod scalable_bloom_filter;
 mod scope_decay_bloom_filter;
+#[cfg(feature = "serde")]
+mod serialization;
+mod stable_bloom_filter;
+mod statistics;
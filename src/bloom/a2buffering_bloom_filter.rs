@@ -1,7 +1,7 @@
 /*!
  * a2buffering_bloom_filter.rs
  *
- * Implements a Murmur3-based Active-Active Buffering bloom filter:
+ * Implements a Active-Active Buffering bloom filter:
  *      A2BufferingBloomFilter  -- an Active-Active Buffering bloom filter, which is essentially 2 bloom
  *                                 filters used together to provide alternating "generations". The older
  *                                 generation is recycled when the newer generation becomes full,
@@ -10,11 +10,25 @@
  *                                 bloom filter, it is copied over to the active bloom filter.
  *                                 Does ~not~ support manual deletion.
  */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::persistence::{self, ChecksumReader, ChecksumWriter, FilterKind};
 use crate::bloom::BloomFilter;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
+use crate::bloom::HashFunction;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use std::io::{Read, Write};
 
 /// Create an enum to control which generation of bloom filter
 /// is the active or current generation.
 #[derive(PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 enum GenerationValues {
     ActiveOne,
     ActiveTwo,
@@ -31,6 +45,8 @@ type Generation = GenerationValues;
 ///    * false_positive_rate: False positive rate
 ///    * expected_inserts:    Expected inserts per generation
 ///    * actual_inserts:      Actual inserts, for the current generation
+///    * hasher:              Hash function used by every generation
+///    * indexing:            How indices are derived from the hash function
 pub struct A2BufferingBloomFilter {
     active1: BloomFilter,
     active2: BloomFilter,
@@ -39,6 +55,8 @@ pub struct A2BufferingBloomFilter {
     false_positive_rate: f64,
     expected_inserts: u64,
     actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
 }
 
 /// Implementation of a generational bloom filter
@@ -63,50 +81,112 @@ impl A2BufferingBloomFilter {
         return self.actual_inserts;
     }
 
-    /// Given a desired false positive rate, calculate the length of the BitVec required
-    /// See 'm' in this SO answer: https://stackoverflow.com/a/22467497
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn calculate_len(expected_inserts: f64, false_positive_rate: f64) -> u64 {
-        let two: f64 = 2.0;
-        return ((-1.0 * (expected_inserts) * false_positive_rate.ln()) / two.ln().powf(two)).ceil()
-            as u64;
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
     }
 
-    /// Calculate the number of hashes required
-    /// See 'k' in this SO answer: https://stackoverflow.com/a/22467497
-    /// k = ceil(m/n * ln(2))
-    fn calculate_hash_count(expected_inserts: f64, len: u64) -> u64 {
-        let two: f64 = 2.0;
-        return (((len as f64) / expected_inserts) * two.ln()).ceil() as u64;
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
     }
 
-    /// Create a new BloomFilter
+    /// Create a new A2BufferingBloomFilter, using the default (Murmur3) hash function
     pub fn new(expected_inserts: u64, false_positive_rate: f64) -> A2BufferingBloomFilter {
-        if false_positive_rate <= 0.0 {
-            panic!(
-                "False positive rate must be a positive number. Currently: {}",
-                false_positive_rate
-            );
+        return A2BufferingBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new A2BufferingBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+    ) -> Result<A2BufferingBloomFilter> {
+        return A2BufferingBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new A2BufferingBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> A2BufferingBloomFilter {
+        return A2BufferingBloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new A2BufferingBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> A2BufferingBloomFilter {
+        match A2BufferingBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            indexing,
+        ) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new A2BufferingBloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<A2BufferingBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
         } else if expected_inserts < 1 {
-            panic!(
-                "Expected number of inserts must be a positive number. Currently: {}",
-                expected_inserts
-            );
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
         }
 
-        let len: u64 =
-            A2BufferingBloomFilter::calculate_len(expected_inserts as f64, false_positive_rate);
-        let hash_count: u64 =
-            A2BufferingBloomFilter::calculate_hash_count(expected_inserts as f64, len);
+        let len: u64 = params::optimal_len(expected_inserts, false_positive_rate);
+        let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        return Ok(A2BufferingBloomFilter::from_parameters(
+            &params, hasher, indexing,
+        ));
+    }
 
+    /// Create a new A2BufferingBloomFilter from already validated parameters
+    pub(crate) fn from_parameters(
+        params: &FilterParameters,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> A2BufferingBloomFilter {
         A2BufferingBloomFilter {
-            active1: BloomFilter::new(expected_inserts, false_positive_rate),
-            active2: BloomFilter::new(expected_inserts, false_positive_rate),
+            active1: BloomFilter::from_parameters(params, hasher, indexing),
+            active2: BloomFilter::from_parameters(params, hasher, indexing),
             current_gen: Generation::ActiveOne,
-            hash_count: hash_count,
-            false_positive_rate: false_positive_rate,
-            expected_inserts: expected_inserts,
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
+            expected_inserts: params.get_expected_inserts(),
             actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
         }
     }
 
@@ -127,7 +207,7 @@ impl A2BufferingBloomFilter {
     }
 
     /// Insert a new element into the current generation BloomFilter
-    pub fn insert(&mut self, item: &str) {
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
         if self.actual_inserts + 1 > self.expected_inserts {
             // Recycle the previous generation
             self.recycle();
@@ -149,8 +229,8 @@ impl A2BufferingBloomFilter {
     /// Check whether an element is probably in the filter or not.
     /// If the item is found in the inactive bloom filter, copy it over to the active one.
     /// This is where the behavior of the A2BufferingBloomFilter and GenerationalBloomFilter
-    /// differ
-    pub fn check(&mut self, item: &str) -> bool {
+    /// differ.
+    pub fn check<T: Item + ?Sized>(&mut self, item: &T) -> bool {
         if self.current_gen == Generation::ActiveOne {
             if self.active1.check(item) {
                 return true;
@@ -176,10 +256,189 @@ impl A2BufferingBloomFilter {
         return false;
     }
 
+    /// Estimate the number of distinct items in each generation, the inactive generation
+    /// first and the active generation last
+    pub fn estimate_generation_cardinalities(&self) -> Vec<f64> {
+        return self
+            .ordered_generations()
+            .iter()
+            .map(|bf| bf.estimate_cardinality())
+            .collect();
+    }
+
+    /// Estimate the number of distinct items in both generations. Items copied from the
+    /// inactive to the active generation by check() are only counted once
+    pub fn estimate_cardinality(&self) -> f64 {
+        return BloomFilter::estimate_combined_cardinality(&[&self.active1, &self.active2]);
+    }
+
+    /// Report the fill statistics of each generation, the inactive generation first and the
+    /// active generation last
+    pub fn generation_fill_statistics(&self) -> Vec<FillStatistics> {
+        return self
+            .ordered_generations()
+            .iter()
+            .map(|bf| bf.fill_statistics())
+            .collect();
+    }
+
+    /// Report the fill statistics of both generations together. The false positive rate is
+    /// the probability that an item is a false positive in either generation
+    pub fn fill_statistics(&self) -> FillStatistics {
+        return FillStatistics::combine(&self.generation_fill_statistics());
+    }
+
+    /// The generations, the inactive generation first and the active generation last
+    fn ordered_generations(&self) -> [&BloomFilter; 2] {
+        let (inactive, active) = match self.current_gen {
+            Generation::ActiveOne => (&self.active2, &self.active1),
+            Generation::ActiveTwo => (&self.active1, &self.active2),
+        };
+        return [inactive, active];
+    }
+
     /// Empty out the entire data structure (ie both generations)
     pub fn empty(&mut self) {
         self.recycle();
         self.recycle();
+        self.actual_inserts = 0;
+    }
+
+    /// Save the A2BufferingBloomFilter to a writer, in the crate's versioned binary format,
+    /// including which generation is active
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut writer = ChecksumWriter::new(writer, FilterKind::A2Buffering)?;
+        writer.write_u8(match self.current_gen {
+            Generation::ActiveOne => 0,
+            Generation::ActiveTwo => 1,
+        })?;
+        writer.write_u64(self.actual_inserts)?;
+        self.active1.write_body(&mut writer)?;
+        self.active2.write_body(&mut writer)?;
+        return writer.finish();
+    }
+
+    /// Load a A2BufferingBloomFilter saved with save(), restoring which generation is active
+    /// and how full it is
+    pub fn load<R: Read>(reader: &mut R) -> Result<A2BufferingBloomFilter> {
+        let mut reader = ChecksumReader::new(reader, FilterKind::A2Buffering)?;
+        let current_gen: Generation = match reader.read_u8()? {
+            0 => Generation::ActiveOne,
+            1 => Generation::ActiveTwo,
+            _ => return Err(Error::Corrupt("unknown current generation")),
+        };
+        let actual_inserts: u64 = reader.read_u64()?;
+        let mut generations: Vec<BloomFilter> = persistence::read_generations(&mut reader, 2)?;
+        reader.finish()?;
+        let active2: BloomFilter = generations.remove(1);
+        let active1: BloomFilter = generations.remove(0);
+        return A2BufferingBloomFilter::from_generations(
+            current_gen,
+            actual_inserts,
+            active1,
+            active2,
+        );
+    }
+
+    /// Restore a A2BufferingBloomFilter from its stored generations, the active generation and its
+    /// insert count, checking that they are consistent
+    fn from_generations(
+        current_gen: Generation,
+        actual_inserts: u64,
+        active1: BloomFilter,
+        active2: BloomFilter,
+    ) -> Result<A2BufferingBloomFilter> {
+        if !active1.has_same_parameters(&active2) {
+            return Err(Error::Corrupt("generations have different parameters"));
+        } else if actual_inserts > active1.get_expected_inserts() {
+            return Err(Error::Corrupt("actual inserts exceed expected inserts"));
+        }
+        Ok(A2BufferingBloomFilter {
+            hash_count: active1.get_hash_count(),
+            false_positive_rate: active1.get_false_positive_rate(),
+            expected_inserts: active1.get_expected_inserts(),
+            actual_inserts: actual_inserts,
+            hasher: active1.get_hasher(),
+            indexing: active1.get_indexing(),
+            active1: active1,
+            active2: active2,
+            current_gen: current_gen,
+        })
+    }
+}
+
+impl Filter for A2BufferingBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        A2BufferingBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return A2BufferingBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        A2BufferingBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return A2BufferingBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return A2BufferingBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return A2BufferingBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return A2BufferingBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use serde::de;
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialized form of a A2BufferingBloomFilter, validated before it becomes one
+    #[derive(Deserialize)]
+    #[serde(rename = "A2BufferingBloomFilter", deny_unknown_fields)]
+    struct A2BufferingBloomFilterRepr {
+        current_gen: Generation,
+        actual_inserts: u64,
+        active1: BloomFilter,
+        active2: BloomFilter,
+    }
+
+    impl Serialize for A2BufferingBloomFilter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("A2BufferingBloomFilter", 4)?;
+            state.serialize_field("current_gen", &self.current_gen)?;
+            state.serialize_field("actual_inserts", &self.actual_inserts)?;
+            state.serialize_field("active1", &self.active1)?;
+            state.serialize_field("active2", &self.active2)?;
+            return state.end();
+        }
+    }
+
+    impl<'de> Deserialize<'de> for A2BufferingBloomFilter {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<A2BufferingBloomFilter, D::Error> {
+            let repr: A2BufferingBloomFilterRepr =
+                A2BufferingBloomFilterRepr::deserialize(deserializer)?;
+            return A2BufferingBloomFilter::from_generations(
+                repr.current_gen,
+                repr.actual_inserts,
+                repr.active1,
+                repr.active2,
+            )
+            .map_err(de::Error::custom);
+        }
     }
 }
 
@@ -205,11 +464,11 @@ mod tests {
         let s2 = "This is another string"; // Inserted
         let s3 = "This is a third string"; // Not inserted
         let mut bf: A2BufferingBloomFilter = A2BufferingBloomFilter::new(2, 0.05);
-        bf.insert(&s);
-        bf.insert(&s2);
-        assert!(bf.check(&s)); // Included
-        assert!(bf.check(&s2)); // Included
-        assert!(!bf.check(&s3)); // Not included
+        bf.insert(s);
+        bf.insert(s2);
+        assert!(bf.check(s)); // Included
+        assert!(bf.check(s2)); // Included
+        assert!(!bf.check(s3)); // Not included
     }
 
     #[test]
@@ -243,37 +502,20 @@ mod tests {
     }
 
     #[test]
-    /// Test that we get the expected value for calculating the length of the
-    /// BitVec using the computation:
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn test_calculate_len() {
-        let m: u64 = A2BufferingBloomFilter::calculate_len(100 as f64, 0.01);
-        assert_eq!(m, 959);
-        let m2: u64 = A2BufferingBloomFilter::calculate_len(1000 as f64, 0.001);
-        assert_eq!(m2, 14378);
-        let m3: u64 = A2BufferingBloomFilter::calculate_len(5 as f64, 0.1);
-        assert_eq!(m3, 24);
-        let m4: u64 = A2BufferingBloomFilter::calculate_len(10000 as f64, 0.01);
-        assert_eq!(m4, 95851);
-        let m5: u64 = A2BufferingBloomFilter::calculate_len(216553 as f64, 0.01);
-        assert_eq!(m5, 2075674);
-    }
-
-    #[test]
-    /// Test that we get the expected value for calculating the number of iterations
-    /// if hashing we need to perform
-    /// k = ceil(m/n * ln(2))
-    fn test_calculate_hash_count() {
-        let k: u64 = A2BufferingBloomFilter::calculate_hash_count(100 as f64, 959);
-        assert_eq!(k, 7);
-        let k2: u64 = A2BufferingBloomFilter::calculate_hash_count(1000 as f64, 14378);
-        assert_eq!(k2, 10);
-        let k3: u64 = A2BufferingBloomFilter::calculate_hash_count(5 as f64, 24);
-        assert_eq!(k3, 4);
-        let k4: u64 = A2BufferingBloomFilter::calculate_hash_count(10000 as f64, 95851);
-        assert_eq!(k4, 7);
-        let k5: u64 = A2BufferingBloomFilter::calculate_hash_count(216553 as f64, 2075674);
-        assert_eq!(k5, 7);
+    /// Test that items copied into the active generation by check() are only counted once
+    fn test_estimate_cardinality() {
+        let mut bf: A2BufferingBloomFilter = A2BufferingBloomFilter::new(500, 0.01);
+        for i in 0..700 {
+            bf.insert(&i);
+        }
+        for i in 0..100 {
+            assert!(bf.check(&i));
+        }
+        let per_generation: Vec<f64> = bf.estimate_generation_cardinalities();
+        assert!((per_generation[0] - 500.0).abs() < 25.0);
+        assert!((per_generation[1] - 300.0).abs() < 15.0);
+        let estimate: f64 = bf.estimate_cardinality();
+        assert!((estimate - 700.0).abs() < 35.0, "{}", estimate);
     }
 
     #[test]
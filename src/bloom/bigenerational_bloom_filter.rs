@@ -1,15 +1,28 @@
 /*!
  * bigenerational_bloom_filter.rs
  *
- * Implements a Murmur3-based generational bloom filter, with a fixed 2 generation scheme.
+ * Implements a generational bloom filter, with a fixed 2 generation scheme.
  *      BigenerationalBloomFilter -- a generational bloom filter, which is essentially 2 bloom filters
  *                                   used together to provide alternating "generations". The older
  *                                   generation is recycled when the newer generation becomes full,
  *                                   in a pattern similar to Blue-Green deployment.
  *                                   Does ~not~ support manual deletion.
  */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::persistence::{self, ChecksumReader, ChecksumWriter, FilterKind};
 use crate::bloom::BloomFilter;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
 use crate::bloom::Generation;
+use crate::bloom::HashFunction;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use std::io::{Read, Write};
 
 /// BigenerationalBloomFilter struct:
 ///    * generation_a:        A single generation's BloomFilter, owned ptr
@@ -19,6 +32,8 @@ use crate::bloom::Generation;
 ///    * false_positive_rate: False positive rate
 ///    * expected_inserts:    Expected inserts per generation
 ///    * actual_inserts:      Actual inserts, for the current generation
+///    * hasher:              Hash function used by every generation
+///    * indexing:            How indices are derived from the hash function
 pub struct BigenerationalBloomFilter {
     generation_a: BloomFilter,
     generation_b: BloomFilter,
@@ -27,6 +42,8 @@ pub struct BigenerationalBloomFilter {
     false_positive_rate: f64,
     expected_inserts: u64,
     actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
 }
 
 /// Implementation of a generational bloom filter
@@ -51,50 +68,112 @@ impl BigenerationalBloomFilter {
         return self.actual_inserts;
     }
 
-    /// Given a desired false positive rate, calculate the length of the BitVec required
-    /// See 'm' in this SO answer: https://stackoverflow.com/a/22467497
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn calculate_len(expected_inserts: f64, false_positive_rate: f64) -> u64 {
-        let two: f64 = 2.0;
-        return ((-1.0 * (expected_inserts) * false_positive_rate.ln()) / two.ln().powf(two)).ceil()
-            as u64;
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
     }
 
-    /// Calculate the number of hashes required
-    /// See 'k' in this SO answer: https://stackoverflow.com/a/22467497
-    /// k = ceil(m/n * ln(2))
-    fn calculate_hash_count(expected_inserts: f64, len: u64) -> u64 {
-        let two: f64 = 2.0;
-        return (((len as f64) / expected_inserts) * two.ln()).ceil() as u64;
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
     }
 
-    /// Create a new BloomFilter
+    /// Create a new BigenerationalBloomFilter, using the default (Murmur3) hash function
     pub fn new(expected_inserts: u64, false_positive_rate: f64) -> BigenerationalBloomFilter {
-        if false_positive_rate <= 0.0 {
-            panic!(
-                "False positive rate must be a positive number. Currently: {}",
-                false_positive_rate
-            );
+        return BigenerationalBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new BigenerationalBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+    ) -> Result<BigenerationalBloomFilter> {
+        return BigenerationalBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new BigenerationalBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> BigenerationalBloomFilter {
+        return BigenerationalBloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new BigenerationalBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> BigenerationalBloomFilter {
+        match BigenerationalBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            indexing,
+        ) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new BigenerationalBloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<BigenerationalBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
         } else if expected_inserts < 1 {
-            panic!(
-                "Expected number of inserts must be a positive number. Currently: {}",
-                expected_inserts
-            );
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
         }
 
-        let len: u64 =
-            BigenerationalBloomFilter::calculate_len(expected_inserts as f64, false_positive_rate);
-        let hash_count: u64 =
-            BigenerationalBloomFilter::calculate_hash_count(expected_inserts as f64, len);
+        let len: u64 = params::optimal_len(expected_inserts, false_positive_rate);
+        let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        return Ok(BigenerationalBloomFilter::from_parameters(
+            &params, hasher, indexing,
+        ));
+    }
 
+    /// Create a new BigenerationalBloomFilter from already validated parameters
+    pub(crate) fn from_parameters(
+        params: &FilterParameters,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> BigenerationalBloomFilter {
         BigenerationalBloomFilter {
-            generation_a: BloomFilter::new(expected_inserts, false_positive_rate),
-            generation_b: BloomFilter::new(expected_inserts, false_positive_rate),
+            generation_a: BloomFilter::from_parameters(params, hasher, indexing),
+            generation_b: BloomFilter::from_parameters(params, hasher, indexing),
             current_gen: Generation::A,
-            hash_count: hash_count,
-            false_positive_rate: false_positive_rate,
-            expected_inserts: expected_inserts,
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
+            expected_inserts: params.get_expected_inserts(),
             actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
         }
     }
 
@@ -115,7 +194,7 @@ impl BigenerationalBloomFilter {
     }
 
     /// Insert a new element into the current generation BloomFilter
-    pub fn insert(&mut self, item: &str) {
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
         if self.actual_inserts + 1 > self.expected_inserts {
             // Recycle the previous generation
             self.recycle();
@@ -135,14 +214,196 @@ impl BigenerationalBloomFilter {
     }
 
     /// Check whether an element is probably in the filter or not
-    pub fn check(&self, item: &str) -> bool {
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
         return self.generation_a.check(item) || self.generation_b.check(item);
     }
 
+    /// Estimate the number of distinct items in each generation, the inactive generation
+    /// first and the current generation last
+    pub fn estimate_generation_cardinalities(&self) -> Vec<f64> {
+        return self
+            .ordered_generations()
+            .iter()
+            .map(|bf| bf.estimate_cardinality())
+            .collect();
+    }
+
+    /// Estimate the number of distinct items in both generations. Items inserted into both
+    /// generations are only counted once
+    pub fn estimate_cardinality(&self) -> f64 {
+        return BloomFilter::estimate_combined_cardinality(&[
+            &self.generation_a,
+            &self.generation_b,
+        ]);
+    }
+
+    /// Report the fill statistics of each generation, the inactive generation first and the
+    /// current generation last
+    pub fn generation_fill_statistics(&self) -> Vec<FillStatistics> {
+        return self
+            .ordered_generations()
+            .iter()
+            .map(|bf| bf.fill_statistics())
+            .collect();
+    }
+
+    /// Report the fill statistics of both generations together. The false positive rate is
+    /// the probability that an item is a false positive in either generation
+    pub fn fill_statistics(&self) -> FillStatistics {
+        return FillStatistics::combine(&self.generation_fill_statistics());
+    }
+
+    /// The generations, the inactive generation first and the current generation last
+    fn ordered_generations(&self) -> [&BloomFilter; 2] {
+        let (inactive, current) = match self.current_gen {
+            Generation::A => (&self.generation_b, &self.generation_a),
+            Generation::B => (&self.generation_a, &self.generation_b),
+        };
+        return [inactive, current];
+    }
+
     /// Empty out the entire data structure (ie both generations)
     pub fn empty(&mut self) {
         self.recycle();
         self.recycle();
+        self.actual_inserts = 0;
+    }
+
+    /// Save the BigenerationalBloomFilter to a writer, in the crate's versioned binary format,
+    /// including which generation is active
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut writer = ChecksumWriter::new(writer, FilterKind::Bigenerational)?;
+        writer.write_u8(match self.current_gen {
+            Generation::A => 0,
+            Generation::B => 1,
+        })?;
+        writer.write_u64(self.actual_inserts)?;
+        self.generation_a.write_body(&mut writer)?;
+        self.generation_b.write_body(&mut writer)?;
+        return writer.finish();
+    }
+
+    /// Load a BigenerationalBloomFilter saved with save(), restoring which generation is active
+    /// and how full it is
+    pub fn load<R: Read>(reader: &mut R) -> Result<BigenerationalBloomFilter> {
+        let mut reader = ChecksumReader::new(reader, FilterKind::Bigenerational)?;
+        let current_gen: Generation = match reader.read_u8()? {
+            0 => Generation::A,
+            1 => Generation::B,
+            _ => return Err(Error::Corrupt("unknown current generation")),
+        };
+        let actual_inserts: u64 = reader.read_u64()?;
+        let mut generations: Vec<BloomFilter> = persistence::read_generations(&mut reader, 2)?;
+        reader.finish()?;
+        let generation_b: BloomFilter = generations.remove(1);
+        let generation_a: BloomFilter = generations.remove(0);
+        return BigenerationalBloomFilter::from_generations(
+            current_gen,
+            actual_inserts,
+            generation_a,
+            generation_b,
+        );
+    }
+
+    /// Restore a BigenerationalBloomFilter from its stored generations, the active generation and its
+    /// insert count, checking that they are consistent
+    fn from_generations(
+        current_gen: Generation,
+        actual_inserts: u64,
+        generation_a: BloomFilter,
+        generation_b: BloomFilter,
+    ) -> Result<BigenerationalBloomFilter> {
+        if !generation_a.has_same_parameters(&generation_b) {
+            return Err(Error::Corrupt("generations have different parameters"));
+        } else if actual_inserts > generation_a.get_expected_inserts() {
+            return Err(Error::Corrupt("actual inserts exceed expected inserts"));
+        }
+        Ok(BigenerationalBloomFilter {
+            hash_count: generation_a.get_hash_count(),
+            false_positive_rate: generation_a.get_false_positive_rate(),
+            expected_inserts: generation_a.get_expected_inserts(),
+            actual_inserts: actual_inserts,
+            hasher: generation_a.get_hasher(),
+            indexing: generation_a.get_indexing(),
+            generation_a: generation_a,
+            generation_b: generation_b,
+            current_gen: current_gen,
+        })
+    }
+}
+
+impl Filter for BigenerationalBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        BigenerationalBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return BigenerationalBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        BigenerationalBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return BigenerationalBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return BigenerationalBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return BigenerationalBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return BigenerationalBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use serde::de;
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialized form of a BigenerationalBloomFilter, validated before it becomes one
+    #[derive(Deserialize)]
+    #[serde(rename = "BigenerationalBloomFilter", deny_unknown_fields)]
+    struct BigenerationalBloomFilterRepr {
+        current_gen: Generation,
+        actual_inserts: u64,
+        generation_a: BloomFilter,
+        generation_b: BloomFilter,
+    }
+
+    impl Serialize for BigenerationalBloomFilter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("BigenerationalBloomFilter", 4)?;
+            state.serialize_field("current_gen", &self.current_gen)?;
+            state.serialize_field("actual_inserts", &self.actual_inserts)?;
+            state.serialize_field("generation_a", &self.generation_a)?;
+            state.serialize_field("generation_b", &self.generation_b)?;
+            return state.end();
+        }
+    }
+
+    impl<'de> Deserialize<'de> for BigenerationalBloomFilter {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<BigenerationalBloomFilter, D::Error> {
+            let repr: BigenerationalBloomFilterRepr =
+                BigenerationalBloomFilterRepr::deserialize(deserializer)?;
+            return BigenerationalBloomFilter::from_generations(
+                repr.current_gen,
+                repr.actual_inserts,
+                repr.generation_a,
+                repr.generation_b,
+            )
+            .map_err(de::Error::custom);
+        }
     }
 }
 
@@ -168,11 +429,11 @@ mod tests {
         let s2 = "This is another string"; // Inserted
         let s3 = "This is a third string"; // Not inserted
         let mut bf: BigenerationalBloomFilter = BigenerationalBloomFilter::new(2, 0.05);
-        bf.insert(&s);
-        bf.insert(&s2);
-        assert!(bf.check(&s)); // Included
-        assert!(bf.check(&s2)); // Included
-        assert!(!bf.check(&s3)); // Not included
+        bf.insert(s);
+        bf.insert(s2);
+        assert!(bf.check(s)); // Included
+        assert!(bf.check(s2)); // Included
+        assert!(!bf.check(s3)); // Not included
     }
 
     #[test]
@@ -206,37 +467,18 @@ mod tests {
     }
 
     #[test]
-    /// Test that we get the expected value for calculating the length of the
-    /// BitVec using the computation:
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn test_calculate_len() {
-        let m: u64 = BigenerationalBloomFilter::calculate_len(100 as f64, 0.01);
-        assert_eq!(m, 959);
-        let m2: u64 = BigenerationalBloomFilter::calculate_len(1000 as f64, 0.001);
-        assert_eq!(m2, 14378);
-        let m3: u64 = BigenerationalBloomFilter::calculate_len(5 as f64, 0.1);
-        assert_eq!(m3, 24);
-        let m4: u64 = BigenerationalBloomFilter::calculate_len(10000 as f64, 0.01);
-        assert_eq!(m4, 95851);
-        let m5: u64 = BigenerationalBloomFilter::calculate_len(216553 as f64, 0.01);
-        assert_eq!(m5, 2075674);
-    }
-
-    #[test]
-    /// Test that we get the expected value for calculating the number of iterations
-    /// if hashing we need to perform
-    /// k = ceil(m/n * ln(2))
-    fn test_calculate_hash_count() {
-        let k: u64 = BigenerationalBloomFilter::calculate_hash_count(100 as f64, 959);
-        assert_eq!(k, 7);
-        let k2: u64 = BigenerationalBloomFilter::calculate_hash_count(1000 as f64, 14378);
-        assert_eq!(k2, 10);
-        let k3: u64 = BigenerationalBloomFilter::calculate_hash_count(5 as f64, 24);
-        assert_eq!(k3, 4);
-        let k4: u64 = BigenerationalBloomFilter::calculate_hash_count(10000 as f64, 95851);
-        assert_eq!(k4, 7);
-        let k5: u64 = BigenerationalBloomFilter::calculate_hash_count(216553 as f64, 2075674);
-        assert_eq!(k5, 7);
+    /// Test that the generation estimates are ordered inactive first, and the total counts
+    /// items in both generations once
+    fn test_estimate_cardinality() {
+        let mut bf: BigenerationalBloomFilter = BigenerationalBloomFilter::new(500, 0.01);
+        for i in 0..700 {
+            bf.insert(&i);
+        }
+        let per_generation: Vec<f64> = bf.estimate_generation_cardinalities();
+        assert!((per_generation[0] - 500.0).abs() < 25.0);
+        assert!((per_generation[1] - 200.0).abs() < 10.0);
+        let estimate: f64 = bf.estimate_cardinality();
+        assert!((estimate - 700.0).abs() < 35.0, "{}", estimate);
     }
 
     #[test]
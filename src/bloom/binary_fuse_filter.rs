@@ -0,0 +1,518 @@
+/*!
+ * binary_fuse_filter.rs
+ *
+ * Implements a static 3-wise binary fuse filter (Graf & Lemire, 2022):
+ *      BinaryFuseFilter -- an immutable filter built in one go from a set of items. Each item
+ *                          maps to three slots in consecutive segments of an array of 8- or
+ *                          16-bit fingerprints, filled in so that the XOR of an item's three
+ *                          slots is its fingerprint. It takes about 1.125 * f bits per item for
+ *                          a false positive rate of 2^-f, where a BloomFilter takes about
+ *                          1.44 * f. Construction retries with a new seed when the slots cannot
+ *                          be filled in, up to a bounded number of attempts.
+ *                          Does not support insertion or deletion.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::hashing::fmix64;
+use crate::bloom::params;
+use crate::bloom::persistence::{ChecksumReader, ChecksumWriter, FilterKind};
+use crate::bloom::BloomHasher;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::HashFunction;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use std::io::{Read, Write};
+
+/// Number of seeds tried before construction gives up
+pub const MAX_ATTEMPTS: u32 = 100;
+/// Starting state of the sequence of seeds, so that the same items always build the same
+/// filter
+const SEED_STATE: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// BinaryFuseFilter struct:
+///    * fingerprints:     Fingerprint array, fingerprint_bits / 8 little-endian bytes per slot
+///    * fingerprint_bits: Size of a fingerprint, 8 or 16 bits
+///    * seed:             Seed mixed into each item's hash, picked during construction
+///    * segment_length:   Number of slots per segment, a power of two
+///    * segment_count:    Number of segments an item's first slot can fall in, 2 less than in all
+///    * num_items:        Number of distinct items the filter was built from
+///    * hasher:           Hash function used to hash the items
+#[derive(Clone)]
+pub struct BinaryFuseFilter {
+    fingerprints: Vec<u8>,
+    fingerprint_bits: u32,
+    seed: u64,
+    segment_length: u64,
+    segment_count: u64,
+    num_items: u64,
+    hasher: HashFunction,
+}
+
+/// Implementation of a static binary fuse filter
+impl BinaryFuseFilter {
+    /// Getter for the number of slots in the fingerprint array
+    pub fn get_len(&self) -> u64 {
+        return (self.segment_count + 2) * self.segment_length;
+    }
+
+    /// Getter for fingerprint_bits
+    pub fn get_fingerprint_bits(&self) -> u32 {
+        return self.fingerprint_bits;
+    }
+
+    /// Getter for the false positive rate, 2^-fingerprint_bits
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return params::binary_fuse_false_positive_rate(self.fingerprint_bits);
+    }
+
+    /// Getter for num_items
+    pub fn get_num_items(&self) -> u64 {
+        return self.num_items;
+    }
+
+    /// Getter for the space used per item, in bits. 0 for a filter built from an empty set
+    pub fn get_bits_per_item(&self) -> f64 {
+        if self.num_items == 0 {
+            return 0.0;
+        }
+        return (self.get_len() * self.fingerprint_bits as u64) as f64 / self.num_items as f64;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Build a new BinaryFuseFilter from the given items, using the default (Murmur3) hash
+    /// function
+    pub fn new<'a, T, I>(items: I, fingerprint_bits: u32) -> BinaryFuseFilter
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        return BinaryFuseFilter::with_hasher(items, fingerprint_bits, HashFunction::default());
+    }
+
+    /// Build a new BinaryFuseFilter from the given items, using the default (Murmur3) hash
+    /// function. Returns an error if the fingerprint size is invalid or construction fails
+    pub fn try_new<'a, T, I>(items: I, fingerprint_bits: u32) -> Result<BinaryFuseFilter>
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        return BinaryFuseFilter::try_with_hasher(items, fingerprint_bits, HashFunction::default());
+    }
+
+    /// Build a new BinaryFuseFilter from the given items, using the given hash function
+    pub fn with_hasher<'a, T, I>(
+        items: I,
+        fingerprint_bits: u32,
+        hasher: HashFunction,
+    ) -> BinaryFuseFilter
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        match BinaryFuseFilter::try_with_hasher(items, fingerprint_bits, hasher) {
+            Ok(bff) => return bff,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Build a new BinaryFuseFilter from the given items, using the given hash function.
+    /// The items are read once, and only their hashes are kept. Duplicate items are allowed.
+    /// Returns an error if the fingerprint size is not 8 or 16, or Error::ConstructionFailed
+    /// if no seed among MAX_ATTEMPTS gives a valid filter
+    pub fn try_with_hasher<'a, T, I>(
+        items: I,
+        fingerprint_bits: u32,
+        hasher: HashFunction,
+    ) -> Result<BinaryFuseFilter>
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        if fingerprint_bits != 8 && fingerprint_bits != 16 {
+            return Err(Error::InvalidFingerprintBits(fingerprint_bits));
+        }
+        let keys: Vec<u64> = items
+            .into_iter()
+            .map(|item| item.with_bytes(|bytes| hasher.hash128(bytes, 0) as u64))
+            .collect();
+        return BinaryFuseFilter::build(keys, fingerprint_bits, hasher, MAX_ATTEMPTS);
+    }
+
+    /// Build the filter from item hashes, trying up to max_attempts seeds. Duplicate hashes
+    /// can never be placed, so after the first failure they are removed (and the array
+    /// resized to match) before trying again
+    fn build(
+        mut keys: Vec<u64>,
+        fingerprint_bits: u32,
+        hasher: HashFunction,
+        max_attempts: u32,
+    ) -> Result<BinaryFuseFilter> {
+        let mut seed_state: u64 = SEED_STATE;
+        for attempt in 0..max_attempts {
+            if attempt == 1 {
+                keys.sort_unstable();
+                keys.dedup();
+            }
+            let segment_length: u64 = params::binary_fuse_segment_length(keys.len() as u64);
+            let mut bff: BinaryFuseFilter = BinaryFuseFilter {
+                fingerprints: vec![],
+                fingerprint_bits: fingerprint_bits,
+                seed: next_seed(&mut seed_state),
+                segment_length: segment_length,
+                segment_count: params::binary_fuse_segment_count(keys.len() as u64, segment_length),
+                num_items: keys.len() as u64,
+                hasher: hasher,
+            };
+            if let Some(order) = bff.peel(&keys) {
+                bff.fingerprints = vec![0; (bff.get_len() * bff.width()) as usize];
+                bff.assign(&order);
+                return Ok(bff);
+            }
+        }
+        return Err(Error::ConstructionFailed(max_attempts));
+    }
+
+    /// Find an order to fill in the slots: repeatedly take a slot that only one remaining item
+    /// maps to, and remove that item. Returns the (hash, position of the slot among the item's
+    /// three) of each item in the order it was removed, or None if some items could not be
+    fn peel(&self, keys: &[u64]) -> Option<Vec<(u64, usize)>> {
+        let len: usize = self.get_len() as usize;
+        // For each slot, the number of items mapping to it, the XOR of their hashes, and the
+        // XOR of the slot's position among each item's three
+        let mut counts: Vec<u32> = vec![0; len];
+        let mut hashes: Vec<u64> = vec![0; len];
+        let mut positions: Vec<u8> = vec![0; len];
+        for key in keys {
+            let hash: u64 = fmix64(key.wrapping_add(self.seed));
+            for (position, slot) in self.slots(hash).iter().enumerate() {
+                counts[*slot] += 1;
+                hashes[*slot] ^= hash;
+                positions[*slot] ^= position as u8;
+            }
+        }
+
+        let mut alone: Vec<usize> = (0..len).filter(|slot| counts[*slot] == 1).collect();
+        let mut order: Vec<(u64, usize)> = Vec::with_capacity(keys.len());
+        while let Some(slot) = alone.pop() {
+            if counts[slot] != 1 {
+                continue;
+            }
+            let hash: u64 = hashes[slot];
+            order.push((hash, positions[slot] as usize));
+            for (position, other) in self.slots(hash).iter().enumerate() {
+                counts[*other] -= 1;
+                hashes[*other] ^= hash;
+                positions[*other] ^= position as u8;
+                if counts[*other] == 1 {
+                    alone.push(*other);
+                }
+            }
+        }
+        if order.len() == keys.len() {
+            return Some(order);
+        }
+        return None;
+    }
+
+    /// Fill in the slots in the reverse of the peeling order, so that each item's slot is set
+    /// after every other item sharing its other two slots
+    fn assign(&mut self, order: &[(u64, usize)]) {
+        for &(hash, found) in order.iter().rev() {
+            let slots: [usize; 3] = self.slots(hash);
+            let mut fingerprint: u64 = self.fingerprint(hash);
+            for (position, slot) in slots.iter().enumerate() {
+                if position != found {
+                    fingerprint ^= self.get_fingerprint(*slot);
+                }
+            }
+            self.set_fingerprint(slots[found], fingerprint);
+        }
+    }
+
+    /// The three slots of a hash: one in each of three consecutive segments, the first picked
+    /// from the high bits of the hash and the other two offset by its low bits
+    fn slots(&self, hash: u64) -> [usize; 3] {
+        let mask: u64 = self.segment_length - 1;
+        let first: u64 =
+            ((hash as u128 * (self.segment_count * self.segment_length) as u128) >> 64) as u64;
+        let second: u64 = (first + self.segment_length) ^ ((hash >> 18) & mask);
+        let third: u64 = (first + 2 * self.segment_length) ^ (hash & mask);
+        return [first as usize, second as usize, third as usize];
+    }
+
+    /// The fingerprint of a hash
+    fn fingerprint(&self, hash: u64) -> u64 {
+        return (hash ^ (hash >> 32)) & ((1 << self.fingerprint_bits) - 1);
+    }
+
+    /// Number of bytes per fingerprint
+    fn width(&self) -> u64 {
+        return (self.fingerprint_bits / 8) as u64;
+    }
+
+    /// Read the fingerprint in the given slot
+    fn get_fingerprint(&self, slot: usize) -> u64 {
+        let width: usize = self.width() as usize;
+        let mut bytes: [u8; 8] = [0; 8];
+        bytes[..width].copy_from_slice(&self.fingerprints[slot * width..(slot + 1) * width]);
+        return u64::from_le_bytes(bytes);
+    }
+
+    /// Write the fingerprint in the given slot
+    fn set_fingerprint(&mut self, slot: usize, fingerprint: u64) {
+        let width: usize = self.width() as usize;
+        self.fingerprints[slot * width..(slot + 1) * width]
+            .copy_from_slice(&fingerprint.to_le_bytes()[..width]);
+    }
+
+    /// Check whether an element is probably in the filter or not. Items the filter was built
+    /// from are always found
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        if self.num_items == 0 {
+            return false;
+        }
+        let key: u64 = item.with_bytes(|bytes| self.hasher.hash128(bytes, 0) as u64);
+        let hash: u64 = fmix64(key.wrapping_add(self.seed));
+        let slots: [usize; 3] = self.slots(hash);
+        return self.fingerprint(hash)
+            == self.get_fingerprint(slots[0])
+                ^ self.get_fingerprint(slots[1])
+                ^ self.get_fingerprint(slots[2]);
+    }
+
+    /// Report the slots, the slots holding a nonzero fingerprint and the false positive rate,
+    /// 2^-f: however the slots are filled, an absent item's fingerprint is compared with the
+    /// XOR of three of them. A filter built from an empty set matches nothing
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let occupied: u64 = (0..self.get_len() as usize)
+            .filter(|slot| self.get_fingerprint(*slot) != 0)
+            .count() as u64;
+        let rate: f64 = if self.num_items == 0 {
+            0.0
+        } else {
+            self.get_false_positive_rate()
+        };
+        return FillStatistics::with_false_positive_rate(self.get_len(), occupied, rate);
+    }
+
+    /// Save the filter in the crate's binary format (see persistence.rs), with a body of:
+    ///      fingerprint_bits: u8
+    ///      hasher:           the hash algorithm and its seed
+    ///      seed:             u64
+    ///      segment_length:   u64
+    ///      segment_count:    u64
+    ///      num_items:        u64
+    ///      fingerprints:     length-prefixed payload
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut writer = ChecksumWriter::new(writer, FilterKind::BinaryFuse)?;
+        writer.write_u8(self.fingerprint_bits as u8)?;
+        writer.write_hasher(self.hasher)?;
+        writer.write_u64(self.seed)?;
+        writer.write_u64(self.segment_length)?;
+        writer.write_u64(self.segment_count)?;
+        writer.write_u64(self.num_items)?;
+        writer.write_payload(&self.fingerprints)?;
+        return writer.finish();
+    }
+
+    /// Load a BinaryFuseFilter saved with save(), validating its header and checksum
+    pub fn load<R: Read>(reader: &mut R) -> Result<BinaryFuseFilter> {
+        let mut reader = ChecksumReader::new(reader, FilterKind::BinaryFuse)?;
+        let fingerprint_bits: u32 = reader.read_u8()? as u32;
+        if fingerprint_bits != 8 && fingerprint_bits != 16 {
+            return Err(Error::InvalidFingerprintBits(fingerprint_bits));
+        }
+        let hasher: HashFunction = reader.read_hasher()?;
+        if reader.get_version() < 2 {
+            // Version 1 also stored an indexing scheme, which the filter doesn't use
+            reader.read_indexing()?;
+        }
+        let seed: u64 = reader.read_u64()?;
+        let segment_length: u64 = reader.read_u64()?;
+        let segment_count: u64 = reader.read_u64()?;
+        let num_items: u64 = reader.read_u64()?;
+        if !segment_length.is_power_of_two()
+            || segment_length > params::BINARY_FUSE_MAX_SEGMENT_LENGTH
+        {
+            return Err(Error::Corrupt("invalid segment length"));
+        }
+        let payload_len: u64 = segment_count
+            .checked_add(2)
+            .and_then(|segments| segments.checked_mul(segment_length))
+            .and_then(|len| len.checked_mul((fingerprint_bits / 8) as u64))
+            .ok_or(Error::Corrupt("invalid segment count"))?;
+        if segment_count < 1 {
+            return Err(Error::Corrupt("invalid segment count"));
+        }
+        let fingerprints: Vec<u8> = reader.read_payload(payload_len)?;
+        reader.finish()?;
+
+        return Ok(BinaryFuseFilter {
+            fingerprints: fingerprints,
+            fingerprint_bits: fingerprint_bits,
+            seed: seed,
+            segment_length: segment_length,
+            segment_count: segment_count,
+            num_items: num_items,
+            hasher: hasher,
+        });
+    }
+}
+
+/// Advance a SplitMix64 sequence and return its next value
+fn next_seed(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z: u64 = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    return z ^ (z >> 31);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that every item is found, and the false positive rate is close to 2^-f
+    fn test_check() {
+        let items: Vec<u64> = (0..10000).collect();
+        for &bits in &[8, 16] {
+            let bff: BinaryFuseFilter = BinaryFuseFilter::new(&items, bits);
+            assert_eq!(bff.get_num_items(), 10000);
+            assert_eq!(
+                bff.fingerprints.len() as u64,
+                bff.get_len() * bits as u64 / 8
+            );
+            assert!(items.iter().all(|i| bff.check(i)));
+            let stats: FillStatistics = bff.fill_statistics();
+            assert_eq!(stats.get_len(), bff.get_len());
+            assert!(stats.get_bits_set() > 0 && stats.get_bits_set() <= bff.get_len());
+            assert_eq!(
+                stats.get_false_positive_rate(),
+                bff.get_false_positive_rate()
+            );
+            let false_positives: usize = (10000..110000u64).filter(|i| bff.check(i)).count();
+            let expected: f64 = 100000.0 * bff.get_false_positive_rate();
+            assert!(
+                (false_positives as f64) < 1.3 * expected + 10.0,
+                "{} {}",
+                bits,
+                false_positives
+            );
+        }
+    }
+
+    #[test]
+    /// Test that the same items build the same filter
+    fn test_deterministic() {
+        let items: Vec<String> = (0..1000).map(|i| format!("item {}", i)).collect();
+        let a: BinaryFuseFilter = BinaryFuseFilter::new(&items, 8);
+        let b: BinaryFuseFilter = BinaryFuseFilter::new(items.iter().rev(), 8);
+        assert_eq!(a.seed, b.seed);
+        assert_eq!(a.fingerprints, b.fingerprints);
+    }
+
+    #[test]
+    /// Test that duplicates are removed after a failed attempt, and that running out of
+    /// attempts is reported
+    fn test_duplicates() {
+        let mut keys: Vec<u64> = (0..1000).collect();
+        keys.extend(0..1000);
+        assert_eq!(
+            BinaryFuseFilter::build(keys.clone(), 8, HashFunction::default(), 1).err(),
+            Some(Error::ConstructionFailed(1))
+        );
+        let bff: BinaryFuseFilter =
+            BinaryFuseFilter::build(keys, 8, HashFunction::default(), MAX_ATTEMPTS).unwrap();
+        assert_eq!(bff.get_num_items(), 1000);
+        assert_eq!(
+            bff.get_len(),
+            BinaryFuseFilter::new(&(0..1000).collect::<Vec<u64>>(), 8).get_len()
+        );
+    }
+
+    #[test]
+    /// Test that tiny and empty sets build
+    fn test_small() {
+        let empty: Vec<u64> = vec![];
+        let bff: BinaryFuseFilter = BinaryFuseFilter::new(&empty, 8);
+        assert_eq!(bff.get_num_items(), 0);
+        assert_eq!(bff.get_bits_per_item(), 0.0);
+        assert_eq!(bff.fill_statistics().get_false_positive_rate(), 0.0);
+        assert!(!(0..1000u64).any(|i| bff.check(&i)));
+        for n in 1..50u64 {
+            let items: Vec<u64> = (0..n).collect();
+            let bff: BinaryFuseFilter = BinaryFuseFilter::new(&items, 16);
+            assert!(items.iter().all(|i| bff.check(i)), "{}", n);
+        }
+    }
+
+    #[test]
+    /// Test that the filter round-trips through save and load, and corruption is reported
+    fn test_save_load() {
+        let items: Vec<u64> = (0..1000).collect();
+        let bff: BinaryFuseFilter = BinaryFuseFilter::with_hasher(&items, 16, HashFunction::Xxh3);
+        let mut buf: Vec<u8> = Vec::new();
+        bff.save(&mut buf).unwrap();
+        let loaded: BinaryFuseFilter = BinaryFuseFilter::load(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.get_hasher(), HashFunction::Xxh3);
+        assert_eq!(loaded.get_fingerprint_bits(), 16);
+        assert_eq!(loaded.get_num_items(), 1000);
+        assert_eq!(loaded.fingerprints, bff.fingerprints);
+        assert!((0..5000u64).all(|i| loaded.check(&i) == bff.check(&i)));
+
+        let mut bad_bits: Vec<u8> = buf.clone();
+        bad_bits[7] = 12;
+        assert_eq!(
+            BinaryFuseFilter::load(&mut &bad_bits[..]).err(),
+            Some(Error::InvalidFingerprintBits(12))
+        );
+        let mut bad_payload: Vec<u8> = buf.clone();
+        let last: usize = bad_payload.len() - 5;
+        bad_payload[last] ^= 1;
+        assert_eq!(
+            BinaryFuseFilter::load(&mut &bad_payload[..]).err(),
+            Some(Error::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    /// Test that version 1 files, which store an unused indexing scheme after the hasher, can
+    /// still be loaded, and an unknown scheme there is reported
+    fn test_load_version_1() {
+        let items: Vec<u64> = (0..100).collect();
+        let bff: BinaryFuseFilter = BinaryFuseFilter::new(&items, 8);
+        let mut buf: Vec<u8> = Vec::new();
+        bff.save(&mut buf).unwrap();
+        // Insert the scheme after the header, fingerprint_bits and hasher, and re-checksum
+        let to_version_1 = |indexing: u8| -> Vec<u8> {
+            let mut v1: Vec<u8> = buf[..buf.len() - 4].to_vec();
+            v1[4] = 1;
+            v1.insert(7 + 1 + 17, indexing);
+            v1.extend_from_slice(&crc32fast::hash(&v1).to_le_bytes());
+            return v1;
+        };
+        let loaded: BinaryFuseFilter = BinaryFuseFilter::load(&mut &to_version_1(1)[..]).unwrap();
+        assert_eq!(loaded.fingerprints, bff.fingerprints);
+        assert!(items.iter().all(|i| loaded.check(i)));
+        assert_eq!(
+            BinaryFuseFilter::load(&mut &to_version_1(7)[..]).err(),
+            Some(Error::Corrupt("unknown indexing scheme"))
+        );
+    }
+
+    #[test]
+    /// Test that invalid fingerprint sizes are reported
+    fn test_invalid_parameters() {
+        let items: Vec<u64> = (0..10).collect();
+        assert_eq!(
+            BinaryFuseFilter::try_new(&items, 12).err(),
+            Some(Error::InvalidFingerprintBits(12))
+        );
+    }
+}
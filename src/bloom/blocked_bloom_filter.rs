@@ -0,0 +1,324 @@
+/*!
+ * blocked_bloom_filter.rs
+ *
+ * Implements a cache-line blocked bloom filter (Putze, Sanders & Singler, 2007):
+ *      BlockedBloomFilter -- a bloom filter split into 64-byte blocks, the size of a cache line.
+ *                            Each item hashes to a single block and sets all of its k bits
+ *                            inside it, so a check costs one cache miss instead of up to k.
+ *                            Blocks fill unevenly, so for the same length the false positive rate
+ *                            is a bit higher than a BloomFilter's: the constructors size the
+ *                            filter with params::optimal_blocked_len() to make up for it.
+ *                            Does not support deletion.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::BloomHasher;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::HashFunction;
+use crate::bloom::Item;
+use crate::bloom::Result;
+
+/// Number of bits in a block
+const BLOCK_BITS: u64 = params::CACHE_LINE_BITS;
+/// Number of 64-bit words in a block
+const BLOCK_WORDS: usize = (BLOCK_BITS / 64) as usize;
+
+/// BlockedBloomFilter struct:
+///    * words:               Bit array, as BLOCK_WORDS words per block
+///    * num_blocks:          Number of blocks
+///    * hash_count:          Hash count, ie bits set per item within its block
+///    * false_positive_rate: False positive rate
+///    * expected_inserts:    Expected inserts
+///    * actual_inserts:      Actual inserts
+///    * hasher:              Hash function used to pick the block and the bits
+#[derive(Clone)]
+pub struct BlockedBloomFilter {
+    words: Vec<u64>,
+    num_blocks: u64,
+    hash_count: u64,
+    false_positive_rate: f64,
+    expected_inserts: u64,
+    actual_inserts: u64,
+    hasher: HashFunction,
+}
+
+/// Implementation of a cache-line blocked bloom filter
+impl BlockedBloomFilter {
+    /// Getter for the length of the bit array, a multiple of the block size
+    pub fn get_len(&self) -> u64 {
+        return self.num_blocks * BLOCK_BITS;
+    }
+
+    /// Getter for num_blocks
+    pub fn get_num_blocks(&self) -> u64 {
+        return self.num_blocks;
+    }
+
+    /// Getter for hash_count
+    pub fn get_hash_count(&self) -> u64 {
+        return self.hash_count;
+    }
+
+    /// Getter for false_positive_rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for expected_inserts
+    pub fn get_expected_inserts(&self) -> u64 {
+        return self.expected_inserts;
+    }
+
+    /// Getter for actual_inserts
+    pub fn get_actual_inserts(&self) -> u64 {
+        return self.actual_inserts;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Create a new BlockedBloomFilter, using the default (Murmur3) hash function
+    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> BlockedBloomFilter {
+        return BlockedBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new BlockedBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(expected_inserts: u64, false_positive_rate: f64) -> Result<BlockedBloomFilter> {
+        return BlockedBloomFilter::try_with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new BlockedBloomFilter, using the given hash function
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> BlockedBloomFilter {
+        match BlockedBloomFilter::try_with_hasher(expected_inserts, false_positive_rate, hasher) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new BlockedBloomFilter, using the given hash function.
+    /// Returns an error if the parameters are invalid, or the rate is too low to reach
+    pub fn try_with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> Result<BlockedBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+        } else if expected_inserts < 1 {
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
+        }
+
+        let len: u64 = params::optimal_blocked_len(expected_inserts, false_positive_rate)?;
+        let num_blocks: u64 = len / BLOCK_BITS;
+        return Ok(BlockedBloomFilter {
+            words: vec![0; num_blocks as usize * BLOCK_WORDS],
+            num_blocks: num_blocks,
+            hash_count: params::optimal_blocked_hash_count(expected_inserts, len),
+            false_positive_rate: false_positive_rate,
+            expected_inserts: expected_inserts,
+            actual_inserts: 0,
+            hasher: hasher,
+        });
+    }
+
+    /// Locate an item: one 128-bit digest picks both its block, from the low half, and its
+    /// bits, from the high half. Returns the index of the block's first word, and the
+    /// starting state (x, y) of the bit positions
+    fn locate(&self, bytes: &[u8]) -> (usize, u64, u64) {
+        let digest_val: u128 = self.hasher.hash128(bytes, 0);
+        let block: u64 = (digest_val as u64) % self.num_blocks;
+        let high: u64 = (digest_val >> 64) as u64;
+        return (block as usize * BLOCK_WORDS, high & 0xffff_ffff, high >> 32);
+    }
+
+    /// Insert a new element into the BlockedBloomFilter
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        item.with_bytes(|bytes| {
+            let (start, mut x, mut y): (usize, u64, u64) = self.locate(bytes);
+            let block: &mut [u64] = &mut self.words[start..start + BLOCK_WORDS];
+            for i in 0..self.hash_count {
+                // Incremental form of g_i = x + i*y + (i^3 - i)/6, within the block
+                let bit: u64 = x % BLOCK_BITS;
+                block[(bit / 64) as usize] |= 1 << (bit % 64);
+                x = x.wrapping_add(y);
+                y = y.wrapping_add(i + 1);
+            }
+        });
+        self.actual_inserts += 1;
+    }
+
+    /// Check whether an element is probably in the filter or not
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return item.with_bytes(|bytes| {
+            let (start, mut x, mut y): (usize, u64, u64) = self.locate(bytes);
+            let block: &[u64] = &self.words[start..start + BLOCK_WORDS];
+            for i in 0..self.hash_count {
+                let bit: u64 = x % BLOCK_BITS;
+                if block[(bit / 64) as usize] & (1 << (bit % 64)) == 0 {
+                    return false;
+                }
+                x = x.wrapping_add(y);
+                y = y.wrapping_add(i + 1);
+            }
+            return true;
+        });
+    }
+
+    /// Empty out the BlockedBloomFilter
+    pub fn empty(&mut self) {
+        self.words = vec![0; self.words.len()];
+        self.actual_inserts = 0;
+    }
+
+    /// Report the bits set, the fill ratio and the estimated current false positive rate.
+    /// An item is checked against a single block, so the rate is the average over the blocks
+    /// of each block's fill ratio to the power k
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let mut bits_set: u64 = 0;
+        let mut rate: f64 = 0.0;
+        for block in self.words.chunks(BLOCK_WORDS) {
+            let block_bits_set: u64 = block.iter().map(|word| word.count_ones() as u64).sum();
+            bits_set += block_bits_set;
+            rate += params::estimate_false_positive_rate(
+                self.hash_count,
+                block_bits_set as f64 / BLOCK_BITS as f64,
+            );
+        }
+        return FillStatistics::with_false_positive_rate(
+            self.get_len(),
+            bits_set,
+            rate / self.num_blocks as f64,
+        );
+    }
+}
+
+impl Filter for BlockedBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        BlockedBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return BlockedBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        BlockedBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return BlockedBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return BlockedBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return BlockedBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return BlockedBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the filter is sized in whole blocks, larger than an unblocked filter
+    fn test_new() {
+        let bf: BlockedBloomFilter = BlockedBloomFilter::new(1000, 0.01);
+        assert_eq!(
+            bf.get_len(),
+            params::optimal_blocked_len(1000, 0.01).unwrap()
+        );
+        assert_eq!(bf.get_len(), bf.get_num_blocks() * 512);
+        assert!(bf.get_len() > params::optimal_len(1000, 0.01));
+        assert_eq!(bf.words.len() as u64, bf.get_num_blocks() * 8);
+        assert_eq!(bf.get_actual_inserts(), 0);
+    }
+
+    #[test]
+    /// Test that each insert only touches the bits of a single block
+    fn test_insert_single_block() {
+        let mut bf: BlockedBloomFilter = BlockedBloomFilter::new(1000, 0.01);
+        for i in 0..100 {
+            let before: Vec<u64> = bf.words.clone();
+            bf.insert(&i);
+            let changed: Vec<usize> = (0..bf.words.len())
+                .filter(|w| bf.words[*w] != before[*w])
+                .map(|w| w / BLOCK_WORDS)
+                .collect();
+            assert!(!changed.is_empty());
+            assert!(changed.iter().all(|block| *block == changed[0]));
+            assert!(bf.check(&i));
+        }
+        assert_eq!(bf.get_actual_inserts(), 100);
+    }
+
+    #[test]
+    /// Test that the measured false positive rate meets the target, and empty clears the filter
+    fn test_check() {
+        let mut bf: BlockedBloomFilter = BlockedBloomFilter::new(10000, 0.01);
+        for i in 0..10000 {
+            bf.insert(&i);
+        }
+        assert!((0..10000).all(|i| bf.check(&i)));
+        let false_positives: usize = (10000..110000).filter(|i| bf.check(i)).count();
+        assert!(false_positives < 1150, "{}", false_positives);
+        let stats: FillStatistics = bf.fill_statistics();
+        assert!((stats.get_false_positive_rate() - 0.01).abs() < 0.002);
+        bf.empty();
+        assert_eq!(bf.fill_statistics().get_bits_set(), 0);
+        assert!(!(0..10000).any(|i| bf.check(&i)));
+    }
+
+    #[test]
+    /// Test that invalid parameters are reported
+    fn test_invalid_parameters() {
+        assert_eq!(
+            BlockedBloomFilter::try_new(0, 0.01).err(),
+            Some(Error::InvalidExpectedInserts(0))
+        );
+        assert_eq!(
+            BlockedBloomFilter::try_new(10, 1.0).err(),
+            Some(Error::InvalidFalsePositiveRate(1.0))
+        );
+        assert_eq!(
+            BlockedBloomFilter::try_new(10, 1e-100).err(),
+            Some(Error::UnreachableFalsePositiveRate(1e-100))
+        );
+    }
+
+    #[test]
+    /// Test that low false positive rates are reachable, with the hash count capped for a block
+    fn test_low_false_positive_rate() {
+        let mut bf: BlockedBloomFilter = BlockedBloomFilter::new(1_000_000, 1e-9);
+        assert!(bf.get_len() < 4 * params::optimal_len(1_000_000, 1e-9));
+        assert_eq!(bf.get_hash_count(), params::BLOCKED_MAX_HASH_COUNT);
+        for i in 0..1000 {
+            bf.insert(&i);
+        }
+        assert!((0..1000).all(|i| bf.check(&i)));
+    }
+}
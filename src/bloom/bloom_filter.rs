@@ -1,12 +1,26 @@
 /*!
  * bloom_filter.rs
  *
- * Implements a Murmur3-based generic bloom filter:
+ * Implements a generic bloom filter:
  *      BloomFilter           -- a standard bloom filter, using a bit-array under-the-hood.
  *                               Does not support deletion.
  */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::persistence::{ChecksumReader, ChecksumWriter, FilterKind};
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
+use crate::bloom::HashFunction;
+use crate::bloom::HashIndices;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use crate::bloom::SimilarityEstimate;
 use bit_vec::BitVec;
-use fasthash::murmur3;
+use std::io::{Read, Write};
 
 /// BloomFilter struct:
 ///    * bit_arr:             Bit array
@@ -15,6 +29,9 @@ use fasthash::murmur3;
 ///    * false_positive_rate: False positive rate
 ///    * expected_inserts:    Expected inserts
 ///    * actual_inserts:      Actual inserts
+///    * hasher:              Hash function used to compute indices
+///    * indexing:            How indices are derived from the hash function
+#[derive(Clone)]
 pub struct BloomFilter {
     bit_arr: BitVec,
     len: u64,
@@ -22,10 +39,17 @@ pub struct BloomFilter {
     false_positive_rate: f64,
     expected_inserts: u64,
     actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
 }
 
 /// Implementation of a standard bloom filter, using a bit array.
 impl BloomFilter {
+    /// Getter for len
+    pub fn get_len(&self) -> u64 {
+        return self.len;
+    }
+
     /// Getter for hash_count
     pub fn get_hash_count(&self) -> u64 {
         return self.hash_count;
@@ -46,76 +70,140 @@ impl BloomFilter {
         return self.actual_inserts;
     }
 
-    /// Given a desired false positive rate, calculate the length of the BitVec required
-    /// See 'm' in this SO answer: https://stackoverflow.com/a/22467497
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn calculate_len(expected_inserts: f64, false_positive_rate: f64) -> u64 {
-        let two: f64 = 2.0;
-        return ((-1.0 * (expected_inserts) * false_positive_rate.ln()) / two.ln().powf(two)).ceil()
-            as u64;
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
     }
 
-    /// Calculate the number of hashes required
-    /// See 'k' in this SO answer: https://stackoverflow.com/a/22467497
-    /// k = ceil(m/n * ln(2))
-    fn calculate_hash_count(expected_inserts: f64, len: u64) -> u64 {
-        let two: f64 = 2.0;
-        return (((len as f64) / expected_inserts) * two.ln()).ceil() as u64;
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
     }
 
-    /// Return a single usize value, representing an index to be marked or checked
-    fn get_hash_index(i: u32, item: &str, len: u64) -> usize {
-        let digest_val: u128 = murmur3::hash128_with_seed(item, i); // Compute a murmur3 seeded hash
-        let bit_index: u64 = digest_val as u64 % len; // Mod the len of the BitVec
-        return bit_index as usize;
+    /// Create a new BloomFilter, using the default (Murmur3) hash function
+    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> BloomFilter {
+        return BloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
     }
 
-    /// Create a new BloomFilter
-    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> BloomFilter {
-        if false_positive_rate <= 0.0 {
-            panic!(
-                "False positive rate must be a positive number. Currently: {}",
-                false_positive_rate
-            );
+    /// Create a new BloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(expected_inserts: u64, false_positive_rate: f64) -> Result<BloomFilter> {
+        return BloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new BloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> BloomFilter {
+        return BloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new BloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> BloomFilter {
+        match BloomFilter::try_with_hashing(expected_inserts, false_positive_rate, hasher, indexing)
+        {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new BloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<BloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
         } else if expected_inserts < 1 {
-            panic!(
-                "Expected number of inserts must be a positive number. Currently: {}",
-                expected_inserts
-            );
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
         }
 
-        let len: u64 = BloomFilter::calculate_len(expected_inserts as f64, false_positive_rate);
-        let hash_count: u64 = BloomFilter::calculate_hash_count(expected_inserts as f64, len);
+        let len: u64 = params::optimal_len(expected_inserts, false_positive_rate);
+        let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
 
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        return Ok(BloomFilter::from_parameters(&params, hasher, indexing));
+    }
+
+    /// Create a new BloomFilter from already validated parameters
+    pub(crate) fn from_parameters(
+        params: &FilterParameters,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> BloomFilter {
         BloomFilter {
-            bit_arr: BitVec::from_elem(len as usize, false), // Create the whole BitVec zeroed-out
-            len: len,
-            hash_count: hash_count,
-            false_positive_rate: false_positive_rate,
-            expected_inserts: expected_inserts,
+            bit_arr: BitVec::from_elem(params.get_len() as usize, false), // Create the whole BitVec zeroed-out
+            len: params.get_len(),
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
+            expected_inserts: params.get_expected_inserts(),
             actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
         }
     }
 
     /// Insert a new element into the BloomFilter
-    pub fn insert(&mut self, item: &str) {
-        for i in 0..self.hash_count {
-            let bit_index: usize = BloomFilter::get_hash_index(i as u32, item, self.len);
-            self.bit_arr.set(bit_index, true); // Set the relevant index to '1'
-        }
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            for bit_index in indices {
+                self.bit_arr.set(bit_index, true); // Set the relevant index to '1'
+            }
+        });
         self.actual_inserts += 1;
     }
 
     /// Check whether an element is probably in the filter or not
-    pub fn check(&self, item: &str) -> bool {
-        for i in 0..self.hash_count {
-            let bit_index: usize = BloomFilter::get_hash_index(i as u32, item, self.len);
-            // Check if the relevant index is set
-            if !self.bit_arr[bit_index as usize] {
-                return false;
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            for bit_index in indices {
+                // Check if the relevant index is set
+                if !self.bit_arr[bit_index] {
+                    return false;
+                }
             }
-        }
-        return true;
+            return true;
+        });
     }
 
     /// Empty out the BloomFilter
@@ -127,13 +215,342 @@ impl BloomFilter {
     /// Set bit pattern of bit_arr BitVec to a particular byte array
     /// Useful for debugging and some special cases
     pub fn set_bits(&mut self, bytes: &[u8]) {
-        self.bit_arr = BitVec::from_bytes(&bytes);
+        self.bit_arr = BitVec::from_bytes(bytes);
+    }
+
+    /// Whether another BloomFilter can be combined with this one: they must have the same
+    /// length, hash count, hash function and indexing scheme, so an item sets the same bits
+    pub fn is_compatible(&self, other: &BloomFilter) -> bool {
+        return self.len == other.len
+            && self.hash_count == other.hash_count
+            && self.hasher == other.hasher
+            && self.indexing == other.indexing;
+    }
+
+    /// Merge another BloomFilter into this one, so that it contains the items of both.
+    /// actual_inserts is set to the estimated number of distinct items in the union
+    pub fn union_with(&mut self, other: &BloomFilter) -> Result<()> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        self.bit_arr.or(&other.bit_arr);
+        self.actual_inserts = self.estimate_cardinality().round() as u64;
+        return Ok(());
+    }
+
+    /// Intersect this BloomFilter with another one, so that it (probably) contains only the
+    /// items of both. actual_inserts is set to the estimated number of distinct items left
+    pub fn intersect_with(&mut self, other: &BloomFilter) -> Result<()> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        self.bit_arr.and(&other.bit_arr);
+        self.actual_inserts = self.estimate_cardinality().round() as u64;
+        return Ok(());
+    }
+
+    /// Return a new BloomFilter containing the items of both filters; see union_with()
+    pub fn union(&self, other: &BloomFilter) -> Result<BloomFilter> {
+        let mut bf: BloomFilter = self.clone();
+        bf.union_with(other)?;
+        return Ok(bf);
+    }
+
+    /// Return a new BloomFilter (probably) containing only the items of both filters;
+    /// see intersect_with()
+    pub fn intersect(&self, other: &BloomFilter) -> Result<BloomFilter> {
+        let mut bf: BloomFilter = self.clone();
+        bf.intersect_with(other)?;
+        return Ok(bf);
+    }
+
+    /// Report the bits set, the fill ratio and the estimated current false positive rate,
+    /// which exceeds the configured rate once the filter is filled past expected_inserts
+    pub fn fill_statistics(&self) -> FillStatistics {
+        return FillStatistics::new(self.len, self.hash_count, self.count_ones());
+    }
+
+    /// Return a smaller BloomFilter holding the same items, by halving the bit array times
+    /// times, OR-ing its two halves together each time. Indices are reduced modulo len, and
+    /// (h mod len) mod len/2 = h mod len/2, so the folded filter is exactly the filter of
+    /// len / 2^times bits the items would have built. The hash count is kept, so the false
+    /// positive rate rises: false_positive_rate becomes the expected rate at
+    /// expected_inserts. Build the filter with Rounding::Foldable(times) to make sure len is
+    /// divisible by 2^times
+    // u64::is_multiple_of() needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn fold(&self, times: u32) -> Result<BloomFilter> {
+        if times >= 64 || self.len % (1 << times) != 0 {
+            return Err(Error::InvalidFoldCount(times));
+        }
+        let len: u64 = self.len >> times;
+        let params: FilterParameters = FilterParameters::new(
+            len,
+            self.hash_count,
+            self.expected_inserts,
+            params::expected_false_positive_rate(len, self.hash_count, self.expected_inserts),
+        );
+        params.validate()?;
+        let mut bf: BloomFilter = BloomFilter::from_parameters(&params, self.hasher, self.indexing);
+        for (i, bit) in self.bit_arr.iter().enumerate() {
+            if bit {
+                bf.bit_arr.set(i % len as usize, true);
+            }
+        }
+        bf.actual_inserts = self.actual_inserts;
+        return Ok(bf);
+    }
+
+    /// Estimate the number of distinct items inserted, from the number of bits set
+    /// (the Swamidass-Baldi estimator). Unlike actual_inserts, this ignores duplicate inserts
+    pub fn estimate_cardinality(&self) -> f64 {
+        return params::estimate_cardinality(self.len, self.hash_count, self.count_ones());
+    }
+
+    /// Estimate the number of distinct items in the union of two compatible filters, from
+    /// the number of bits set in either of them
+    pub fn estimate_union_cardinality(&self, other: &BloomFilter) -> Result<f64> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        return Ok(BloomFilter::estimate_combined_cardinality(&[self, other]));
+    }
+
+    /// Estimate the number of distinct items in the intersection of two compatible filters:
+    /// |A ∩ B| = |A| + |B| - |A ∪ B|, floored at 0
+    pub fn estimate_intersection_cardinality(&self, other: &BloomFilter) -> Result<f64> {
+        let union: f64 = self.estimate_union_cardinality(other)?;
+        let intersection: f64 = self.estimate_cardinality() + other.estimate_cardinality() - union;
+        return Ok(intersection.max(0.0));
+    }
+
+    /// Estimate the sizes of the intersection and union of the sets held by two compatible
+    /// filters, and their Jaccard similarity with an error bound that depends on len and
+    /// hash_count: the fuller the filters, the wider the bound
+    pub fn estimate_similarity(&self, other: &BloomFilter) -> Result<SimilarityEstimate> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        let bits_set_or: u64 = self
+            .bit_arr
+            .storage()
+            .iter()
+            .zip(other.bit_arr.storage().iter())
+            .map(|(a, b)| (a | b).count_ones() as u64)
+            .sum();
+        return Ok(SimilarityEstimate::new(
+            self.len,
+            self.hash_count,
+            self.count_ones(),
+            other.count_ones(),
+            bits_set_or,
+        ));
+    }
+
+    /// Estimate the number of distinct items in the union of compatible filters, eg the
+    /// generations of a windowed filter, from the number of bits set in any of them
+    pub(crate) fn estimate_combined_cardinality(filters: &[&BloomFilter]) -> f64 {
+        let first: &BloomFilter = filters[0];
+        let mut combined: Vec<u32> = first.bit_arr.storage().to_vec();
+        for bf in filters[1..].iter() {
+            for (block, other) in combined.iter_mut().zip(bf.bit_arr.storage().iter()) {
+                *block |= other;
+            }
+        }
+        let bits_set: u64 = combined.iter().map(|block| block.count_ones() as u64).sum();
+        return params::estimate_cardinality(first.len, first.hash_count, bits_set);
+    }
+
+    /// Count the bits set in the bit array
+    fn count_ones(&self) -> u64 {
+        return self
+            .bit_arr
+            .storage()
+            .iter()
+            .map(|block| block.count_ones() as u64)
+            .sum();
+    }
+
+    /// Whether another BloomFilter has the same length, hash count, sizing and hashing,
+    /// ie whether the same item sets the same bits in both
+    pub(crate) fn has_same_parameters(&self, other: &BloomFilter) -> bool {
+        return self.len == other.len
+            && self.hash_count == other.hash_count
+            && self.expected_inserts == other.expected_inserts
+            && self.false_positive_rate == other.false_positive_rate
+            && self.hasher == other.hasher
+            && self.indexing == other.indexing;
+    }
+
+    /// Save the BloomFilter to a writer, in the crate's versioned binary format
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut writer = ChecksumWriter::new(writer, FilterKind::Bloom)?;
+        self.write_body(&mut writer)?;
+        return writer.finish();
+    }
+
+    /// Load a BloomFilter saved with save(), validating its header and checksum
+    pub fn load<R: Read>(reader: &mut R) -> Result<BloomFilter> {
+        let mut reader = ChecksumReader::new(reader, FilterKind::Bloom)?;
+        let bf: BloomFilter = BloomFilter::read_body(&mut reader)?;
+        reader.finish()?;
+        return Ok(bf);
+    }
+
+    /// Write the parameters, hashing, insert counts and bits of the BloomFilter
+    pub(crate) fn write_body<W: Write>(&self, writer: &mut ChecksumWriter<W>) -> Result<()> {
+        writer.write_u64(self.len)?;
+        writer.write_u64(self.hash_count)?;
+        writer.write_hashing(self.hasher, self.indexing)?;
+        writer.write_u64(self.expected_inserts)?;
+        writer.write_u64(self.actual_inserts)?;
+        writer.write_f64(self.false_positive_rate)?;
+        return writer.write_payload(&self.bit_arr.to_bytes());
+    }
+
+    /// Read a BloomFilter written by write_body()
+    pub(crate) fn read_body<R: Read>(reader: &mut ChecksumReader<R>) -> Result<BloomFilter> {
+        let (params, actual_inserts, hasher, indexing) = BloomFilter::read_parameters(reader)?;
+        let bytes: Vec<u8> = reader.read_payload(params.get_len().div_ceil(8))?;
+        return BloomFilter::from_stored(&params, actual_inserts, hasher, indexing, &bytes);
+    }
+
+    /// Read and validate the fields written by write_body() ahead of the bit payload: the
+    /// parameters, the actual inserts, the hash function and the indexing scheme
+    pub(crate) fn read_parameters<R: Read>(
+        reader: &mut ChecksumReader<R>,
+    ) -> Result<(FilterParameters, u64, HashFunction, IndexingScheme)> {
+        let len: u64 = reader.read_u64()?;
+        let hash_count: u64 = reader.read_u64()?;
+        let (hasher, indexing) = reader.read_hashing()?;
+        let expected_inserts: u64 = reader.read_u64()?;
+        let actual_inserts: u64 = reader.read_u64()?;
+        let false_positive_rate: f64 = reader.read_f64()?;
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        params.validate()?;
+        return Ok((params, actual_inserts, hasher, indexing));
+    }
+
+    /// Restore a BloomFilter from its stored state, validating the parameters and that the
+    /// bit payload matches the length
+    pub(crate) fn from_stored(
+        params: &FilterParameters,
+        actual_inserts: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+        bytes: &[u8],
+    ) -> Result<BloomFilter> {
+        params.validate()?;
+        if bytes.len() as u64 != params.get_len().div_ceil(8) {
+            return Err(Error::Corrupt(
+                "payload length does not match the filter length",
+            ));
+        }
+        let mut bit_arr: BitVec = BitVec::from_bytes(bytes);
+        bit_arr.truncate(params.get_len() as usize);
+        let mut bf: BloomFilter = BloomFilter::from_parameters(params, hasher, indexing);
+        bf.bit_arr = bit_arr;
+        bf.actual_inserts = actual_inserts;
+        return Ok(bf);
+    }
+}
+
+impl Filter for BloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        BloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return BloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        BloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return BloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return BloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return BloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return BloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use crate::bloom::serialization::{ByteBuf, Bytes};
+    use serde::de;
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialized form of a BloomFilter, validated before it becomes one
+    #[derive(Deserialize)]
+    #[serde(rename = "BloomFilter", deny_unknown_fields)]
+    struct BloomFilterRepr {
+        len: u64,
+        hash_count: u64,
+        false_positive_rate: f64,
+        expected_inserts: u64,
+        actual_inserts: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+        bits: ByteBuf,
+    }
+
+    impl Serialize for BloomFilter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("BloomFilter", 8)?;
+            state.serialize_field("len", &self.len)?;
+            state.serialize_field("hash_count", &self.hash_count)?;
+            state.serialize_field("false_positive_rate", &self.false_positive_rate)?;
+            state.serialize_field("expected_inserts", &self.expected_inserts)?;
+            state.serialize_field("actual_inserts", &self.actual_inserts)?;
+            state.serialize_field("hasher", &self.hasher)?;
+            state.serialize_field("indexing", &self.indexing)?;
+            state.serialize_field("bits", &Bytes(&self.bit_arr.to_bytes()))?;
+            return state.end();
+        }
+    }
+
+    impl<'de> Deserialize<'de> for BloomFilter {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<BloomFilter, D::Error> {
+            let repr: BloomFilterRepr = BloomFilterRepr::deserialize(deserializer)?;
+            let params: FilterParameters = FilterParameters::new(
+                repr.len,
+                repr.hash_count,
+                repr.expected_inserts,
+                repr.false_positive_rate,
+            );
+            return BloomFilter::from_stored(
+                &params,
+                repr.actual_inserts,
+                repr.hasher,
+                repr.indexing,
+                &repr.bits.0,
+            )
+            .map_err(de::Error::custom);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bloom::params::Rounding;
+    use crate::bloom::BloomHasher;
 
     #[test]
     /// Test that we can create a new BloomFilter using BloomFilter::new()
@@ -153,19 +570,26 @@ mod tests {
         let s2 = "This is another string"; // Inserted
         let s3 = "This is a third string"; // Not inserted
         let mut bf: BloomFilter = BloomFilter::new(2, 0.05);
-        bf.insert(&s);
-        bf.insert(&s2);
-        assert!(bf.check(&s)); // Included
-        assert!(bf.check(&s2)); // Included
-        assert!(!bf.check(&s3)); // Not included
+        bf.insert(s);
+        bf.insert(s2);
+        assert!(bf.check(s)); // Included
+        assert!(bf.check(s2)); // Included
+        assert!(!bf.check(s3)); // Not included
     }
 
     #[test]
-    /// Test that the get_hash_index method works as expected
-    fn test_get_hash_index() {
+    /// Test that the PerSeed indexing scheme marks the same indices as earlier versions,
+    /// where index i is the low 64 bits of the murmur3 digest seeded with i, mod len
+    fn test_per_seed_indexing() {
         let s = "This is a test string";
-        let i: u32 = 32;
-        assert_eq!(BloomFilter::get_hash_index(i, &s, 10), 4);
+        let mut bf: BloomFilter =
+            BloomFilter::with_hashing(2, 0.05, HashFunction::Murmur3, IndexingScheme::PerSeed);
+        bf.insert(s);
+        for i in 0..bf.get_hash_count() {
+            let digest_val: u128 = HashFunction::Murmur3.hash128(s.as_bytes(), i as u32);
+            let index: usize = (digest_val as u64 % bf.len) as usize;
+            assert!(bf.bit_arr[index]);
+        }
     }
 
     #[test]
@@ -214,40 +638,6 @@ mod tests {
         }
     }
 
-    #[test]
-    /// Test that we get the expected value for calculating the length of the
-    /// BitVec using the computation:
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn test_calculate_len() {
-        let m: u64 = BloomFilter::calculate_len(100 as f64, 0.01);
-        assert_eq!(m, 959);
-        let m2: u64 = BloomFilter::calculate_len(1000 as f64, 0.001);
-        assert_eq!(m2, 14378);
-        let m3: u64 = BloomFilter::calculate_len(5 as f64, 0.1);
-        assert_eq!(m3, 24);
-        let m4: u64 = BloomFilter::calculate_len(10000 as f64, 0.01);
-        assert_eq!(m4, 95851);
-        let m5: u64 = BloomFilter::calculate_len(216553 as f64, 0.01);
-        assert_eq!(m5, 2075674);
-    }
-
-    #[test]
-    /// Test that we get the expected value for calculating the number of iterations
-    /// if hashing we need to perform
-    /// k = ceil(m/n * ln(2))
-    fn test_calculate_hash_count() {
-        let k: u64 = BloomFilter::calculate_hash_count(100 as f64, 959);
-        assert_eq!(k, 7);
-        let k2: u64 = BloomFilter::calculate_hash_count(1000 as f64, 14378);
-        assert_eq!(k2, 10);
-        let k3: u64 = BloomFilter::calculate_hash_count(5 as f64, 24);
-        assert_eq!(k3, 4);
-        let k4: u64 = BloomFilter::calculate_hash_count(10000 as f64, 95851);
-        assert_eq!(k4, 7);
-        let k5: u64 = BloomFilter::calculate_hash_count(216553 as f64, 2075674);
-        assert_eq!(k5, 7);
-    }
-
     #[test]
     #[should_panic]
     /// Test that we can't pass zero as the expected_inserts value
@@ -272,4 +662,200 @@ mod tests {
         #[allow(unused_variables)]
         let bf: BloomFilter = BloomFilter::new(1, -0.03);
     }
+
+    #[test]
+    /// Test that save/load round-trips the parameters, insert counts and bits
+    fn test_save_load() {
+        let mut bf: BloomFilter =
+            BloomFilter::with_hashing(100, 0.01, HashFunction::Xxh3, IndexingScheme::PerSeed);
+        for k in 0..50 {
+            bf.insert(&k);
+        }
+        let mut buf: Vec<u8> = Vec::new();
+        bf.save(&mut buf).unwrap();
+        let loaded: BloomFilter = BloomFilter::load(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.get_len(), 959);
+        assert_eq!(loaded.get_hash_count(), 7);
+        assert_eq!(loaded.get_actual_inserts(), 50);
+        assert_eq!(loaded.get_expected_inserts(), 100);
+        assert_eq!(loaded.get_false_positive_rate(), 0.01);
+        assert_eq!(loaded.get_hasher(), HashFunction::Xxh3);
+        assert_eq!(loaded.get_indexing(), IndexingScheme::PerSeed);
+        assert_eq!(loaded.bit_arr, bf.bit_arr);
+    }
+
+    #[test]
+    /// Test that loading a truncated or tampered file fails
+    fn test_load_corrupt() {
+        let mut bf: BloomFilter = BloomFilter::new(100, 0.01);
+        bf.insert("hello");
+        let mut buf: Vec<u8> = Vec::new();
+        bf.save(&mut buf).unwrap();
+        let truncated: &[u8] = &buf[..buf.len() - 10];
+        assert_eq!(
+            BloomFilter::load(&mut &truncated[..]).err(),
+            Some(Error::Io(std::io::ErrorKind::UnexpectedEof))
+        );
+        let mut tampered: Vec<u8> = buf.clone();
+        let last_payload_byte: usize = buf.len() - 5;
+        tampered[last_payload_byte] ^= 0x01;
+        assert_eq!(
+            BloomFilter::load(&mut &tampered[..]).err(),
+            Some(Error::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    /// Test that union/intersect combine the bit arrays and estimate the inserts
+    fn test_union_intersect() {
+        let mut a: BloomFilter = BloomFilter::new(1000, 0.01);
+        let mut b: BloomFilter = BloomFilter::new(1000, 0.01);
+        for i in 0..300 {
+            a.insert(&i);
+        }
+        for i in 200..500 {
+            b.insert(&i);
+        }
+        let union: BloomFilter = a.union(&b).unwrap();
+        assert!((0..500).all(|i| union.check(&i)));
+        assert!((union.get_actual_inserts() as i64 - 500).abs() < 25);
+        assert_eq!(union.count_ones(), {
+            let mut bits: BitVec = a.bit_arr.clone();
+            bits.or(&b.bit_arr);
+            bits.iter().filter(|bit| *bit).count() as u64
+        });
+
+        a.intersect_with(&b).unwrap();
+        assert!((200..300).all(|i| a.check(&i)));
+        assert!((0..100).filter(|i| a.check(i)).count() < 10);
+        assert!(a.get_actual_inserts() < 200);
+    }
+
+    #[test]
+    /// Test that filters with different lengths, hash counts or hashing can't be combined
+    fn test_incompatible() {
+        let mut bf: BloomFilter = BloomFilter::new(100, 0.01);
+        let other_len: BloomFilter = BloomFilter::new(200, 0.01);
+        let other_hasher: BloomFilter = BloomFilter::with_hasher(100, 0.01, HashFunction::Xxh3);
+        let other_indexing: BloomFilter =
+            BloomFilter::with_hashing(100, 0.01, HashFunction::Murmur3, IndexingScheme::PerSeed);
+        for other in [other_len, other_hasher, other_indexing].iter() {
+            assert!(!bf.is_compatible(other));
+            assert_eq!(bf.union_with(other), Err(Error::IncompatibleFilters));
+            assert_eq!(bf.intersect(other).err(), Some(Error::IncompatibleFilters));
+        }
+    }
+
+    #[test]
+    /// Test that the cardinality estimates ignore duplicates, and estimate unions and
+    /// intersections of overlapping sets
+    fn test_estimate_cardinality() {
+        let mut a: BloomFilter = BloomFilter::new(1000, 0.01);
+        let mut b: BloomFilter = BloomFilter::new(1000, 0.01);
+        assert_eq!(a.estimate_cardinality(), 0.0);
+        for i in 0..600 {
+            a.insert(&i);
+            a.insert(&i);
+        }
+        for i in 400..1000 {
+            b.insert(&i);
+        }
+        assert_eq!(a.get_actual_inserts(), 1200);
+        assert!((a.estimate_cardinality() - 600.0).abs() < 30.0);
+        let union: f64 = a.estimate_union_cardinality(&b).unwrap();
+        assert!((union - 1000.0).abs() < 50.0, "{}", union);
+        let intersection: f64 = a.estimate_intersection_cardinality(&b).unwrap();
+        assert!((intersection - 200.0).abs() < 40.0, "{}", intersection);
+        assert_eq!(
+            a.estimate_union_cardinality(&BloomFilter::new(10, 0.01)),
+            Err(Error::IncompatibleFilters)
+        );
+    }
+
+    #[test]
+    /// Test that the estimated false positive rate is near the configured rate when the filter
+    /// holds expected_inserts items, and grows past it when overfilled
+    fn test_fill_statistics() {
+        let mut bf: BloomFilter = BloomFilter::new(1000, 0.01);
+        assert_eq!(bf.fill_statistics().get_bits_set(), 0);
+        for i in 0..1000 {
+            bf.insert(&i);
+        }
+        let stats: FillStatistics = bf.fill_statistics();
+        assert_eq!(stats.get_len(), bf.get_len());
+        assert!((stats.get_fill_ratio() - 0.5).abs() < 0.02);
+        assert!((stats.get_false_positive_rate() - 0.01).abs() < 0.002);
+        for i in 1000..3000 {
+            bf.insert(&i);
+        }
+        assert!(bf.fill_statistics().get_false_positive_rate() > 0.1);
+    }
+
+    #[test]
+    /// Test that the Jaccard estimate is within its error bound of the true similarity
+    fn test_estimate_similarity() {
+        let mut a: BloomFilter = BloomFilter::new(2000, 0.01);
+        let mut b: BloomFilter = BloomFilter::new(2000, 0.01);
+        for i in 0..1500 {
+            a.insert(&i);
+        }
+        for i in 500..2000 {
+            b.insert(&i);
+        }
+        let similarity: SimilarityEstimate = a.estimate_similarity(&b).unwrap();
+        assert_eq!(
+            similarity.get_union(),
+            a.estimate_union_cardinality(&b).unwrap()
+        );
+        assert!(
+            (similarity.get_intersection() - a.estimate_intersection_cardinality(&b).unwrap())
+                .abs()
+                < 1e-9
+        );
+        // |A ∩ B| = 1000, |A ∪ B| = 2000
+        assert!((similarity.get_jaccard() - 0.5).abs() < similarity.get_error_bound());
+        assert!(similarity.get_error_bound() < 0.1);
+        assert_eq!(
+            a.estimate_similarity(&BloomFilter::new(10, 0.01)).err(),
+            Some(Error::IncompatibleFilters)
+        );
+    }
+
+    #[test]
+    /// Test that a folded filter is bit for bit the smaller filter built from the same items,
+    /// with either indexing scheme
+    fn test_fold() {
+        for indexing in [IndexingScheme::PerSeed, IndexingScheme::DoubleHashing].iter() {
+            let mut bf: BloomFilter =
+                BloomFilter::with_hashing(1000, 0.001, HashFunction::Xxh3, *indexing);
+            let len: u64 = params::round_len_up(bf.get_len(), Rounding::Foldable(3));
+            let params: FilterParameters =
+                FilterParameters::new(len, bf.get_hash_count(), 1000, 0.001);
+            bf = BloomFilter::from_parameters(&params, HashFunction::Xxh3, *indexing);
+            let small_params: FilterParameters =
+                FilterParameters::new(len / 8, bf.get_hash_count(), 1000, 0.001);
+            let mut small: BloomFilter =
+                BloomFilter::from_parameters(&small_params, HashFunction::Xxh3, *indexing);
+            for i in 0..1000 {
+                bf.insert(&i);
+                small.insert(&i);
+            }
+            let folded: BloomFilter = bf.fold(3).unwrap();
+            assert_eq!(folded.bit_arr, small.bit_arr);
+            assert_eq!(folded.get_len(), len / 8);
+            assert_eq!(folded.get_actual_inserts(), 1000);
+            assert!(folded.get_false_positive_rate() > bf.get_false_positive_rate());
+            assert!((0..1000).all(|i| folded.check(&i)));
+            assert_eq!(bf.fold(0).unwrap().bit_arr, bf.bit_arr);
+        }
+    }
+
+    #[test]
+    /// Test that a length not divisible by 2^times can't be folded
+    fn test_fold_invalid() {
+        let bf: BloomFilter = BloomFilter::new(100, 0.01);
+        assert_eq!(bf.get_len(), 959);
+        assert_eq!(bf.fold(1).err(), Some(Error::InvalidFoldCount(1)));
+        assert_eq!(bf.fold(64).err(), Some(Error::InvalidFoldCount(64)));
+    }
 }
@@ -0,0 +1,570 @@
+/*!
+ * builder.rs
+ *
+ * Implements a builder for every bloom filter variant, supporting several ways of sizing
+ * the filter:
+ *      Sizing           -- how the length (m) and hash count (k) of a filter are chosen: from the
+ *                          expected inserts and a false positive rate, explicitly, or from a
+ *                          memory budget plus either the expected inserts or a false positive rate.
+ *      FilterParameters -- the resolved length, hash count, expected inserts (capacity) and
+ *                          false positive rate of a filter.
+ *      Builder          -- collects the sizing, hashing options and variant-specific knobs, and
+ *                          builds any of the filter types.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::params::Rounding;
+use crate::bloom::A2BufferingBloomFilter;
+use crate::bloom::BigenerationalBloomFilter;
+use crate::bloom::BloomFilter;
+use crate::bloom::CountingBloomFilter;
+use crate::bloom::Error;
+use crate::bloom::GenerationalBloomFilter;
+use crate::bloom::HashFunction;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Result;
+use crate::bloom::ScopeDecayBloomFilter;
+use std::time::Duration;
+
+/// How the length (m) and hash count (k) of a filter are chosen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Sizing {
+    /// Size for n expected inserts at a target false positive rate p
+    ExpectedInserts {
+        expected_inserts: u64,
+        false_positive_rate: f64,
+    },
+    /// Use an explicit length m and hash count k; the capacity is the n for which k is optimal
+    Explicit { len: u64, hash_count: u64 },
+    /// Fit the filter in a memory budget (in bytes), for n expected inserts
+    MemoryAndInserts { bytes: u64, expected_inserts: u64 },
+    /// Fit the filter in a memory budget (in bytes), at a target false positive rate p;
+    /// the achievable capacity is reported as the expected inserts
+    MemoryAndFalsePositiveRate {
+        bytes: u64,
+        false_positive_rate: f64,
+    },
+}
+
+/// Resolved parameters of a filter:
+///    * len:                 Length of the bit (or counter) array
+///    * hash_count:          Hash count
+///    * expected_inserts:    Expected inserts (the capacity of the filter)
+///    * false_positive_rate: False positive rate at expected_inserts
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FilterParameters {
+    len: u64,
+    hash_count: u64,
+    expected_inserts: u64,
+    false_positive_rate: f64,
+}
+
+/// Builder struct:
+///    * sizing:              How to choose the length and hash count
+///    * hasher:              Hash function
+///    * indexing:            Indexing scheme
+///    * num_generations:     Number of generations, for GenerationalBloomFilter
+///    * bit_reset_rate:      Bit reset rate, for ScopeDecayBloomFilter
+///    * rounding:            How to round the length of the filter
+///    * generation_duration: Time slice of a generation, for GenerationalBloomFilter
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Builder {
+    sizing: Sizing,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
+    num_generations: Option<u64>,
+    bit_reset_rate: Option<f64>,
+    rounding: Rounding,
+    generation_duration: Option<Duration>,
+}
+
+impl FilterParameters {
+    /// Create a new FilterParameters
+    pub(crate) fn new(
+        len: u64,
+        hash_count: u64,
+        expected_inserts: u64,
+        false_positive_rate: f64,
+    ) -> FilterParameters {
+        FilterParameters {
+            len: len,
+            hash_count: hash_count,
+            expected_inserts: expected_inserts,
+            false_positive_rate: false_positive_rate,
+        }
+    }
+
+    /// Check that stored parameters describe a valid filter, eg after loading them
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.len < 1 {
+            return Err(Error::InvalidLen(self.len));
+        } else if self.hash_count < 1 {
+            return Err(Error::InvalidHashCount(self.hash_count));
+        } else if self.expected_inserts < 1 {
+            return Err(Error::InvalidExpectedInserts(self.expected_inserts));
+        } else if !(self.false_positive_rate > 0.0 && self.false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(self.false_positive_rate));
+        }
+        return Ok(());
+    }
+
+    /// Getter for len
+    pub fn get_len(&self) -> u64 {
+        return self.len;
+    }
+
+    /// Getter for hash_count
+    pub fn get_hash_count(&self) -> u64 {
+        return self.hash_count;
+    }
+
+    /// Getter for expected_inserts
+    pub fn get_expected_inserts(&self) -> u64 {
+        return self.expected_inserts;
+    }
+
+    /// Getter for false_positive_rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+}
+
+/// The false positive rate of (m, k) after n inserts, kept at least f64::MIN_POSITIVE so a
+/// filter far larger than it needs to be still reports (and saves) a valid rate
+fn resolved_false_positive_rate(len: u64, hash_count: u64, inserts: u64) -> f64 {
+    return params::expected_false_positive_rate(len, hash_count, inserts).max(f64::MIN_POSITIVE);
+}
+
+impl Builder {
+    /// Create a new Builder with the given sizing, the default hash function and indexing
+    /// scheme, and no variant-specific knobs set
+    pub fn new(sizing: Sizing) -> Builder {
+        Builder {
+            sizing: sizing,
+            hasher: HashFunction::default(),
+            indexing: IndexingScheme::default(),
+            num_generations: None,
+            bit_reset_rate: None,
+            rounding: Rounding::default(),
+            generation_duration: None,
+        }
+    }
+
+    /// Set the hash function
+    pub fn hasher(mut self, hasher: HashFunction) -> Builder {
+        self.hasher = hasher;
+        return self;
+    }
+
+    /// Set the indexing scheme
+    pub fn indexing(mut self, indexing: IndexingScheme) -> Builder {
+        self.indexing = indexing;
+        return self;
+    }
+
+    /// Set the number of generations, required by build_generational_bloom_filter()
+    pub fn num_generations(mut self, num_generations: u64) -> Builder {
+        self.num_generations = Some(num_generations);
+        return self;
+    }
+
+    /// Set the bit reset rate, required by build_scope_decay_bloom_filter()
+    pub fn bit_reset_rate(mut self, bit_reset_rate: f64) -> Builder {
+        self.bit_reset_rate = Some(bit_reset_rate);
+        return self;
+    }
+
+    /// Set how the length is rounded. Lengths sized for a false positive rate are rounded up,
+    /// and lengths sized from a memory budget are rounded down; explicit lengths are kept
+    pub fn rounding(mut self, rounding: Rounding) -> Builder {
+        self.rounding = rounding;
+        return self;
+    }
+
+    /// Set the time slice of a generation, optional for build_generational_bloom_filter(): the
+    /// oldest generation is then also recycled when the current one expires, using the system
+    /// clock (see GenerationalBloomFilter::with_generation_duration())
+    pub fn generation_duration(mut self, generation_duration: Duration) -> Builder {
+        self.generation_duration = Some(generation_duration);
+        return self;
+    }
+
+    /// Resolve the sizing into the parameters of a single bit array, ie those of a
+    /// BloomFilter or ScopeDecayBloomFilter. For a memory budget, this reports the achievable
+    /// false positive rate or capacity
+    pub fn parameters(&self) -> Result<FilterParameters> {
+        return self.resolve(1, 1);
+    }
+
+    /// Resolve the sizing into FilterParameters, for a filter made of `copies` arrays whose
+    /// cells are `bits_per_cell` bits wide. A memory budget is split evenly between the copies
+    fn resolve(&self, bits_per_cell: u64, copies: u64) -> Result<FilterParameters> {
+        match self.sizing {
+            Sizing::ExpectedInserts {
+                expected_inserts,
+                false_positive_rate,
+            } => {
+                if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+                    return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+                } else if expected_inserts < 1 {
+                    return Err(Error::InvalidExpectedInserts(expected_inserts));
+                }
+                let len: u64 = params::round_len_up(
+                    params::optimal_len(expected_inserts, false_positive_rate),
+                    self.rounding,
+                );
+                let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+                return Ok(FilterParameters::new(
+                    len,
+                    hash_count,
+                    expected_inserts,
+                    false_positive_rate,
+                ));
+            }
+            Sizing::Explicit { len, hash_count } => {
+                if len < 1 {
+                    return Err(Error::InvalidLen(len));
+                } else if hash_count < 1 {
+                    return Err(Error::InvalidHashCount(hash_count));
+                }
+                // The number of inserts for which hash_count is optimal: n = m/k * ln(2)
+                let two: f64 = 2.0;
+                let expected_inserts: u64 = ((len as f64 / hash_count as f64) * two.ln())
+                    .floor()
+                    .max(1.0) as u64;
+                return Ok(FilterParameters::new(
+                    len,
+                    hash_count,
+                    expected_inserts,
+                    resolved_false_positive_rate(len, hash_count, expected_inserts),
+                ));
+            }
+            Sizing::MemoryAndInserts {
+                bytes,
+                expected_inserts,
+            } => {
+                if expected_inserts < 1 {
+                    return Err(Error::InvalidExpectedInserts(expected_inserts));
+                }
+                let len: u64 = params::round_len_down(
+                    bytes.saturating_mul(8) / bits_per_cell / copies,
+                    self.rounding,
+                );
+                if len < 1 {
+                    return Err(Error::InvalidMemoryBudget(bytes));
+                }
+                let hash_count: u64 =
+                    params::optimal_hash_count(expected_inserts, len).min(params::MAX_HASH_COUNT);
+                let false_positive_rate: f64 =
+                    resolved_false_positive_rate(len, hash_count, expected_inserts);
+                if false_positive_rate >= 1.0 {
+                    return Err(Error::InvalidMemoryBudget(bytes));
+                }
+                return Ok(FilterParameters::new(
+                    len,
+                    hash_count,
+                    expected_inserts,
+                    false_positive_rate,
+                ));
+            }
+            Sizing::MemoryAndFalsePositiveRate {
+                bytes,
+                false_positive_rate,
+            } => {
+                if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+                    return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+                }
+                let len: u64 = params::round_len_down(
+                    bytes.saturating_mul(8) / bits_per_cell / copies,
+                    self.rounding,
+                );
+                let expected_inserts: u64 = params::capacity(len, false_positive_rate);
+                if expected_inserts < 1 {
+                    return Err(Error::InvalidMemoryBudget(bytes));
+                }
+                let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+                return Ok(FilterParameters::new(
+                    len,
+                    hash_count,
+                    expected_inserts,
+                    false_positive_rate,
+                ));
+            }
+        }
+    }
+
+    /// Build a BloomFilter
+    pub fn build_bloom_filter(&self) -> Result<BloomFilter> {
+        let params: FilterParameters = self.resolve(1, 1)?;
+        return Ok(BloomFilter::from_parameters(
+            &params,
+            self.hasher,
+            self.indexing,
+        ));
+    }
+
+    /// Build a CountingBloomFilter. A memory budget is divided into 64-bit counters
+    pub fn build_counting_bloom_filter(&self) -> Result<CountingBloomFilter> {
+        let params: FilterParameters = self.resolve(64, 1)?;
+        return Ok(CountingBloomFilter::from_parameters(
+            &params,
+            self.hasher,
+            self.indexing,
+        ));
+    }
+
+    /// Build a ScopeDecayBloomFilter. Requires bit_reset_rate to be set
+    pub fn build_scope_decay_bloom_filter(&self) -> Result<ScopeDecayBloomFilter> {
+        let bit_reset_rate: f64 = match self.bit_reset_rate {
+            Some(rate) => rate,
+            None => return Err(Error::MissingParameter("bit_reset_rate")),
+        };
+        if !(0.0..=1.0).contains(&bit_reset_rate) {
+            return Err(Error::InvalidBitResetRate(bit_reset_rate));
+        }
+        let params: FilterParameters = self.resolve(1, 1)?;
+        return Ok(ScopeDecayBloomFilter::from_parameters(
+            &params,
+            bit_reset_rate,
+            self.hasher,
+            self.indexing,
+        ));
+    }
+
+    /// Build a GenerationalBloomFilter. Requires num_generations to be set; a memory budget
+    /// is split evenly between the generations
+    pub fn build_generational_bloom_filter(&self) -> Result<GenerationalBloomFilter> {
+        let num_generations: u64 = match self.num_generations {
+            Some(generations) => generations,
+            None => return Err(Error::MissingParameter("num_generations")),
+        };
+        if num_generations < 2 {
+            return Err(Error::InvalidNumGenerations(num_generations));
+        }
+        let params: FilterParameters = self.resolve(1, num_generations)?;
+        let gbf: GenerationalBloomFilter = GenerationalBloomFilter::from_parameters(
+            &params,
+            num_generations,
+            self.hasher,
+            self.indexing,
+        );
+        match self.generation_duration {
+            Some(duration) => return gbf.try_with_generation_duration(duration),
+            None => return Ok(gbf),
+        }
+    }
+
+    /// Build a BigenerationalBloomFilter. A memory budget is split between both generations
+    pub fn build_bigenerational_bloom_filter(&self) -> Result<BigenerationalBloomFilter> {
+        let params: FilterParameters = self.resolve(1, 2)?;
+        return Ok(BigenerationalBloomFilter::from_parameters(
+            &params,
+            self.hasher,
+            self.indexing,
+        ));
+    }
+
+    /// Build an A2BufferingBloomFilter. A memory budget is split between both buffers
+    pub fn build_a2buffering_bloom_filter(&self) -> Result<A2BufferingBloomFilter> {
+        let params: FilterParameters = self.resolve(1, 2)?;
+        return Ok(A2BufferingBloomFilter::from_parameters(
+            &params,
+            self.hasher,
+            self.indexing,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that sizing by expected inserts matches the constructors
+    fn test_expected_inserts() {
+        let params: FilterParameters = Builder::new(Sizing::ExpectedInserts {
+            expected_inserts: 1000,
+            false_positive_rate: 0.001,
+        })
+        .parameters()
+        .unwrap();
+        assert_eq!(params.get_len(), 14378);
+        assert_eq!(params.get_hash_count(), 10);
+        assert_eq!(params.get_expected_inserts(), 1000);
+        assert_eq!(params.get_false_positive_rate(), 0.001);
+    }
+
+    #[test]
+    /// Test that explicit sizing keeps m and k, and reports the capacity k is optimal for
+    fn test_explicit() {
+        let params: FilterParameters = Builder::new(Sizing::Explicit {
+            len: 959,
+            hash_count: 7,
+        })
+        .parameters()
+        .unwrap();
+        assert_eq!(params.get_len(), 959);
+        assert_eq!(params.get_hash_count(), 7);
+        assert_eq!(params.get_expected_inserts(), 94);
+        assert!((params.get_false_positive_rate() - 0.0075).abs() < 0.0005);
+    }
+
+    #[test]
+    /// Test that a memory budget is turned into bits, or split between counters and copies
+    fn test_memory_and_inserts() {
+        let builder: Builder = Builder::new(Sizing::MemoryAndInserts {
+            bytes: 1200,
+            expected_inserts: 1000,
+        });
+        let params: FilterParameters = builder.parameters().unwrap();
+        assert_eq!(params.get_len(), 9600);
+        assert_eq!(params.get_hash_count(), 7);
+        assert!(params.get_false_positive_rate() < 0.01);
+        assert_eq!(builder.resolve(64, 1).unwrap().get_len(), 150);
+        assert_eq!(builder.resolve(1, 3).unwrap().get_len(), 3200);
+    }
+
+    #[test]
+    /// Test that an oversized budget caps the hash count, and a valid rate is still reported
+    fn test_oversized() {
+        let params: FilterParameters = Builder::new(Sizing::MemoryAndInserts {
+            bytes: 1 << 20,
+            expected_inserts: 10,
+        })
+        .parameters()
+        .unwrap();
+        assert_eq!(params.get_hash_count(), params::MAX_HASH_COUNT);
+        assert_eq!(params.validate(), Ok(()));
+        let params: FilterParameters = Builder::new(Sizing::Explicit {
+            len: 1000,
+            hash_count: 1000,
+        })
+        .parameters()
+        .unwrap();
+        assert_eq!(params.get_hash_count(), 1000);
+        assert_eq!(params.validate(), Ok(()));
+        assert_eq!(
+            Builder::new(Sizing::MemoryAndInserts {
+                bytes: 1,
+                expected_inserts: 1_000_000_000,
+            })
+            .parameters()
+            .err(),
+            Some(Error::InvalidMemoryBudget(1))
+        );
+    }
+
+    #[test]
+    /// Test that a memory budget plus a false positive rate reports the achievable capacity
+    fn test_memory_and_false_positive_rate() {
+        let params: FilterParameters = Builder::new(Sizing::MemoryAndFalsePositiveRate {
+            bytes: 1798,
+            false_positive_rate: 0.001,
+        })
+        .parameters()
+        .unwrap();
+        assert_eq!(params.get_len(), 14384);
+        assert_eq!(params.get_expected_inserts(), 1000);
+        assert_eq!(params.get_hash_count(), 10);
+        assert_eq!(params.get_false_positive_rate(), 0.001);
+    }
+
+    #[test]
+    /// Test that rounding rounds lengths sized for a false positive rate up, and lengths sized
+    /// from a memory budget down
+    fn test_rounding() {
+        let by_rate = Builder::new(Sizing::ExpectedInserts {
+            expected_inserts: 100,
+            false_positive_rate: 0.01,
+        })
+        .rounding(Rounding::CacheLines);
+        assert_eq!(by_rate.parameters().unwrap().get_len(), 1024);
+        assert_eq!(by_rate.parameters().unwrap().get_hash_count(), 8);
+        let by_memory = Builder::new(Sizing::MemoryAndInserts {
+            bytes: 1000,
+            expected_inserts: 100,
+        })
+        .rounding(Rounding::PowerOfTwo);
+        assert_eq!(by_memory.parameters().unwrap().get_len(), 4096);
+        let explicit = Builder::new(Sizing::Explicit {
+            len: 959,
+            hash_count: 7,
+        })
+        .rounding(Rounding::Words);
+        assert_eq!(explicit.parameters().unwrap().get_len(), 959);
+    }
+
+    #[test]
+    /// Test that invalid sizings are reported as errors
+    fn test_invalid_sizing() {
+        let explicit_len = Builder::new(Sizing::Explicit {
+            len: 0,
+            hash_count: 3,
+        });
+        assert_eq!(explicit_len.parameters(), Err(Error::InvalidLen(0)));
+        let explicit_k = Builder::new(Sizing::Explicit {
+            len: 10,
+            hash_count: 0,
+        });
+        assert_eq!(explicit_k.parameters(), Err(Error::InvalidHashCount(0)));
+        let tiny = Builder::new(Sizing::MemoryAndFalsePositiveRate {
+            bytes: 1,
+            false_positive_rate: 0.0001,
+        });
+        assert_eq!(tiny.parameters(), Err(Error::InvalidMemoryBudget(1)));
+        let counters = Builder::new(Sizing::MemoryAndInserts {
+            bytes: 7,
+            expected_inserts: 10,
+        });
+        assert_eq!(
+            counters.build_counting_bloom_filter().err(),
+            Some(Error::InvalidMemoryBudget(7))
+        );
+    }
+
+    #[test]
+    /// Test that variant-specific knobs are required and validated
+    fn test_knobs() {
+        let builder = Builder::new(Sizing::ExpectedInserts {
+            expected_inserts: 100,
+            false_positive_rate: 0.01,
+        });
+        assert_eq!(
+            builder.build_generational_bloom_filter().err(),
+            Some(Error::MissingParameter("num_generations"))
+        );
+        assert_eq!(
+            builder.build_scope_decay_bloom_filter().err(),
+            Some(Error::MissingParameter("bit_reset_rate"))
+        );
+        assert_eq!(
+            builder
+                .num_generations(1)
+                .build_generational_bloom_filter()
+                .err(),
+            Some(Error::InvalidNumGenerations(1))
+        );
+        assert_eq!(
+            builder
+                .bit_reset_rate(1.5)
+                .build_scope_decay_bloom_filter()
+                .err(),
+            Some(Error::InvalidBitResetRate(1.5))
+        );
+        assert_eq!(
+            builder
+                .num_generations(3)
+                .generation_duration(Duration::ZERO)
+                .build_generational_bloom_filter()
+                .err(),
+            Some(Error::InvalidGenerationDuration(Duration::ZERO))
+        );
+        let gbf: GenerationalBloomFilter = builder
+            .num_generations(3)
+            .generation_duration(Duration::from_secs(60))
+            .build_generational_bloom_filter()
+            .unwrap();
+        assert_eq!(gbf.get_generation_duration(), Some(Duration::from_secs(60)));
+    }
+}
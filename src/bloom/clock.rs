@@ -0,0 +1,94 @@
+/*!
+ * clock.rs
+ *
+ * Implements the time sources used by filters which expire items over time:
+ *      Clock       -- the trait of a time source, reporting the time elapsed since a fixed
+ *                     starting point.
+ *      SystemClock -- a Clock reading the system wall clock, as the time since the Unix epoch,
+ *                     so its readings carry over when a saved filter is loaded again.
+ *      ManualClock -- a Clock which only moves when told to, for tests and simulations. Clones
+ *                     share the same time, so a caller can keep one to advance a filter's clock.
+ */
+#![allow(clippy::needless_return)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A source of time for filters which expire items over time
+pub trait Clock: Send + Sync {
+    /// Time elapsed since a fixed starting point. A reading earlier than a previous one is
+    /// treated as no time passing. For the time a filter spent saved to count towards its
+    /// expiry, the starting point must be the same when it is loaded again
+    fn now(&self) -> Duration;
+}
+
+/// SystemClock struct, with no fields: the starting point is the Unix epoch
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    /// Create a new SystemClock
+    pub fn new() -> SystemClock {
+        return SystemClock;
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        // A system clock set before the epoch reads as the epoch itself
+        return SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO);
+    }
+}
+
+/// ManualClock struct:
+///    * nanos: Time elapsed, in nanoseconds, shared by every clone
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Create a new ManualClock, starting at zero
+    pub fn new() -> ManualClock {
+        return ManualClock::default();
+    }
+
+    /// Move the clock (and every clone of it) forward by the given duration
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        return Duration::from_nanos(self.nanos.load(Ordering::SeqCst));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that clones of a ManualClock share its time, which only moves when advanced
+    fn test_manual_clock() {
+        let clock: ManualClock = ManualClock::new();
+        let handle: ManualClock = clock.clone();
+        assert_eq!(clock.now(), Duration::ZERO);
+        handle.advance(Duration::from_secs(90));
+        handle.advance(Duration::from_millis(500));
+        assert_eq!(clock.now(), Duration::from_millis(90500));
+    }
+
+    #[test]
+    /// Test that every SystemClock reads the time since the Unix epoch
+    fn test_system_clock() {
+        let first: Duration = SystemClock::new().now();
+        // Later than 2020-01-01
+        assert!(first > Duration::from_secs(1_577_836_800));
+        assert!(SystemClock::new().now() >= first);
+    }
+}
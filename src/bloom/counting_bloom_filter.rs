@@ -1,11 +1,23 @@
 /*!
  * counting_bloom_filter.rs
  *
- * Implements a Murmur3-based counting bloom filter:
+ * Implements a counting bloom filter:
  *      CountingBloomFilter   -- A bloom filter which supports deleting items. Uses an
  *                               int-array rather than a bit-array under-the-hood.
  */
-use fasthash::murmur3;
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::DeletableFilter;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
+use crate::bloom::HashFunction;
+use crate::bloom::HashIndices;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
 
 /// CountingBloomFilter struct
 ///    * counter:             Counter vec
@@ -14,6 +26,8 @@ use fasthash::murmur3;
 ///    * false_positive_rate: False positive rate
 ///    * expected_inserts:    Expected inserts
 ///    * actual_inserts:      Actual inserts
+///    * hasher:              Hash function used to compute indices
+///    * indexing:            How indices are derived from the hash function
 pub struct CountingBloomFilter {
     counters: Vec<u64>,
     len: u64,
@@ -21,10 +35,17 @@ pub struct CountingBloomFilter {
     false_positive_rate: f64,
     expected_inserts: u64,
     actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
 }
 
 /// Implementation of a counting bloom filter. Uses a Vec<u64> to hold the counters
 impl CountingBloomFilter {
+    /// Getter for len
+    pub fn get_len(&self) -> u64 {
+        return self.len;
+    }
+
     /// Getter for hash_count
     pub fn get_hash_count(&self) -> u64 {
         return self.hash_count;
@@ -45,99 +66,216 @@ impl CountingBloomFilter {
         return self.actual_inserts;
     }
 
-    /// Given a desired false positive rate, calculate the length of the counter vec
-    /// See 'm' in this SO answer: https://stackoverflow.com/a/22467497
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn calculate_len(expected_inserts: f64, false_positive_rate: f64) -> u64 {
-        let two: f64 = 2.0;
-        return ((-1.0 * (expected_inserts) * false_positive_rate.ln()) / two.ln().powf(two)).ceil()
-            as u64;
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
     }
 
-    /// Calculate the number of hashes required
-    /// See 'k' in this SO answer: https://stackoverflow.com/a/22467497
-    /// k = ceil(m/n * ln(2))
-    fn calculate_hash_count(expected_inserts: f64, len: u64) -> u64 {
-        let two: f64 = 2.0;
-        return (((len as f64) / expected_inserts) * two.ln()).ceil() as u64;
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
     }
 
-    /// Return a single usize value, representing an index to be marked or checked
-    fn get_hash_index(i: u32, item: &str, len: u64) -> usize {
-        let digest_val: u128 = murmur3::hash128_with_seed(item, i); // Compute a murmur3 seeded hash
-        let counter_index: u64 = digest_val as u64 % len; // Mod the len of the counter vec
-        return counter_index as usize;
+    /// Create a new CountingBloomFilter, using the default (Murmur3) hash function
+    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> CountingBloomFilter {
+        return CountingBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
     }
 
-    /// Create a new CountingBloomFilter
-    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> CountingBloomFilter {
-        if false_positive_rate <= 0.0 {
-            panic!(
-                "False positive rate must be a positive number. Currently: {}",
-                false_positive_rate
-            );
+    /// Create a new CountingBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(expected_inserts: u64, false_positive_rate: f64) -> Result<CountingBloomFilter> {
+        return CountingBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new CountingBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> CountingBloomFilter {
+        return CountingBloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new CountingBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> CountingBloomFilter {
+        match CountingBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            indexing,
+        ) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new CountingBloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<CountingBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
         } else if expected_inserts < 1 {
-            panic!(
-                "Expected number of inserts must be a positive number. Currently: {}",
-                expected_inserts
-            );
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
         }
 
-        let len: u64 =
-            CountingBloomFilter::calculate_len(expected_inserts as f64, false_positive_rate);
-        let hash_count: u64 =
-            CountingBloomFilter::calculate_hash_count(expected_inserts as f64, len);
+        let len: u64 = params::optimal_len(expected_inserts, false_positive_rate);
+        let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        return Ok(CountingBloomFilter::from_parameters(
+            &params, hasher, indexing,
+        ));
+    }
 
+    /// Create a new CountingBloomFilter from already validated parameters
+    pub(crate) fn from_parameters(
+        params: &FilterParameters,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> CountingBloomFilter {
         CountingBloomFilter {
-            counters: vec![0; len as usize],
-            len: len,
-            hash_count: hash_count,
-            false_positive_rate: false_positive_rate,
-            expected_inserts: expected_inserts,
+            counters: vec![0; params.get_len() as usize],
+            len: params.get_len(),
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
+            expected_inserts: params.get_expected_inserts(),
             actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
+        }
+    }
+
+    /// Add 1 to (or subtract 1 from) the counters at each of an item's indices.
+    /// If any counter would overflow (or underflow), the counters already updated are
+    /// restored and an error is returned
+    fn update_counters(&mut self, bytes: &[u8], increment: bool) -> Result<()> {
+        let indices: HashIndices = HashIndices::new(
+            &self.hasher,
+            self.indexing,
+            bytes,
+            self.hash_count,
+            self.len,
+        );
+        for (applied, counter_index) in indices.enumerate() {
+            let counter: u64 = self.counters[counter_index];
+            let updated: Option<u64> = if increment {
+                counter.checked_add(1)
+            } else {
+                counter.checked_sub(1)
+            };
+            match updated {
+                Some(val) => self.counters[counter_index] = val,
+                None => {
+                    // Undo the counters we already touched, so the filter is left unchanged
+                    let applied_indices: HashIndices = HashIndices::new(
+                        &self.hasher,
+                        self.indexing,
+                        bytes,
+                        self.hash_count,
+                        self.len,
+                    );
+                    for restore_index in applied_indices.take(applied) {
+                        let restore = &mut self.counters[restore_index];
+                        *restore = if increment {
+                            *restore - 1
+                        } else {
+                            *restore + 1
+                        };
+                    }
+                    if increment {
+                        return Err(Error::CounterOverflow);
+                    }
+                    return Err(Error::CounterUnderflow);
+                }
+            }
         }
+        return Ok(());
     }
 
     /// Insert a new element into the CountingBloomFilter
-    pub fn insert(&mut self, item: &str) {
-        for i in 0..self.hash_count {
-            let counter_index: usize =
-                CountingBloomFilter::get_hash_index(i as u32, item, self.len);
-            let counter = &mut self.counters[counter_index];
-            *counter += 1; // Increment the relevant index by 1
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        if let Err(e) = self.try_insert(item) {
+            panic!("{}", e);
         }
+    }
+
+    /// Insert a new element into the CountingBloomFilter.
+    /// Returns an error, leaving the filter unchanged, if a counter would overflow
+    pub fn try_insert<T: Item + ?Sized>(&mut self, item: &T) -> Result<()> {
+        item.with_bytes(|bytes| self.update_counters(bytes, true))?;
         self.actual_inserts += 1;
+        return Ok(());
     }
 
     /// Check whether an element is probably in the filter or not
-    pub fn check(&self, item: &str) -> bool {
-        for i in 0..self.hash_count {
-            let counter_index: usize =
-                CountingBloomFilter::get_hash_index(i as u32, item, self.len);
-            // Check if the relevant index is set
-            if self.counters[counter_index as usize] < 1 {
-                return false;
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            for counter_index in indices {
+                // Check if the relevant index is set
+                if self.counters[counter_index] < 1 {
+                    return false;
+                }
             }
-        }
-        return true;
+            return true;
+        });
     }
 
     /// An item can only be deleted if it appears it was previously inserted
     /// (E.g. it passes a .check()) BUT obviously this is probabilistic so
     /// .delete() should be used with an abundance of caution
-    pub fn delete(&mut self, item: &str) {
-        if !self.check(item) {
-            return;
+    pub fn delete<T: Item + ?Sized>(&mut self, item: &T) {
+        if let Err(e) = self.try_delete(item) {
+            panic!("{}", e);
         }
-        for i in 0..self.hash_count {
-            let counter_index: usize =
-                CountingBloomFilter::get_hash_index(i as u32, item, self.len);
-            let counter = &mut self.counters[counter_index];
-            *counter -= 1; // Decrement by 1
-            if *counter == u64::max_value() {
-                panic!("CountingBloomFilter::delete() has been used on elements not contained in the filter and now corrupted!");
-            }
+    }
+
+    /// Delete an element from the CountingBloomFilter, as with .delete().
+    /// Returns an error, leaving the filter unchanged, if a counter would underflow
+    pub fn try_delete<T: Item + ?Sized>(&mut self, item: &T) -> Result<()> {
+        if !self.check(item) {
+            return Ok(());
         }
+        return item.with_bytes(|bytes| self.update_counters(bytes, false));
+    }
+
+    /// Report the counters above zero, the fill ratio and the estimated current false
+    /// positive rate
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let counters_set: u64 = self.counters.iter().filter(|c| **c > 0).count() as u64;
+        return FillStatistics::new(self.len, self.hash_count, counters_set);
     }
 
     /// Empty out the CountingBloomFilter: reset the counter vec so that it is zeroed-out
@@ -148,9 +286,107 @@ impl CountingBloomFilter {
     }
 }
 
+impl Filter for CountingBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        CountingBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return CountingBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        CountingBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return CountingBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return CountingBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return CountingBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return CountingBloomFilter::get_actual_inserts(self);
+    }
+}
+
+impl DeletableFilter for CountingBloomFilter {
+    fn try_delete_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        return CountingBloomFilter::try_delete(self, bytes);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use crate::bloom::serialization::{self, ByteBuf, Bytes};
+    use serde::de;
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialized form of a CountingBloomFilter, validated before it becomes one
+    #[derive(Deserialize)]
+    #[serde(rename = "CountingBloomFilter", deny_unknown_fields)]
+    struct CountingBloomFilterRepr {
+        len: u64,
+        hash_count: u64,
+        false_positive_rate: f64,
+        expected_inserts: u64,
+        actual_inserts: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+        counters: ByteBuf,
+    }
+
+    impl Serialize for CountingBloomFilter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("CountingBloomFilter", 8)?;
+            state.serialize_field("len", &self.len)?;
+            state.serialize_field("hash_count", &self.hash_count)?;
+            state.serialize_field("false_positive_rate", &self.false_positive_rate)?;
+            state.serialize_field("expected_inserts", &self.expected_inserts)?;
+            state.serialize_field("actual_inserts", &self.actual_inserts)?;
+            state.serialize_field("hasher", &self.hasher)?;
+            state.serialize_field("indexing", &self.indexing)?;
+            let counters: Vec<u8> = serialization::encode_counters(&self.counters);
+            state.serialize_field("counters", &Bytes(&counters))?;
+            return state.end();
+        }
+    }
+
+    impl<'de> Deserialize<'de> for CountingBloomFilter {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<CountingBloomFilter, D::Error> {
+            let repr: CountingBloomFilterRepr = CountingBloomFilterRepr::deserialize(deserializer)?;
+            let params: FilterParameters = FilterParameters::new(
+                repr.len,
+                repr.hash_count,
+                repr.expected_inserts,
+                repr.false_positive_rate,
+            );
+            params.validate().map_err(de::Error::custom)?;
+            let counters: Vec<u64> = serialization::decode_counters(&repr.counters.0, repr.len)
+                .map_err(de::Error::custom)?;
+            let mut cbf: CountingBloomFilter =
+                CountingBloomFilter::from_parameters(&params, repr.hasher, repr.indexing);
+            cbf.counters = counters;
+            cbf.actual_inserts = repr.actual_inserts;
+            return Ok(cbf);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bloom::BloomHasher;
 
     #[test]
     /// Test that we can create a new CountingBloomFilter using CountingBloomFilter::new()
@@ -170,19 +406,30 @@ mod tests {
         let s2 = "This is another string"; // Inserted
         let s3 = "This is a third string"; // Not inserted
         let mut bf: CountingBloomFilter = CountingBloomFilter::new(2, 0.05);
-        bf.insert(&s);
-        bf.insert(&s2);
-        assert!(bf.check(&s)); // Included
-        assert!(bf.check(&s2)); // Included
-        assert!(!bf.check(&s3)); // Not included
+        bf.insert(s);
+        bf.insert(s2);
+        assert!(bf.check(s)); // Included
+        assert!(bf.check(s2)); // Included
+        assert!(!bf.check(s3)); // Not included
     }
 
     #[test]
-    /// Test that the get_hash_index method works as expected
-    fn test_get_hash_index() {
+    /// Test that the PerSeed indexing scheme marks the same indices as earlier versions,
+    /// where index i is the low 64 bits of the murmur3 digest seeded with i, mod len
+    fn test_per_seed_indexing() {
         let s = "This is a test string";
-        let i: u32 = 32;
-        assert_eq!(CountingBloomFilter::get_hash_index(i, &s, 10), 4);
+        let mut bf: CountingBloomFilter = CountingBloomFilter::with_hashing(
+            2,
+            0.05,
+            HashFunction::Murmur3,
+            IndexingScheme::PerSeed,
+        );
+        bf.insert(s);
+        for i in 0..bf.get_hash_count() {
+            let digest_val: u128 = HashFunction::Murmur3.hash128(s.as_bytes(), i as u32);
+            let index: usize = (digest_val as u64 % bf.len) as usize;
+            assert!(bf.counters[index] > 0);
+        }
     }
 
     #[test]
@@ -231,6 +478,50 @@ mod tests {
         assert!(!bf.check(&1.to_string()));
     }
 
+    #[test]
+    /// Test that a counter underflow is reported, and leaves the counters untouched
+    fn test_delete_underflow() {
+        let mut bf: CountingBloomFilter = CountingBloomFilter::new(100, 0.05);
+        bf.insert(&1);
+        let mut indices = HashIndices::new(
+            &bf.hasher,
+            bf.indexing,
+            &[1, 0, 0, 0],
+            bf.hash_count,
+            bf.len,
+        );
+        let last: usize = indices.by_ref().last().unwrap();
+        bf.counters[last] = 0;
+        let before: Vec<u64> = bf.counters.clone();
+        assert_eq!(
+            bf.update_counters(&[1, 0, 0, 0], false),
+            Err(Error::CounterUnderflow)
+        );
+        assert_eq!(bf.counters, before);
+        // The item no longer passes check(), so try_delete() leaves it alone
+        assert_eq!(bf.try_delete(&1), Ok(()));
+        assert_eq!(bf.counters, before);
+    }
+
+    #[test]
+    /// Test that try_insert reports an overflow, and leaves the counters untouched
+    fn test_try_insert_overflow() {
+        let mut bf: CountingBloomFilter = CountingBloomFilter::new(100, 0.05);
+        let mut indices = HashIndices::new(
+            &bf.hasher,
+            bf.indexing,
+            &[1, 0, 0, 0],
+            bf.hash_count,
+            bf.len,
+        );
+        let last: usize = indices.by_ref().last().unwrap();
+        bf.counters[last] = u64::MAX;
+        let before: Vec<u64> = bf.counters.clone();
+        assert_eq!(bf.try_insert(&1), Err(Error::CounterOverflow));
+        assert_eq!(bf.counters, before);
+        assert_eq!(bf.get_actual_inserts(), 0);
+    }
+
     #[test]
     /// Test that the empty method empties out the counter Vec
     fn test_empty() {
@@ -249,37 +540,20 @@ mod tests {
     }
 
     #[test]
-    /// Test that we get the expected value for calculating the length of the
-    /// BitVec using the computation:
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn test_calculate_len() {
-        let m: u64 = CountingBloomFilter::calculate_len(100 as f64, 0.01);
-        assert_eq!(m, 959);
-        let m2: u64 = CountingBloomFilter::calculate_len(1000 as f64, 0.001);
-        assert_eq!(m2, 14378);
-        let m3: u64 = CountingBloomFilter::calculate_len(5 as f64, 0.1);
-        assert_eq!(m3, 24);
-        let m4: u64 = CountingBloomFilter::calculate_len(10000 as f64, 0.01);
-        assert_eq!(m4, 95851);
-        let m5: u64 = CountingBloomFilter::calculate_len(216553 as f64, 0.01);
-        assert_eq!(m5, 2075674);
-    }
-
-    #[test]
-    /// Test that we get the expected value for calculating the number of iterations
-    /// if hashing we need to perform
-    /// k = ceil(m/n * ln(2))
-    fn test_calculate_hash_count() {
-        let k: u64 = CountingBloomFilter::calculate_hash_count(100 as f64, 959);
-        assert_eq!(k, 7);
-        let k2: u64 = CountingBloomFilter::calculate_hash_count(1000 as f64, 14378);
-        assert_eq!(k2, 10);
-        let k3: u64 = CountingBloomFilter::calculate_hash_count(5 as f64, 24);
-        assert_eq!(k3, 4);
-        let k4: u64 = CountingBloomFilter::calculate_hash_count(10000 as f64, 95851);
-        assert_eq!(k4, 7);
-        let k5: u64 = CountingBloomFilter::calculate_hash_count(216553 as f64, 2075674);
-        assert_eq!(k5, 7);
+    /// Test that deleting items clears their counters from the fill statistics
+    fn test_fill_statistics() {
+        let mut bf: CountingBloomFilter = CountingBloomFilter::new(100, 0.01);
+        for i in 0..100 {
+            bf.insert(&i);
+        }
+        let stats: FillStatistics = bf.fill_statistics();
+        assert!(stats.get_bits_set() > 0);
+        assert!(stats.get_bits_set() <= 100 * bf.get_hash_count());
+        for i in 0..100 {
+            bf.delete(&i);
+        }
+        assert_eq!(bf.fill_statistics().get_bits_set(), 0);
+        assert_eq!(bf.fill_statistics().get_false_positive_rate(), 0.0);
     }
 
     #[test]
@@ -0,0 +1,538 @@
+/*!
+ * cuckoo_filter.rs
+ *
+ * Implements a cuckoo filter (Fan, Andersen, Kaminsky & Mitzenmacher, 2014):
+ *      CuckooFilter -- stores a short fingerprint of each item in one of two candidate buckets
+ *                      of b slots. The second bucket is derived from the first and the
+ *                      fingerprint alone, so that a stored fingerprint can be moved ("kicked")
+ *                      to its alternate bucket to make room for a new one. Unlike a
+ *                      BloomFilter it supports deletion and counting, and it can fill up:
+ *                      an insert that cannot find room within a bounded number of kicks
+ *                      fails with Error::FilterFull, leaving the filter unchanged.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::BloomHasher;
+use crate::bloom::DeletableFilter;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::HashFunction;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use rand::distributions::{Distribution, Uniform};
+use rand::Rng;
+
+/// Default number of slots per bucket
+pub const DEFAULT_BUCKET_SIZE: u64 = 4;
+/// Largest supported fingerprint size, in bits
+pub const MAX_FINGERPRINT_BITS: u32 = 32;
+/// Largest supported number of slots per bucket
+pub const MAX_BUCKET_SIZE: u64 = 16;
+/// Number of fingerprints moved to their alternate bucket before an insert gives up
+const MAX_KICKS: usize = 500;
+
+/// CuckooFilter struct:
+///    * slots:               Fingerprints, bit-packed in bucket order. 0 marks an empty slot
+///    * num_buckets:         Number of buckets, a power of two
+///    * bucket_size:         Number of slots per bucket
+///    * fingerprint_bits:    Size of a fingerprint, in bits
+///    * false_positive_rate: False positive rate once the filter is full
+///    * expected_inserts:    Expected inserts
+///    * actual_inserts:      Actual inserts, less deletions
+///    * hasher:              Hash function used to pick the buckets and the fingerprints
+#[derive(Clone)]
+pub struct CuckooFilter {
+    slots: Vec<u64>,
+    num_buckets: u64,
+    bucket_size: u64,
+    fingerprint_bits: u32,
+    false_positive_rate: f64,
+    expected_inserts: u64,
+    actual_inserts: u64,
+    hasher: HashFunction,
+}
+
+/// Implementation of a cuckoo filter
+impl CuckooFilter {
+    /// Getter for the total number of slots
+    pub fn get_len(&self) -> u64 {
+        return self.num_buckets * self.bucket_size;
+    }
+
+    /// Getter for num_buckets
+    pub fn get_num_buckets(&self) -> u64 {
+        return self.num_buckets;
+    }
+
+    /// Getter for bucket_size
+    pub fn get_bucket_size(&self) -> u64 {
+        return self.bucket_size;
+    }
+
+    /// Getter for fingerprint_bits
+    pub fn get_fingerprint_bits(&self) -> u32 {
+        return self.fingerprint_bits;
+    }
+
+    /// Getter for the hash count: the number of candidate buckets of an item, always 2
+    pub fn get_hash_count(&self) -> u64 {
+        return 2;
+    }
+
+    /// Getter for false_positive_rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for expected_inserts
+    pub fn get_expected_inserts(&self) -> u64 {
+        return self.expected_inserts;
+    }
+
+    /// Getter for actual_inserts
+    pub fn get_actual_inserts(&self) -> u64 {
+        return self.actual_inserts;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Create a new CuckooFilter with DEFAULT_BUCKET_SIZE slots per bucket, using the default
+    /// (Murmur3) hash function
+    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> CuckooFilter {
+        return CuckooFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new CuckooFilter with DEFAULT_BUCKET_SIZE slots per bucket, using the default
+    /// (Murmur3) hash function. Returns an error if the parameters are invalid
+    pub fn try_new(expected_inserts: u64, false_positive_rate: f64) -> Result<CuckooFilter> {
+        return CuckooFilter::try_with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new CuckooFilter with DEFAULT_BUCKET_SIZE slots per bucket, using the given
+    /// hash function
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> CuckooFilter {
+        match CuckooFilter::try_with_hasher(expected_inserts, false_positive_rate, hasher) {
+            Ok(cf) => return cf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new CuckooFilter with DEFAULT_BUCKET_SIZE slots per bucket, using the given
+    /// hash function. The fingerprints are sized with params::cuckoo_fingerprint_bits().
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> Result<CuckooFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+        }
+        let fingerprint_bits: u32 =
+            params::cuckoo_fingerprint_bits(false_positive_rate, DEFAULT_BUCKET_SIZE);
+        return CuckooFilter::try_with_layout(
+            expected_inserts,
+            fingerprint_bits,
+            DEFAULT_BUCKET_SIZE,
+            hasher,
+        );
+    }
+
+    /// Create a new CuckooFilter with the given fingerprint size and bucket size, using the
+    /// given hash function
+    pub fn with_layout(
+        expected_inserts: u64,
+        fingerprint_bits: u32,
+        bucket_size: u64,
+        hasher: HashFunction,
+    ) -> CuckooFilter {
+        match CuckooFilter::try_with_layout(expected_inserts, fingerprint_bits, bucket_size, hasher)
+        {
+            Ok(cf) => return cf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new CuckooFilter with the given fingerprint size and bucket size, using the
+    /// given hash function. Returns an error if the parameters are invalid
+    pub fn try_with_layout(
+        expected_inserts: u64,
+        fingerprint_bits: u32,
+        bucket_size: u64,
+        hasher: HashFunction,
+    ) -> Result<CuckooFilter> {
+        if expected_inserts < 1 {
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
+        } else if !(1..=MAX_FINGERPRINT_BITS).contains(&fingerprint_bits) {
+            return Err(Error::InvalidFingerprintBits(fingerprint_bits));
+        } else if !(1..=MAX_BUCKET_SIZE).contains(&bucket_size) {
+            return Err(Error::InvalidBucketSize(bucket_size));
+        }
+
+        let num_buckets: u64 = params::cuckoo_num_buckets(expected_inserts, bucket_size);
+        let total_bits: u64 = num_buckets * bucket_size * fingerprint_bits as u64;
+        return Ok(CuckooFilter {
+            slots: vec![0; total_bits.div_ceil(64) as usize],
+            num_buckets: num_buckets,
+            bucket_size: bucket_size,
+            fingerprint_bits: fingerprint_bits,
+            false_positive_rate: params::cuckoo_false_positive_rate(fingerprint_bits, bucket_size),
+            expected_inserts: expected_inserts,
+            actual_inserts: 0,
+            hasher: hasher,
+        });
+    }
+
+    /// Read the fingerprint in the given slot of the given bucket
+    fn get_slot(&self, bucket: u64, slot: u64) -> u64 {
+        let bit: u64 = (bucket * self.bucket_size + slot) * self.fingerprint_bits as u64;
+        let (word, offset): (usize, u64) = ((bit / 64) as usize, bit % 64);
+        let mut value: u64 = self.slots[word] >> offset;
+        if offset + self.fingerprint_bits as u64 > 64 {
+            value |= self.slots[word + 1] << (64 - offset);
+        }
+        return value & self.fingerprint_mask();
+    }
+
+    /// Write a fingerprint into the given slot of the given bucket
+    fn set_slot(&mut self, bucket: u64, slot: u64, fingerprint: u64) {
+        let mask: u64 = self.fingerprint_mask();
+        let bit: u64 = (bucket * self.bucket_size + slot) * self.fingerprint_bits as u64;
+        let (word, offset): (usize, u64) = ((bit / 64) as usize, bit % 64);
+        self.slots[word] = (self.slots[word] & !(mask << offset)) | (fingerprint << offset);
+        if offset + self.fingerprint_bits as u64 > 64 {
+            let shift: u64 = 64 - offset;
+            self.slots[word + 1] =
+                (self.slots[word + 1] & !(mask >> shift)) | (fingerprint >> shift);
+        }
+    }
+
+    /// Mask covering the bits of a fingerprint
+    fn fingerprint_mask(&self) -> u64 {
+        return (1u64 << self.fingerprint_bits) - 1;
+    }
+
+    /// Locate an item: one 128-bit digest gives its first bucket, from the low half, and its
+    /// fingerprint, from the high half. A fingerprint of 0 would read as an empty slot, so
+    /// it is stored as 1. Returns the item's two buckets and its fingerprint
+    fn locate(&self, bytes: &[u8]) -> (u64, u64, u64) {
+        let digest_val: u128 = self.hasher.hash128(bytes, 0);
+        let bucket: u64 = (digest_val as u64) & (self.num_buckets - 1);
+        let fingerprint: u64 = ((digest_val >> 64) as u64 & self.fingerprint_mask()).max(1);
+        return (
+            bucket,
+            self.alternate_bucket(bucket, fingerprint),
+            fingerprint,
+        );
+    }
+
+    /// The other candidate bucket of a fingerprint stored in the given bucket. XORing with
+    /// the fingerprint's hash is its own inverse, so this maps each bucket to the other
+    fn alternate_bucket(&self, bucket: u64, fingerprint: u64) -> u64 {
+        let fingerprint_hash: u64 = self.hasher.hash128(&fingerprint.to_le_bytes(), 0) as u64;
+        return (bucket ^ fingerprint_hash) & (self.num_buckets - 1);
+    }
+
+    /// Store a fingerprint in the first empty slot of a bucket. Returns false if it is full
+    fn store(&mut self, bucket: u64, fingerprint: u64) -> bool {
+        for slot in 0..self.bucket_size {
+            if self.get_slot(bucket, slot) == 0 {
+                self.set_slot(bucket, slot, fingerprint);
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Count the slots of a bucket holding the given fingerprint
+    fn count_in_bucket(&self, bucket: u64, fingerprint: u64) -> u64 {
+        return (0..self.bucket_size)
+            .filter(|slot| self.get_slot(bucket, *slot) == fingerprint)
+            .count() as u64;
+    }
+
+    /// Insert a new element into the CuckooFilter.
+    /// Panics if the filter is full, see .try_insert()
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        if let Err(e) = self.try_insert(item) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Insert a new element into the CuckooFilter, as with .insert(). When both candidate
+    /// buckets are full, fingerprints are kicked to their alternate buckets, up to MAX_KICKS
+    /// times. Returns Error::FilterFull, leaving the filter unchanged, if no room is found
+    pub fn try_insert<T: Item + ?Sized>(&mut self, item: &T) -> Result<()> {
+        let (first, second, fingerprint): (u64, u64, u64) =
+            item.with_bytes(|bytes| self.locate(bytes));
+        if !(self.store(first, fingerprint) || self.store(second, fingerprint)) {
+            self.relocate(first, second, fingerprint)?;
+        }
+        self.actual_inserts += 1;
+        return Ok(());
+    }
+
+    /// Make room for a fingerprint whose buckets are both full, by kicking out a random
+    /// fingerprint, then moving it to its alternate bucket, and so on. The kicks are recorded
+    /// so that they can be undone if no empty slot is reached
+    fn relocate(&mut self, first: u64, second: u64, fingerprint: u64) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let slot_range: Uniform<u64> = Uniform::new(0, self.bucket_size);
+        let mut kicked: Vec<(u64, u64, u64)> = Vec::with_capacity(MAX_KICKS);
+        let mut bucket: u64 = if rng.gen() { first } else { second };
+        let mut fingerprint: u64 = fingerprint;
+        for _ in 0..MAX_KICKS {
+            let slot: u64 = slot_range.sample(&mut rng);
+            let victim: u64 = self.get_slot(bucket, slot);
+            self.set_slot(bucket, slot, fingerprint);
+            kicked.push((bucket, slot, victim));
+            fingerprint = victim;
+            bucket = self.alternate_bucket(bucket, fingerprint);
+            if self.store(bucket, fingerprint) {
+                return Ok(());
+            }
+        }
+        for (bucket, slot, victim) in kicked.into_iter().rev() {
+            self.set_slot(bucket, slot, victim);
+        }
+        return Err(Error::FilterFull);
+    }
+
+    /// Check whether an element is probably in the filter or not
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return self.count(item) > 0;
+    }
+
+    /// Count how many times an element was probably inserted, ie the number of copies of its
+    /// fingerprint in its two buckets. Never less than the true count, as long as the item
+    /// was not deleted more often than it was inserted
+    pub fn count<T: Item + ?Sized>(&self, item: &T) -> u64 {
+        let (first, second, fingerprint): (u64, u64, u64) =
+            item.with_bytes(|bytes| self.locate(bytes));
+        let mut copies: u64 = self.count_in_bucket(first, fingerprint);
+        if second != first {
+            copies += self.count_in_bucket(second, fingerprint);
+        }
+        return copies;
+    }
+
+    /// Delete one copy of an element from the CuckooFilter. Deleting an item which was never
+    /// inserted can remove another item sharing its fingerprint and buckets
+    pub fn delete<T: Item + ?Sized>(&mut self, item: &T) {
+        if let Err(e) = self.try_delete(item) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Delete one copy of an element from the CuckooFilter, as with .delete().
+    /// Does nothing if the element is not in the filter
+    pub fn try_delete<T: Item + ?Sized>(&mut self, item: &T) -> Result<()> {
+        let (first, second, fingerprint): (u64, u64, u64) =
+            item.with_bytes(|bytes| self.locate(bytes));
+        for bucket in [first, second] {
+            for slot in 0..self.bucket_size {
+                if self.get_slot(bucket, slot) == fingerprint {
+                    self.set_slot(bucket, slot, 0);
+                    self.actual_inserts = self.actual_inserts.saturating_sub(1);
+                    return Ok(());
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Empty out the CuckooFilter
+    pub fn empty(&mut self) {
+        self.slots = vec![0; self.slots.len()];
+        self.actual_inserts = 0;
+    }
+
+    /// Report the occupied slots, the load factor and the estimated current false positive
+    /// rate: a check compares the fingerprint with the occupied slots of its two buckets,
+    /// 2b * load of them on average
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let mut occupied: u64 = 0;
+        for bucket in 0..self.num_buckets {
+            occupied += (0..self.bucket_size)
+                .filter(|slot| self.get_slot(bucket, *slot) != 0)
+                .count() as u64;
+        }
+        let load: f64 = occupied as f64 / self.get_len() as f64;
+        let match_rate: f64 = 0.5f64.powi(self.fingerprint_bits as i32);
+        let rate: f64 = 1.0 - (1.0 - match_rate).powf(2.0 * self.bucket_size as f64 * load);
+        return FillStatistics::with_false_positive_rate(self.get_len(), occupied, rate);
+    }
+}
+
+impl Filter for CuckooFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        CuckooFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return CuckooFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        CuckooFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return CuckooFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return CuckooFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return CuckooFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return CuckooFilter::get_actual_inserts(self);
+    }
+}
+
+impl DeletableFilter for CuckooFilter {
+    fn try_delete_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        return CuckooFilter::try_delete(self, bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the filter is sized from (n, p), with the default bucket size
+    fn test_new() {
+        let cf: CuckooFilter = CuckooFilter::new(1000, 0.01);
+        assert_eq!(cf.get_bucket_size(), DEFAULT_BUCKET_SIZE);
+        assert_eq!(cf.get_fingerprint_bits(), 10);
+        assert_eq!(cf.get_num_buckets(), 512);
+        assert_eq!(cf.get_len(), 2048);
+        assert_eq!(cf.slots.len(), 320);
+        assert!(cf.get_false_positive_rate() <= 0.01);
+        assert_eq!(cf.get_hash_count(), 2);
+        assert_eq!(cf.get_actual_inserts(), 0);
+    }
+
+    #[test]
+    /// Test that fingerprints straddling two words are read back intact
+    fn test_slots() {
+        let mut cf: CuckooFilter = CuckooFilter::with_layout(64, 13, 3, HashFunction::default());
+        let value = |bucket: u64, slot: u64| ((bucket * 3 + slot + 1) * 97) & ((1 << 13) - 1);
+        for bucket in 0..cf.get_num_buckets() {
+            for slot in 0..3 {
+                cf.set_slot(bucket, slot, value(bucket, slot));
+            }
+        }
+        for bucket in 0..cf.get_num_buckets() {
+            for slot in 0..3 {
+                assert_eq!(cf.get_slot(bucket, slot), value(bucket, slot));
+            }
+        }
+        cf.set_slot(5, 1, 0);
+        assert_eq!(cf.get_slot(5, 0), value(5, 0));
+        assert_eq!(cf.get_slot(5, 1), 0);
+        assert_eq!(cf.get_slot(5, 2), value(5, 2));
+    }
+
+    #[test]
+    /// Test that an item's buckets map onto each other
+    fn test_alternate_bucket() {
+        let cf: CuckooFilter = CuckooFilter::new(1000, 0.01);
+        for i in 0..1000u32 {
+            let (first, second, fingerprint): (u64, u64, u64) = cf.locate(&i.to_le_bytes());
+            assert!(fingerprint > 0);
+            assert_eq!(cf.alternate_bucket(second, fingerprint), first);
+        }
+    }
+
+    #[test]
+    /// Test insert, check, count and delete
+    fn test_insert_delete() {
+        let mut cf: CuckooFilter = CuckooFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            cf.insert(&i);
+        }
+        assert!((0..1000).all(|i| cf.check(&i)));
+        assert_eq!(cf.get_actual_inserts(), 1000);
+        assert_eq!(cf.fill_statistics().get_bits_set(), 1000);
+        cf.insert(&7);
+        assert!(cf.count(&7) >= 2);
+        cf.delete(&7);
+        cf.delete(&7);
+        for i in 0..500 {
+            cf.delete(&i);
+        }
+        assert!((500..1000).all(|i| cf.check(&i)));
+        assert!((0..500).filter(|i| cf.check(i)).count() < 10);
+        assert_eq!(cf.get_actual_inserts(), 500);
+        cf.empty();
+        assert_eq!(cf.fill_statistics().get_bits_set(), 0);
+        assert!(!(500..1000).any(|i| cf.check(&i)));
+    }
+
+    #[test]
+    /// Test that a full filter reports an error and is left unchanged
+    fn test_full() {
+        let mut cf: CuckooFilter = CuckooFilter::with_layout(8, 16, 1, HashFunction::default());
+        let mut inserted: Vec<u32> = vec![];
+        let mut error: Option<Error> = None;
+        for i in 0..100u32 {
+            let before: Vec<u64> = cf.slots.clone();
+            match cf.try_insert(&i) {
+                Ok(()) => inserted.push(i),
+                Err(e) => {
+                    assert_eq!(cf.slots, before);
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        assert_eq!(error, Some(Error::FilterFull));
+        assert!(inserted.len() as u64 <= cf.get_len());
+        assert_eq!(cf.get_actual_inserts(), inserted.len() as u64);
+        assert!(inserted.iter().all(|i| cf.check(i)));
+    }
+
+    #[test]
+    /// Test that invalid parameters are reported
+    fn test_invalid_parameters() {
+        assert_eq!(
+            CuckooFilter::try_new(0, 0.01).err(),
+            Some(Error::InvalidExpectedInserts(0))
+        );
+        assert_eq!(
+            CuckooFilter::try_new(10, 0.0).err(),
+            Some(Error::InvalidFalsePositiveRate(0.0))
+        );
+        assert_eq!(
+            CuckooFilter::try_with_layout(10, 33, 4, HashFunction::default()).err(),
+            Some(Error::InvalidFingerprintBits(33))
+        );
+        assert_eq!(
+            CuckooFilter::try_with_layout(10, 8, 0, HashFunction::default()).err(),
+            Some(Error::InvalidBucketSize(0))
+        );
+    }
+}
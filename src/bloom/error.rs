@@ -0,0 +1,261 @@
+/*!
+ * error.rs
+ *
+ * Implements the error type returned by the fallible constructors and mutation methods:
+ *      Error  -- an enum with one variant per failure.
+ *      Result -- a Result type alias using Error.
+ */
+#![allow(clippy::needless_return)]
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Errors returned by the bloom filter types
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    /// The false positive rate is not in the open interval (0.0, 1.0)
+    InvalidFalsePositiveRate(f64),
+    /// The expected number of inserts is zero
+    InvalidExpectedInserts(u64),
+    /// The number of generations is less than 2
+    InvalidNumGenerations(u64),
+    /// The bit reset rate is not in the closed interval [0.0, 1.0]
+    InvalidBitResetRate(f64),
+    /// An explicit length is zero, or too small for the filter
+    InvalidLen(u64),
+    /// An explicit hash count is zero
+    InvalidHashCount(u64),
+    /// The memory budget (in bytes) is too small to hold a useful filter
+    InvalidMemoryBudget(u64),
+    /// A parameter required by the filter variant was not given to the builder
+    MissingParameter(&'static str),
+    /// A counter would drop below zero
+    CounterUnderflow,
+    /// A counter would exceed its maximum value
+    CounterOverflow,
+    /// Reading or writing a saved filter failed
+    Io(io::ErrorKind),
+    /// The data does not start with the magic bytes of a saved filter
+    InvalidMagic,
+    /// The saved filter uses a format version this version of the crate cannot read
+    UnsupportedVersion(u16),
+    /// The checksum of a saved filter does not match its contents
+    ChecksumMismatch,
+    /// A saved filter has an invalid or inconsistent field
+    Corrupt(&'static str),
+    /// The filters can't be combined: their length, hash count or hashing differ
+    IncompatibleFilters,
+    /// The filter length is not divisible by 2^times, so it can't be folded times times
+    InvalidFoldCount(u32),
+    /// The growth factor of a scalable filter is less than 1.0
+    InvalidGrowthFactor(f64),
+    /// The tightening ratio of a scalable filter is not in the open interval (0.0, 1.0)
+    InvalidTighteningRatio(f64),
+    /// The fingerprint size (in bits) is not supported by the filter: between 1 and 32
+    /// inclusive for a cuckoo filter, 8 or 16 for a binary fuse filter
+    InvalidFingerprintBits(u32),
+    /// The bucket size is not between 1 and 16 inclusive
+    InvalidBucketSize(u64),
+    /// The filter has no room left for the item
+    FilterFull,
+    /// The remainder size (in bits) is below 1, or too large for the fingerprint
+    InvalidRemainderBits(u32),
+    /// A static filter could not be built within the given number of attempts
+    ConstructionFailed(u32),
+    /// The maximum cell value of a stable filter is not between 1 and 255 inclusive
+    InvalidMaxCellValue(u64),
+    /// The number of cells a stable filter decrements per insert is zero, or more than it has
+    InvalidDecrementCount(u64),
+    /// The generation duration of a generational filter is zero
+    InvalidGenerationDuration(Duration),
+    /// No filter of the kind that fits in memory can meet the false positive rate
+    UnreachableFalsePositiveRate(f64),
+}
+
+/// Result type alias for the bloom filter types
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidFalsePositiveRate(rate) => write!(
+                f,
+                "False positive rate must be a number between 0.0 and 1.0 exclusive. Currently: {}",
+                rate
+            ),
+            Error::InvalidExpectedInserts(inserts) => write!(
+                f,
+                "Expected number of inserts must be a positive number. Currently: {}",
+                inserts
+            ),
+            Error::InvalidNumGenerations(generations) => write!(
+                f,
+                "Expected number of generations must be a positive integer >= 2. Currently: {}",
+                generations
+            ),
+            Error::InvalidBitResetRate(rate) => write!(
+                f,
+                "Bit reset rate must be a positive number between 0.0 and 1.0 inclusive. Currently: {}",
+                rate
+            ),
+            Error::InvalidLen(len) => {
+                write!(f, "Length must be a positive number. Currently: {}", len)
+            }
+            Error::InvalidHashCount(hash_count) => write!(
+                f,
+                "Hash count must be a positive number. Currently: {}",
+                hash_count
+            ),
+            Error::InvalidMemoryBudget(bytes) => write!(
+                f,
+                "Memory budget is too small for the requested filter. Currently: {} bytes",
+                bytes
+            ),
+            Error::MissingParameter(name) => {
+                write!(f, "Missing required parameter: {}", name)
+            }
+            Error::CounterUnderflow => write!(
+                f,
+                "Counter underflow: delete() has been used on an element not contained in the filter"
+            ),
+            Error::CounterOverflow => write!(f, "Counter overflow: a counter is already at its maximum"),
+            Error::Io(kind) => write!(f, "I/O error: {:?}", kind),
+            Error::InvalidMagic => write!(f, "Not a saved filter: invalid magic bytes"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Unsupported format version: {}", version)
+            }
+            Error::ChecksumMismatch => write!(f, "Checksum mismatch: the saved filter is corrupt"),
+            Error::Corrupt(reason) => write!(f, "Corrupt saved filter: {}", reason),
+            Error::IncompatibleFilters => write!(
+                f,
+                "Incompatible filters: the length, hash count and hashing must be the same"
+            ),
+            Error::InvalidFoldCount(times) => write!(
+                f,
+                "Length must be divisible by 2^times to fold a filter times times. Currently: {}",
+                times
+            ),
+            Error::InvalidGrowthFactor(factor) => write!(
+                f,
+                "Growth factor must be a number >= 1.0. Currently: {}",
+                factor
+            ),
+            Error::InvalidTighteningRatio(ratio) => write!(
+                f,
+                "Tightening ratio must be a number between 0.0 and 1.0 exclusive. Currently: {}",
+                ratio
+            ),
+            Error::InvalidFingerprintBits(bits) => write!(
+                f,
+                "Fingerprint size is not supported by this filter. Currently: {}",
+                bits
+            ),
+            Error::InvalidBucketSize(size) => write!(
+                f,
+                "Bucket size must be between 1 and 16 inclusive. Currently: {}",
+                size
+            ),
+            Error::FilterFull => write!(f, "Filter full: no room left to insert the item"),
+            Error::InvalidRemainderBits(bits) => write!(
+                f,
+                "Remainder size must be at least 1 bit, and fit in a 64-bit fingerprint. Currently: {}",
+                bits
+            ),
+            Error::ConstructionFailed(attempts) => write!(
+                f,
+                "Construction failed: no valid filter found in {} attempts",
+                attempts
+            ),
+            Error::InvalidMaxCellValue(value) => write!(
+                f,
+                "Maximum cell value must be between 1 and 255 inclusive. Currently: {}",
+                value
+            ),
+            Error::InvalidDecrementCount(count) => write!(
+                f,
+                "Decrement count must be between 1 and the number of cells inclusive. Currently: {}",
+                count
+            ),
+            Error::InvalidGenerationDuration(duration) => write!(
+                f,
+                "Generation duration must be positive. Currently: {:?}",
+                duration
+            ),
+            Error::UnreachableFalsePositiveRate(rate) => write!(
+                f,
+                "False positive rate is too low for this filter to reach. Currently: {}",
+                rate
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        return Error::Io(e.kind());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the Display messages include the offending values
+    fn test_display() {
+        assert_eq!(
+            Error::InvalidFalsePositiveRate(-0.03).to_string(),
+            "False positive rate must be a number between 0.0 and 1.0 exclusive. Currently: -0.03"
+        );
+        assert_eq!(
+            Error::InvalidExpectedInserts(0).to_string(),
+            "Expected number of inserts must be a positive number. Currently: 0"
+        );
+        assert!(Error::InvalidNumGenerations(1)
+            .to_string()
+            .ends_with("Currently: 1"));
+        assert!(Error::InvalidBitResetRate(33.7)
+            .to_string()
+            .ends_with("Currently: 33.7"));
+        assert_eq!(
+            Error::MissingParameter("num_generations").to_string(),
+            "Missing required parameter: num_generations"
+        );
+        assert!(Error::InvalidFoldCount(3)
+            .to_string()
+            .ends_with("Currently: 3"));
+        assert!(Error::InvalidGrowthFactor(0.5)
+            .to_string()
+            .ends_with("Currently: 0.5"));
+        assert!(Error::InvalidTighteningRatio(1.5)
+            .to_string()
+            .ends_with("Currently: 1.5"));
+        assert!(Error::InvalidFingerprintBits(33)
+            .to_string()
+            .ends_with("Currently: 33"));
+        assert!(Error::InvalidBucketSize(0)
+            .to_string()
+            .ends_with("Currently: 0"));
+        assert!(Error::InvalidRemainderBits(0)
+            .to_string()
+            .ends_with("Currently: 0"));
+        assert!(Error::ConstructionFailed(10)
+            .to_string()
+            .ends_with("in 10 attempts"));
+        assert!(Error::InvalidMaxCellValue(256)
+            .to_string()
+            .ends_with("Currently: 256"));
+        assert!(Error::InvalidDecrementCount(0)
+            .to_string()
+            .ends_with("Currently: 0"));
+        assert!(Error::InvalidGenerationDuration(Duration::ZERO)
+            .to_string()
+            .ends_with("Currently: 0ns"));
+        assert!(Error::UnreachableFalsePositiveRate(0.001)
+            .to_string()
+            .ends_with("Currently: 0.001"));
+    }
+}
@@ -0,0 +1,120 @@
+/*!
+ * filter.rs
+ *
+ * Defines the traits shared by the bloom filter variants:
+ *      Filter          -- the common interface implemented by every bloom filter type, so that
+ *                         callers can be generic over the kind of filter in use, or pick one
+ *                         at runtime as a `Box<dyn Filter>`.
+ *      DeletableFilter -- a Filter which additionally supports deleting items.
+ */
+#![allow(clippy::needless_return)]
+use crate::bloom::Item;
+use crate::bloom::Result;
+
+/// Common interface implemented by every bloom filter variant.
+///
+/// The required methods take the stable byte encoding of an item (see `Item`), so the trait
+/// can be used as a trait object. The generic `insert` and `check` encode the item and call
+/// them; they are only available on sized types, which includes `Box<dyn Filter>`. Checking
+/// takes `&mut self`, since some variants (A2BufferingBloomFilter) update the filter on a hit.
+pub trait Filter {
+    /// Insert the byte encoding of an element into the filter
+    fn insert_bytes(&mut self, bytes: &[u8]);
+
+    /// Check whether the byte encoding of an element is probably in the filter or not
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool;
+
+    /// Empty out the filter
+    fn empty(&mut self);
+
+    /// Getter for hash_count
+    fn get_hash_count(&self) -> u64;
+
+    /// Getter for false_positive_rate
+    fn get_false_positive_rate(&self) -> f64;
+
+    /// Getter for expected_inserts
+    fn get_expected_inserts(&self) -> u64;
+
+    /// Getter for actual_inserts
+    fn get_actual_inserts(&self) -> u64;
+
+    /// Insert a new element into the filter
+    fn insert<T: Item + ?Sized>(&mut self, item: &T)
+    where
+        Self: Sized,
+    {
+        item.with_bytes(|bytes| self.insert_bytes(bytes));
+    }
+
+    /// Check whether an element is probably in the filter or not
+    fn check<T: Item + ?Sized>(&mut self, item: &T) -> bool
+    where
+        Self: Sized,
+    {
+        return item.with_bytes(|bytes| self.check_bytes(bytes));
+    }
+}
+
+/// A Filter which also supports deleting items that were previously inserted.
+pub trait DeletableFilter: Filter {
+    /// Delete the byte encoding of an element from the filter, returning an error instead of
+    /// panicking if the filter's counters would be corrupted
+    fn try_delete_bytes(&mut self, bytes: &[u8]) -> Result<()>;
+
+    /// Delete an element from the filter
+    fn delete<T: Item + ?Sized>(&mut self, item: &T)
+    where
+        Self: Sized,
+    {
+        match self.try_delete(item) {
+            Ok(()) => return,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Delete an element from the filter, returning an error instead of panicking if the
+    /// filter's counters would be corrupted
+    fn try_delete<T: Item + ?Sized>(&mut self, item: &T) -> Result<()>
+    where
+        Self: Sized,
+    {
+        return item.with_bytes(|bytes| self.try_delete_bytes(bytes));
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for Box<F> {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        (**self).insert_bytes(bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return (**self).check_bytes(bytes);
+    }
+
+    fn empty(&mut self) {
+        (**self).empty();
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return (**self).get_hash_count();
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return (**self).get_false_positive_rate();
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return (**self).get_expected_inserts();
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return (**self).get_actual_inserts();
+    }
+}
+
+impl<F: DeletableFilter + ?Sized> DeletableFilter for Box<F> {
+    fn try_delete_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        return (**self).try_delete_bytes(bytes);
+    }
+}
@@ -1,16 +1,34 @@
 /*!
  * generational_bloom_filter.rs
  *
- * Implements a Murmur3-based generational bloom filter:
+ * Implements a generational bloom filter:
  *      GenerationalBloomFilter  -- a generational bloom filter, which is essentially n bloom filters
  *                                  used together to provide alternating "generations". The oldest
  *                                  generation is recycled when the newest generation becomes full,
  *                                  in a pattern similar to Blue-Green deployment, but with n
- *                                  generations in use at any moment.
+ *                                  generations in use at any moment. With a generation
+ *                                  duration set, a generation is also recycled once its time
+ *                                  slice expires, so items are remembered for a time window.
  *                                  Does ~not~ support manual deletion.
  */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::persistence::{self, ChecksumReader, ChecksumWriter, FilterKind};
 use crate::bloom::BloomFilter;
+use crate::bloom::Clock;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
+use crate::bloom::HashFunction;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use crate::bloom::SystemClock;
 use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::time::Duration;
 
 /// GenerationalBloomFilter struct:
 ///    * generations:         VecDeque containing each generation's BloomFilter
@@ -19,6 +37,11 @@ use std::collections::VecDeque;
 ///    * false_positive_rate: False positive rate
 ///    * expected_inserts:    Expected inserts per generation
 ///    * actual_inserts:      Actual inserts, for the current generation
+///    * hasher:              Hash function used by every generation
+///    * indexing:            How indices are derived from the hash function
+///    * generation_duration: Time slice of a generation, if generations also expire over time
+///    * generation_start:    Time the current generation started, read from clock
+///    * clock:               Source of time for generation_duration
 pub struct GenerationalBloomFilter {
     generations: VecDeque<BloomFilter>,
     num_generations: u64,
@@ -26,6 +49,23 @@ pub struct GenerationalBloomFilter {
     false_positive_rate: f64,
     expected_inserts: u64,
     actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
+    generation_duration: Option<Duration>,
+    generation_start: Duration,
+    clock: Box<dyn Clock>,
+}
+
+/// The time window of a saved GenerationalBloomFilter:
+///    * generation_duration: Time slice of a generation, if generations also expire over time
+///    * generation_elapsed:  Time the current generation had been active for
+///    * saved_at:            Time of the save, read from the clock of the filter
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct TimeWindow {
+    generation_duration: Option<Duration>,
+    generation_elapsed: Duration,
+    saved_at: Duration,
 }
 
 /// Implementation of a generational bloom filter
@@ -55,21 +95,19 @@ impl GenerationalBloomFilter {
         return self.actual_inserts;
     }
 
-    /// Given a desired false positive rate, calculate the length of the BitVec required
-    /// See 'm' in this SO answer: https://stackoverflow.com/a/22467497
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn calculate_len(expected_inserts: f64, false_positive_rate: f64) -> u64 {
-        let two: f64 = 2.0;
-        return ((-1.0 * (expected_inserts) * false_positive_rate.ln()) / two.ln().powf(two)).ceil()
-            as u64;
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
     }
 
-    /// Calculate the number of hashes required
-    /// See 'k' in this SO answer: https://stackoverflow.com/a/22467497
-    /// k = ceil(m/n * ln(2))
-    fn calculate_hash_count(expected_inserts: f64, len: u64) -> u64 {
-        let two: f64 = 2.0;
-        return (((len as f64) / expected_inserts) * two.ln()).ceil() as u64;
+    /// Getter for generation_duration
+    pub fn get_generation_duration(&self) -> Option<Duration> {
+        return self.generation_duration;
     }
 
     /// Initialize generations VecDeque, by placing num_generations
@@ -78,84 +116,267 @@ impl GenerationalBloomFilter {
         expected_inserts: u64,
         false_positive_rate: f64,
         num_generations: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
     ) -> VecDeque<BloomFilter> {
         let mut generations: VecDeque<BloomFilter> =
             VecDeque::with_capacity(num_generations as usize);
         for _ in 0..num_generations {
-            generations.push_back(BloomFilter::new(expected_inserts, false_positive_rate));
+            generations.push_back(BloomFilter::with_hashing(
+                expected_inserts,
+                false_positive_rate,
+                hasher,
+                indexing,
+            ));
         }
         return generations;
     }
 
-    /// Create a new GenerationalBloomFilter
+    /// Create a new GenerationalBloomFilter, using the default (Murmur3) hash function
     pub fn new(
         expected_inserts: u64,
         false_positive_rate: f64,
         num_generations: u64,
     ) -> GenerationalBloomFilter {
-        if false_positive_rate <= 0.0 {
-            panic!(
-                "False positive rate must be a positive number. Currently: {}",
-                false_positive_rate
-            );
+        return GenerationalBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            num_generations,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new GenerationalBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        num_generations: u64,
+    ) -> Result<GenerationalBloomFilter> {
+        return GenerationalBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            num_generations,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new GenerationalBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        num_generations: u64,
+        hasher: HashFunction,
+    ) -> GenerationalBloomFilter {
+        return GenerationalBloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            num_generations,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new GenerationalBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        num_generations: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> GenerationalBloomFilter {
+        match GenerationalBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            num_generations,
+            hasher,
+            indexing,
+        ) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new GenerationalBloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        num_generations: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<GenerationalBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
         } else if expected_inserts < 1 {
-            panic!(
-                "Expected number of inserts must be a positive number. Currently: {}",
-                expected_inserts
-            );
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
         } else if num_generations < 2 {
-            panic!(
-                "Expected number of generations must be a positive integer >= 2. Currently: {}",
-                num_generations
-            );
+            return Err(Error::InvalidNumGenerations(num_generations));
+        }
+
+        let len: u64 = params::optimal_len(expected_inserts, false_positive_rate);
+        let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        return Ok(GenerationalBloomFilter::from_parameters(
+            &params,
+            num_generations,
+            hasher,
+            indexing,
+        ));
+    }
+
+    /// Also recycle the oldest generation whenever the current one has been active for the
+    /// given duration, even if it isn't full. Items are then remembered for between
+    /// num_generations - 1 and num_generations durations, or less if generations fill up first.
+    /// Time is read from the system clock, unless another is set with with_clock()
+    pub fn with_generation_duration(
+        self,
+        generation_duration: Duration,
+    ) -> GenerationalBloomFilter {
+        match self.try_with_generation_duration(generation_duration) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
         }
+    }
 
-        let len: u64 =
-            GenerationalBloomFilter::calculate_len(expected_inserts as f64, false_positive_rate);
-        let hash_count: u64 =
-            GenerationalBloomFilter::calculate_hash_count(expected_inserts as f64, len);
+    /// Also recycle the oldest generation whenever the current one has been active for the
+    /// given duration. Returns an error if the duration is zero
+    pub fn try_with_generation_duration(
+        mut self,
+        generation_duration: Duration,
+    ) -> Result<GenerationalBloomFilter> {
+        if generation_duration.is_zero() {
+            return Err(Error::InvalidGenerationDuration(generation_duration));
+        }
+        self.generation_duration = Some(generation_duration);
+        self.generation_start = self.clock.now();
+        return Ok(self);
+    }
+
+    /// Read time from the given clock, eg a ManualClock in tests. The current generation
+    /// starts its time slice anew: to keep the time window of a saved filter, pass the clock
+    /// to load_with_clock() instead
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> GenerationalBloomFilter {
+        self.generation_start = clock.now();
+        self.clock = Box::new(clock);
+        return self;
+    }
 
+    /// Create a new GenerationalBloomFilter from already validated parameters
+    pub(crate) fn from_parameters(
+        params: &FilterParameters,
+        num_generations: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> GenerationalBloomFilter {
+        let mut generations: VecDeque<BloomFilter> =
+            VecDeque::with_capacity(num_generations as usize);
+        for _ in 0..num_generations {
+            generations.push_back(BloomFilter::from_parameters(params, hasher, indexing));
+        }
         GenerationalBloomFilter {
-            generations: GenerationalBloomFilter::init_generations(
-                expected_inserts,
-                false_positive_rate,
-                num_generations,
-            ),
+            generations: generations,
             num_generations: num_generations,
-            hash_count: hash_count,
-            false_positive_rate: false_positive_rate,
-            expected_inserts: expected_inserts,
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
+            expected_inserts: params.get_expected_inserts(),
             actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
+            generation_duration: None,
+            generation_start: Duration::ZERO,
+            clock: Box::new(SystemClock::new()),
         }
     }
 
-    /// Recycle the oldest generation, creating a new BloomFilter
-    /// for the new active generation
+    /// Recycle the oldest generation, emptying it and reusing it
+    /// as the new active generation
     fn recycle(&mut self) {
-        self.generations.pop_front();
-        self.generations.push_back(BloomFilter::new(
-            self.expected_inserts,
-            self.false_positive_rate,
-        ));
+        if let Some(mut bf) = self.generations.pop_front() {
+            bf.empty();
+            self.generations.push_back(bf);
+        }
+    }
+
+    /// Number of generations, oldest first, whose items have expired at the given time but
+    /// which haven't been recycled yet: one per whole time slice the current generation has
+    /// been active for. Always 0 without a generation duration
+    fn expired_generations(&self, now: Duration) -> usize {
+        match self.generation_duration {
+            Some(duration) => {
+                let elapsed: Duration = now.saturating_sub(self.generation_start);
+                let slices: u128 = elapsed.as_nanos() / duration.as_nanos();
+                return slices.min(self.num_generations as u128) as usize;
+            }
+            None => return 0,
+        }
+    }
+
+    /// Number of generations whose items have expired by now. Without a generation duration
+    /// this is always 0, and the clock isn't read
+    fn expired_generations_now(&self) -> usize {
+        if self.generation_duration.is_none() {
+            return 0;
+        }
+        return self.expired_generations(self.clock.now());
+    }
+
+    /// Recycle the generations whose time slice has expired. The new current generation
+    /// starts at the end of the last expired slice. insert() calls this, and check() ignores
+    /// expired generations anyway, but calling it frees them for the statistics and estimates
+    pub fn expire(&mut self) {
+        if self.generation_duration.is_none() {
+            return;
+        }
+        let now: Duration = self.clock.now();
+        let elapsed: Duration = now.saturating_sub(self.generation_start);
+        self.expire_elapsed(now, elapsed);
+    }
+
+    /// Recycle the generations whose time slice has expired, given that the current
+    /// generation has been active for elapsed at the time now
+    fn expire_elapsed(&mut self, now: Duration, elapsed: Duration) {
+        if let Some(duration) = self.generation_duration {
+            let slices: u128 = elapsed.as_nanos() / duration.as_nanos();
+            if slices == 0 {
+                return;
+            }
+            for _ in 0..slices.min(self.num_generations as u128) {
+                self.recycle();
+            }
+            self.actual_inserts = 0;
+            let into_slice: u128 = elapsed.as_nanos() % duration.as_nanos();
+            self.generation_start = now.saturating_sub(Duration::from_nanos(into_slice as u64));
+        }
     }
 
     /// Insert a new element into the current generation BloomFilter
-    pub fn insert(&mut self, item: &str) {
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        self.expire();
         if self.actual_inserts + 1 > self.expected_inserts {
             // Recycle the oldest generation
             self.recycle();
             self.actual_inserts = 0;
+            if self.generation_duration.is_some() {
+                self.generation_start = self.clock.now();
+            }
         }
-        match self.generations.front_mut() {
+        match self.generations.back_mut() {
             Some(bf) => bf.insert(item), // The compiler will do the dereference for us
             None => panic!("VecDeque 'generations' is uninitialized!"),
         }
         self.actual_inserts += 1;
     }
 
-    /// Check whether an element matches any generation of the BloomFilter
-    pub fn check(&self, item: &str) -> bool {
-        for bf in self.generations.iter() {
+    /// Check whether an element matches any generation of the BloomFilter, leaving out the
+    /// generations which have expired
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        let expired: usize = self.expired_generations_now();
+        for bf in self.generations.iter().skip(expired) {
             if bf.check(item) {
                 return true;
             }
@@ -163,25 +384,264 @@ impl GenerationalBloomFilter {
         return false;
     }
 
-    /// Check whether an element matches the current generation of the BloomFilter
-    pub fn check_current(&self, item: &str) -> bool {
+    /// Check whether an element matches the current generation of the BloomFilter, unless it
+    /// has expired
+    pub fn check_current<T: Item + ?Sized>(&self, item: &T) -> bool {
+        if self.expired_generations_now() > 0 {
+            return false;
+        }
         match self.generations.back() {
             Some(bf) => bf.check(item),
             None => panic!("VecDeque 'generations' is uninitialized!"),
         }
     }
 
+    /// Estimate the number of distinct items in each generation, oldest first: the last
+    /// estimate is for the active generation
+    pub fn estimate_generation_cardinalities(&self) -> Vec<f64> {
+        return self
+            .generations
+            .iter()
+            .map(|bf| bf.estimate_cardinality())
+            .collect();
+    }
+
+    /// Estimate the number of distinct items in the whole window. Items inserted into more
+    /// than one generation are only counted once
+    pub fn estimate_cardinality(&self) -> f64 {
+        let generations: Vec<&BloomFilter> = self.generations.iter().collect();
+        return BloomFilter::estimate_combined_cardinality(&generations);
+    }
+
+    /// Report the fill statistics of each generation, oldest first: the last report is for
+    /// the active generation
+    pub fn generation_fill_statistics(&self) -> Vec<FillStatistics> {
+        return self
+            .generations
+            .iter()
+            .map(|bf| bf.fill_statistics())
+            .collect();
+    }
+
+    /// Report the fill statistics of the whole window. The false positive rate is the
+    /// probability that an item is a false positive in any generation
+    pub fn fill_statistics(&self) -> FillStatistics {
+        return FillStatistics::combine(&self.generation_fill_statistics());
+    }
+
     /// Empty out the entire data structure (ie all generations)
     pub fn empty(&mut self) {
         for g in self.generations.iter_mut() {
             g.empty();
         }
+        self.actual_inserts = 0;
+        self.generation_start = self.clock.now();
+    }
+
+    /// Save the GenerationalBloomFilter to a writer, in the crate's versioned binary format.
+    /// The generations are written oldest first, so the active generation is the last one.
+    /// The time window is saved too: the generation duration, how long the current generation
+    /// has been active, and the time of the save. The clock itself is not saved
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut writer = ChecksumWriter::new(writer, FilterKind::Generational)?;
+        writer.write_u64(self.num_generations)?;
+        writer.write_u64(self.actual_inserts)?;
+        match self.generation_duration {
+            Some(duration) => {
+                let now: Duration = self.clock.now();
+                writer.write_u8(1)?;
+                writer.write_duration(duration)?;
+                writer.write_duration(now.saturating_sub(self.generation_start))?;
+                writer.write_duration(now)?;
+            }
+            None => writer.write_u8(0)?,
+        }
+        for bf in self.generations.iter() {
+            bf.write_body(&mut writer)?;
+        }
+        return writer.finish();
+    }
+
+    /// Load a GenerationalBloomFilter saved with save(), restoring the order of the
+    /// generations and how full the active generation is. Time is read from a SystemClock
+    pub fn load<R: Read>(reader: &mut R) -> Result<GenerationalBloomFilter> {
+        return GenerationalBloomFilter::load_with_clock(reader, SystemClock::new());
+    }
+
+    /// Load a GenerationalBloomFilter saved with save(), reading time from the given clock.
+    /// The time since the save counts towards the current generation, so generations which
+    /// expired in the meantime are recycled right away. If the clock reads earlier than the
+    /// save, as a different clock may, the current generation resumes where it was saved
+    pub fn load_with_clock<R: Read, C: Clock + 'static>(
+        reader: &mut R,
+        clock: C,
+    ) -> Result<GenerationalBloomFilter> {
+        let mut reader = ChecksumReader::new(reader, FilterKind::Generational)?;
+        let num_generations: u64 = reader.read_u64()?;
+        let actual_inserts: u64 = reader.read_u64()?;
+        if num_generations < 2 {
+            // Checked before reading, so a corrupt count can't cause a huge allocation
+            return Err(Error::InvalidNumGenerations(num_generations));
+        }
+        // Version 1 files have no time window
+        let mut window: TimeWindow = TimeWindow::default();
+        if reader.get_version() >= 2 && reader.read_u8()? != 0 {
+            window.generation_duration = Some(reader.read_duration()?);
+            window.generation_elapsed = reader.read_duration()?;
+            window.saved_at = reader.read_duration()?;
+        }
+        let generations: Vec<BloomFilter> =
+            persistence::read_generations(&mut reader, num_generations)?;
+        reader.finish()?;
+        return GenerationalBloomFilter::from_generations(actual_inserts, generations)?
+            .resume(window, Box::new(clock));
+    }
+
+    /// Restore the time window of a saved filter, reading time from the given clock from now
+    /// on, and recycle the generations which expired since it was saved
+    fn resume(
+        mut self,
+        window: TimeWindow,
+        clock: Box<dyn Clock>,
+    ) -> Result<GenerationalBloomFilter> {
+        self.clock = clock;
+        let now: Duration = self.clock.now();
+        self.generation_start = now;
+        if let Some(duration) = window.generation_duration {
+            if duration.is_zero() {
+                return Err(Error::InvalidGenerationDuration(duration));
+            }
+            self.generation_duration = Some(duration);
+            let elapsed: Duration = window
+                .generation_elapsed
+                .saturating_add(now.saturating_sub(window.saved_at));
+            self.generation_start = now.saturating_sub(elapsed);
+            self.expire_elapsed(now, elapsed);
+        }
+        return Ok(self);
+    }
+
+    /// Restore a GenerationalBloomFilter from its stored generations (oldest first) and the
+    /// insert count of the active generation, checking that they are consistent
+    pub(crate) fn from_generations(
+        actual_inserts: u64,
+        generations: Vec<BloomFilter>,
+    ) -> Result<GenerationalBloomFilter> {
+        let num_generations: u64 = generations.len() as u64;
+        if num_generations < 2 {
+            return Err(Error::InvalidNumGenerations(num_generations));
+        }
+        let first: &BloomFilter = &generations[0];
+        if generations.iter().any(|bf| !first.has_same_parameters(bf)) {
+            return Err(Error::Corrupt("generations have different parameters"));
+        } else if actual_inserts > first.get_expected_inserts() {
+            return Err(Error::Corrupt("actual inserts exceed expected inserts"));
+        }
+        Ok(GenerationalBloomFilter {
+            num_generations: num_generations,
+            hash_count: first.get_hash_count(),
+            false_positive_rate: first.get_false_positive_rate(),
+            expected_inserts: first.get_expected_inserts(),
+            actual_inserts: actual_inserts,
+            hasher: first.get_hasher(),
+            indexing: first.get_indexing(),
+            generations: generations.into_iter().collect(),
+            generation_duration: None,
+            generation_start: Duration::ZERO,
+            clock: Box::new(SystemClock::new()),
+        })
+    }
+}
+
+impl Filter for GenerationalBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        GenerationalBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return GenerationalBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        GenerationalBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return GenerationalBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return GenerationalBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return GenerationalBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return GenerationalBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use serde::de;
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialized form of a GenerationalBloomFilter, validated before it becomes one.
+    /// The generations are stored oldest first, so the active generation is the last one
+    #[derive(Deserialize)]
+    #[serde(rename = "GenerationalBloomFilter", deny_unknown_fields)]
+    struct GenerationalBloomFilterRepr {
+        actual_inserts: u64,
+        generations: Vec<BloomFilter>,
+        #[serde(default)]
+        time_window: TimeWindow,
+    }
+
+    impl Serialize for GenerationalBloomFilter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            let now: Duration = self.clock.now();
+            let mut state = serializer.serialize_struct("GenerationalBloomFilter", 3)?;
+            state.serialize_field("actual_inserts", &self.actual_inserts)?;
+            state.serialize_field("generations", &self.generations)?;
+            state.serialize_field(
+                "time_window",
+                &TimeWindow {
+                    generation_duration: self.generation_duration,
+                    generation_elapsed: now.saturating_sub(self.generation_start),
+                    saved_at: now,
+                },
+            )?;
+            return state.end();
+        }
+    }
+
+    impl<'de> Deserialize<'de> for GenerationalBloomFilter {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<GenerationalBloomFilter, D::Error> {
+            let repr: GenerationalBloomFilterRepr =
+                GenerationalBloomFilterRepr::deserialize(deserializer)?;
+            let time_window: TimeWindow = repr.time_window;
+            return GenerationalBloomFilter::from_generations(
+                repr.actual_inserts,
+                repr.generations,
+            )
+            .and_then(|bf| bf.resume(time_window, Box::new(SystemClock::new())))
+            .map_err(de::Error::custom);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bloom::ManualClock;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
 
     #[test]
     /// Test that we can create a new GenerationalBloomFilter using GenerationalBloomFilter::new()
@@ -201,11 +661,11 @@ mod tests {
         let s2 = "This is another string"; // Inserted
         let s3 = "This is a third string"; // Not inserted
         let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(2, 0.05, 3);
-        bf.insert(&s);
-        bf.insert(&s2);
-        assert!(bf.check(&s)); // Included
-        assert!(bf.check(&s2)); // Included
-        assert!(!bf.check(&s3)); // Not included
+        bf.insert(s);
+        bf.insert(s2);
+        assert!(bf.check(s)); // Included
+        assert!(bf.check(s2)); // Included
+        assert!(!bf.check(s3)); // Not included
     }
 
     #[test]
@@ -239,37 +699,182 @@ mod tests {
     }
 
     #[test]
-    /// Test that we get the expected value for calculating the length of the
-    /// BitVec using the computation:
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn test_calculate_len() {
-        let m: u64 = GenerationalBloomFilter::calculate_len(100 as f64, 0.01);
-        assert_eq!(m, 959);
-        let m2: u64 = GenerationalBloomFilter::calculate_len(1000 as f64, 0.001);
-        assert_eq!(m2, 14378);
-        let m3: u64 = GenerationalBloomFilter::calculate_len(5 as f64, 0.1);
-        assert_eq!(m3, 24);
-        let m4: u64 = GenerationalBloomFilter::calculate_len(10000 as f64, 0.01);
-        assert_eq!(m4, 95851);
-        let m5: u64 = GenerationalBloomFilter::calculate_len(216553 as f64, 0.01);
-        assert_eq!(m5, 2075674);
+    /// Test that inserts go into the current generation, and survive until their generation
+    /// is the oldest one recycled
+    fn test_rotation() {
+        let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(10, 0.01, 3);
+        for i in 0..10 {
+            bf.insert(&i);
+            assert!(bf.check_current(&i));
+        }
+        // Fill two more generations: the first one is still in the window
+        for i in 10..30 {
+            bf.insert(&i);
+        }
+        assert!(!bf.check_current(&0));
+        assert!((0..30).all(|i| bf.check(&i)));
+        // The next insert recycles the generation holding 0..10
+        bf.insert(&30);
+        assert_eq!(bf.get_actual_inserts(), 1);
+        assert!((10..31).all(|i| bf.check(&i)));
+        assert!((0..10).filter(|i| bf.check(i)).count() < 3);
+    }
+
+    #[test]
+    /// Test that a generation is recycled when its time slice expires, even if it isn't full,
+    /// and that check() leaves out expired generations before the next insert
+    fn test_time_rotation() {
+        let clock: ManualClock = ManualClock::new();
+        let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(100, 0.01, 3)
+            .with_clock(clock.clone())
+            .with_generation_duration(Duration::from_secs(60));
+        assert_eq!(bf.get_generation_duration(), Some(Duration::from_secs(60)));
+        for i in 0..5 {
+            bf.insert(&i);
+        }
+        clock.advance(Duration::from_secs(59));
+        assert!(bf.check_current(&0));
+        clock.advance(Duration::from_secs(1));
+        assert!(!bf.check_current(&0));
+        assert!(bf.check(&0));
+        // The insert recycles the oldest generation, and starts a new one at 60s
+        for i in 5..10 {
+            bf.insert(&i);
+        }
+        assert_eq!(bf.get_actual_inserts(), 5);
+        assert!(bf.check_current(&5));
+        assert!(bf.generation_fill_statistics()[1].get_bits_set() > 0);
+        // Two more slices later, only the generation started at 60s is left
+        clock.advance(Duration::from_secs(150));
+        assert!(!(0..5).any(|i| bf.check(&i)));
+        assert!((5..10).all(|i| bf.check(&i)));
+        // And after three, nothing is
+        clock.advance(Duration::from_secs(30));
+        assert!(!(0..10).any(|i| bf.check(&i)));
+    }
+
+    #[test]
+    /// Test that expire() recycles at most every generation after a long idle period, and
+    /// keeps the time slices aligned
+    fn test_expire() {
+        let clock: ManualClock = ManualClock::new();
+        let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(100, 0.01, 3)
+            .with_clock(clock.clone())
+            .with_generation_duration(Duration::from_secs(60));
+        for i in 0..30 {
+            bf.insert(&i);
+        }
+        clock.advance(Duration::from_secs(3600 * 24 * 365 * 100 + 30));
+        bf.expire();
+        assert_eq!(bf.get_actual_inserts(), 0);
+        assert!(bf
+            .generation_fill_statistics()
+            .iter()
+            .all(|s| s.get_bits_set() == 0));
+        bf.insert(&30);
+        // The current slice started 30s ago, so it expires in 30s
+        clock.advance(Duration::from_secs(29));
+        assert!(bf.check_current(&30));
+        clock.advance(Duration::from_secs(1));
+        assert!(!bf.check_current(&30));
+        assert!(bf.check(&30));
+    }
+
+    #[test]
+    /// Test that generations are still recycled when full, and that a full generation starts
+    /// a new time slice
+    fn test_time_and_count_rotation() {
+        let clock: ManualClock = ManualClock::new();
+        let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(10, 0.01, 2)
+            .with_clock(clock.clone())
+            .with_generation_duration(Duration::from_secs(60));
+        clock.advance(Duration::from_secs(50));
+        for i in 0..11 {
+            bf.insert(&i);
+        }
+        assert_eq!(bf.get_actual_inserts(), 1);
+        // The generation holding 10 started at 50s, when the previous one filled up
+        clock.advance(Duration::from_secs(59));
+        assert!(bf.check_current(&10));
+        clock.advance(Duration::from_secs(1));
+        assert!(!bf.check_current(&10));
+    }
+
+    #[test]
+    /// Test that a zero generation duration is rejected
+    fn test_invalid_generation_duration() {
+        let bf: GenerationalBloomFilter = GenerationalBloomFilter::new(10, 0.01, 2);
+        assert_eq!(bf.get_generation_duration(), None);
+        assert_eq!(
+            bf.try_with_generation_duration(Duration::ZERO).err(),
+            Some(Error::InvalidGenerationDuration(Duration::ZERO))
+        );
+    }
+
+    #[test]
+    /// Test that a file written in version 1 of the format, without a time window, still
+    /// loads, and that a saved zero generation duration is rejected
+    fn test_load_version_1() {
+        let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(10, 0.01, 2);
+        bf.insert("a");
+        let mut buf: Vec<u8> = Vec::new();
+        bf.save(&mut buf).unwrap();
+        // Drop the time window flag after the header and the two counts, and re-checksum
+        let mut v1: Vec<u8> = buf[..buf.len() - 4].to_vec();
+        v1[4] = 1;
+        v1.remove(7 + 16);
+        v1.extend_from_slice(&crc32fast::hash(&v1).to_le_bytes());
+        let loaded: GenerationalBloomFilter = GenerationalBloomFilter::load(&mut &v1[..]).unwrap();
+        assert_eq!(loaded.get_generation_duration(), None);
+        assert_eq!(loaded.get_actual_inserts(), 1);
+        assert!(loaded.check("a"));
+
+        let clock: ManualClock = ManualClock::new();
+        let window: TimeWindow = TimeWindow {
+            generation_duration: Some(Duration::ZERO),
+            generation_elapsed: Duration::ZERO,
+            saved_at: Duration::ZERO,
+        };
+        assert_eq!(
+            bf.resume(window, Box::new(clock)).err(),
+            Some(Error::InvalidGenerationDuration(Duration::ZERO))
+        );
+    }
+
+    #[test]
+    /// Test that the window estimate counts items in every generation once
+    fn test_estimate_cardinality() {
+        let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(500, 0.01, 3);
+        for i in 0..1200 {
+            bf.insert(&i);
+        }
+        let per_generation: Vec<f64> = bf.estimate_generation_cardinalities();
+        assert_eq!(per_generation.len(), 3);
+        assert!((per_generation[0] - 500.0).abs() < 25.0);
+        assert!((per_generation[2] - 200.0).abs() < 10.0);
+        let estimate: f64 = bf.estimate_cardinality();
+        assert!((estimate - 1200.0).abs() < 60.0, "{}", estimate);
     }
 
     #[test]
-    /// Test that we get the expected value for calculating the number of iterations
-    /// if hashing we need to perform
-    /// k = ceil(m/n * ln(2))
-    fn test_calculate_hash_count() {
-        let k: u64 = GenerationalBloomFilter::calculate_hash_count(100 as f64, 959);
-        assert_eq!(k, 7);
-        let k2: u64 = GenerationalBloomFilter::calculate_hash_count(1000 as f64, 14378);
-        assert_eq!(k2, 10);
-        let k3: u64 = GenerationalBloomFilter::calculate_hash_count(5 as f64, 24);
-        assert_eq!(k3, 4);
-        let k4: u64 = GenerationalBloomFilter::calculate_hash_count(10000 as f64, 95851);
-        assert_eq!(k4, 7);
-        let k5: u64 = GenerationalBloomFilter::calculate_hash_count(216553 as f64, 2075674);
-        assert_eq!(k5, 7);
+    /// Test that the window reports one set of statistics per generation, and combines them
+    fn test_fill_statistics() {
+        let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(500, 0.01, 3);
+        for i in 0..1000 {
+            bf.insert(&i);
+        }
+        let per_generation: Vec<FillStatistics> = bf.generation_fill_statistics();
+        assert_eq!(per_generation.len(), 3);
+        // The oldest generation has not been used yet
+        assert_eq!(per_generation[0].get_bits_set(), 0);
+        let combined: FillStatistics = bf.fill_statistics();
+        assert_eq!(combined.get_len(), 3 * per_generation[0].get_len());
+        assert_eq!(
+            combined.get_bits_set(),
+            per_generation[1].get_bits_set() + per_generation[2].get_bits_set()
+        );
+        assert!(combined.get_false_positive_rate() > per_generation[1].get_false_positive_rate());
+        assert!(combined.get_false_positive_rate() < 0.03);
     }
 
     #[test]
@@ -304,4 +909,34 @@ mod tests {
         #[allow(unused_variables)]
         let bf: GenerationalBloomFilter = GenerationalBloomFilter::new(1, 0.05, 1);
     }
+
+    /// A Clock counting how many times it is read
+    struct CountingClock {
+        reads: Arc<AtomicU64>,
+    }
+
+    impl Clock for CountingClock {
+        fn now(&self) -> Duration {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            return Duration::ZERO;
+        }
+    }
+
+    #[test]
+    /// Test that inserts and checks don't read the clock without a generation duration
+    fn test_count_only_skips_clock() {
+        let reads: Arc<AtomicU64> = Arc::new(AtomicU64::new(0));
+        let mut bf: GenerationalBloomFilter =
+            GenerationalBloomFilter::new(10, 0.01, 3).with_clock(CountingClock {
+                reads: reads.clone(),
+            });
+        let before: u64 = reads.load(Ordering::Relaxed);
+        for i in 0..50 {
+            bf.insert(&i);
+            assert!(bf.check(&i));
+            assert!(bf.check_current(&i));
+        }
+        bf.expire();
+        assert_eq!(reads.load(Ordering::Relaxed), before);
+    }
 }
@@ -8,6 +8,7 @@
 /// Create an enum to control which generation of bloom filter
 /// is the active or current generation.
 #[derive(PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GenerationValues {
     A,
     B,
@@ -0,0 +1,359 @@
+/*!
+ * hashing.rs
+ *
+ * Implements the hash functions used to derive bloom filter indices:
+ *      BloomHasher   -- a trait for seeded 128-bit hash functions over an item's bytes.
+ *      Murmur3Hasher -- MurmurHash3 (x64, 128-bit), bit-compatible with the indices computed
+ *                       by earlier versions of this crate. The default.
+ *      Xxh3Hasher    -- XXH3 (128-bit).
+ *      SipHasher     -- keyed SipHash-2-4 (128-bit), for inputs that may be adversarial.
+ *      HashFunction  -- an enum over the hashers above, which is what each filter stores.
+ *      IndexingScheme -- how the hash_count indices of an item are derived from the hasher:
+ *                        either one seeded digest per index (PerSeed, the scheme used by
+ *                        earlier versions of this crate), or Kirsch-Mitzenmacher enhanced double
+ *                        hashing over the two halves of a single digest (DoubleHashing).
+ *
+ * All of the implementations are pure Rust and read their input little-endian, so the same
+ * item produces the same digest on every platform.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use siphasher::sip128::{Hasher128, SipHasher24};
+use std::hash::Hasher;
+use xxhash_rust::xxh3;
+
+/// A seeded 128-bit hash function over an item's byte encoding.
+pub trait BloomHasher {
+    /// Compute the 128-bit digest of bytes, using the given seed
+    fn hash128(&self, bytes: &[u8], seed: u32) -> u128;
+}
+
+/// MurmurHash3, x64 128-bit variant
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Murmur3Hasher;
+
+/// XXH3, 128-bit variant
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Xxh3Hasher;
+
+/// SipHash-2-4, 128-bit variant, keyed by two u64 keys
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SipHasher {
+    k0: u64,
+    k1: u64,
+}
+
+/// The hash function used by a filter
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashFunction {
+    Murmur3,
+    Xxh3,
+    SipHash(SipHasher),
+}
+
+/// How the indices of an item are derived from its digest(s)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IndexingScheme {
+    /// Compute one digest per index, seeded with the index number, and keep its low 64 bits
+    PerSeed,
+    /// Compute a single digest, and combine its halves h1 and h2 as
+    /// g_i = h1 + i*h2 + (i^3 - i)/6 (enhanced double hashing)
+    DoubleHashing,
+}
+
+/// Iterator over the indices of an item, for a filter of a given len and hash_count
+pub(crate) struct HashIndices<'a> {
+    hasher: &'a HashFunction,
+    indexing: IndexingScheme,
+    bytes: &'a [u8],
+    len: u64,
+    hash_count: u64,
+    i: u64,
+    x: u64,
+    y: u64,
+}
+
+/// Final avalanche mix of a 64-bit MurmurHash3 lane
+pub(crate) fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^= k >> 33;
+    return k;
+}
+
+/// Read up to 8 bytes as a little-endian u64
+fn read_u64_le(bytes: &[u8]) -> u64 {
+    let mut val: u64 = 0;
+    for (i, b) in bytes.iter().enumerate() {
+        val |= (*b as u64) << (8 * i);
+    }
+    return val;
+}
+
+impl BloomHasher for Murmur3Hasher {
+    /// MurmurHash3_x64_128, returning h1 in the low 64 bits and h2 in the high 64 bits
+    fn hash128(&self, bytes: &[u8], seed: u32) -> u128 {
+        const C1: u64 = 0x87c3_7b91_1142_53d5;
+        const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+        let mut h1: u64 = seed as u64;
+        let mut h2: u64 = seed as u64;
+
+        let mut blocks = bytes.chunks_exact(16);
+        for block in &mut blocks {
+            let mut k1: u64 = read_u64_le(&block[..8]);
+            let mut k2: u64 = read_u64_le(&block[8..]);
+
+            k1 = k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+            h1 ^= k1;
+            h1 = h1.rotate_left(27).wrapping_add(h2);
+            h1 = h1.wrapping_mul(5).wrapping_add(0x52dc_e729);
+
+            k2 = k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+            h2 ^= k2;
+            h2 = h2.rotate_left(31).wrapping_add(h1);
+            h2 = h2.wrapping_mul(5).wrapping_add(0x3849_5ab5);
+        }
+
+        let tail: &[u8] = blocks.remainder();
+        if tail.len() > 8 {
+            let k2: u64 = read_u64_le(&tail[8..]);
+            h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        }
+        if !tail.is_empty() {
+            let k1: u64 = read_u64_le(&tail[..tail.len().min(8)]);
+            h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        }
+
+        h1 ^= bytes.len() as u64;
+        h2 ^= bytes.len() as u64;
+        h1 = h1.wrapping_add(h2);
+        h2 = h2.wrapping_add(h1);
+        h1 = fmix64(h1);
+        h2 = fmix64(h2);
+        h1 = h1.wrapping_add(h2);
+        h2 = h2.wrapping_add(h1);
+
+        return ((h2 as u128) << 64) | (h1 as u128);
+    }
+}
+
+impl BloomHasher for Xxh3Hasher {
+    fn hash128(&self, bytes: &[u8], seed: u32) -> u128 {
+        return xxh3::xxh3_128_with_seed(bytes, seed as u64);
+    }
+}
+
+impl SipHasher {
+    /// Create a new SipHasher from its two keys
+    pub fn new(k0: u64, k1: u64) -> SipHasher {
+        SipHasher { k0: k0, k1: k1 }
+    }
+
+    /// Getter for the keys (k0, k1)
+    pub fn get_keys(&self) -> (u64, u64) {
+        return (self.k0, self.k1);
+    }
+}
+
+impl BloomHasher for SipHasher {
+    /// The seed is hashed (little-endian) ahead of the item's bytes
+    fn hash128(&self, bytes: &[u8], seed: u32) -> u128 {
+        let mut hasher = SipHasher24::new_with_keys(self.k0, self.k1);
+        hasher.write(&seed.to_le_bytes());
+        hasher.write(bytes);
+        return hasher.finish128().as_u128();
+    }
+}
+
+impl Default for HashFunction {
+    /// Murmur3 is the default, matching the indices computed by earlier versions of this crate
+    fn default() -> HashFunction {
+        HashFunction::Murmur3
+    }
+}
+
+impl BloomHasher for HashFunction {
+    fn hash128(&self, bytes: &[u8], seed: u32) -> u128 {
+        match self {
+            HashFunction::Murmur3 => Murmur3Hasher.hash128(bytes, seed),
+            HashFunction::Xxh3 => Xxh3Hasher.hash128(bytes, seed),
+            HashFunction::SipHash(hasher) => hasher.hash128(bytes, seed),
+        }
+    }
+}
+
+impl Default for IndexingScheme {
+    /// DoubleHashing is the default, since it only needs a single digest per item
+    fn default() -> IndexingScheme {
+        return IndexingScheme::DoubleHashing;
+    }
+}
+
+impl<'a> HashIndices<'a> {
+    /// Create the index iterator for an item's bytes
+    pub(crate) fn new(
+        hasher: &'a HashFunction,
+        indexing: IndexingScheme,
+        bytes: &'a [u8],
+        hash_count: u64,
+        len: u64,
+    ) -> HashIndices<'a> {
+        let (x, y): (u64, u64) = match indexing {
+            IndexingScheme::PerSeed => (0, 0),
+            IndexingScheme::DoubleHashing => {
+                let digest_val: u128 = hasher.hash128(bytes, 0);
+                (digest_val as u64, (digest_val >> 64) as u64)
+            }
+        };
+        HashIndices {
+            hasher: hasher,
+            indexing: indexing,
+            bytes: bytes,
+            len: len,
+            hash_count: hash_count,
+            i: 0,
+            x: x,
+            y: y,
+        }
+    }
+}
+
+impl Iterator for HashIndices<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.i >= self.hash_count {
+            return None;
+        }
+        let index: u64 = match self.indexing {
+            IndexingScheme::PerSeed => {
+                let digest_val: u128 = self.hasher.hash128(self.bytes, self.i as u32);
+                digest_val as u64 % self.len // Mod the len of the filter
+            }
+            IndexingScheme::DoubleHashing => {
+                // Incremental form of g_i = h1 + i*h2 + (i^3 - i)/6
+                let index: u64 = self.x % self.len;
+                self.x = self.x.wrapping_add(self.y);
+                self.y = self.y.wrapping_add(self.i + 1);
+                index
+            }
+        };
+        self.i += 1;
+        return Some(index as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test Murmur3Hasher against the reference MurmurHash3_x64_128 output
+    fn test_murmur3_reference_values() {
+        assert_eq!(
+            Murmur3Hasher.hash128(b"hello", 0),
+            121118445609844952839898260755277781762
+        );
+        assert_eq!(
+            Murmur3Hasher.hash128(b"hello", 123),
+            19243349499071459060235768594146641163
+        );
+        assert_eq!(
+            Murmur3Hasher.hash128(b"helloworld", 0),
+            216280293825344914020777844322685271162
+        );
+        assert_eq!(Murmur3Hasher.hash128(b"", 0), 0);
+    }
+
+    #[test]
+    /// Test that the low 64 bits of the Murmur3 digest give the same index as earlier versions
+    fn test_murmur3_index_compatibility() {
+        let digest_val: u128 = Murmur3Hasher.hash128(b"This is a test string", 32);
+        assert_eq!(digest_val as u64 % 10, 4);
+    }
+
+    #[test]
+    /// Test that PerSeed indexing reproduces the per-seed indices of earlier versions
+    fn test_per_seed_indices() {
+        let hasher = HashFunction::Murmur3;
+        let bytes: &[u8] = b"This is a test string";
+        let indices: Vec<usize> =
+            HashIndices::new(&hasher, IndexingScheme::PerSeed, bytes, 33, 10).collect();
+        assert_eq!(indices.len(), 33);
+        assert_eq!(indices[32], 4);
+        for (i, index) in indices.iter().enumerate() {
+            assert_eq!(*index as u64, hasher.hash128(bytes, i as u32) as u64 % 10);
+        }
+    }
+
+    #[test]
+    /// Test that DoubleHashing indexing follows g_i = h1 + i*h2 + (i^3 - i)/6
+    fn test_double_hashing_indices() {
+        let hasher = HashFunction::Murmur3;
+        let bytes: &[u8] = b"This is a test string";
+        let digest_val: u128 = hasher.hash128(bytes, 0);
+        let h1: u64 = digest_val as u64;
+        let h2: u64 = (digest_val >> 64) as u64;
+        let len: u64 = 1_000_003;
+        let indices: Vec<usize> =
+            HashIndices::new(&hasher, IndexingScheme::DoubleHashing, bytes, 20, len).collect();
+        assert_eq!(indices.len(), 20);
+        for (i, index) in indices.iter().enumerate() {
+            let i = i as u64;
+            let g: u64 = h1
+                .wrapping_add(i.wrapping_mul(h2))
+                .wrapping_add((i * i * i - i) / 6);
+            assert_eq!(*index as u64, g % len);
+        }
+    }
+
+    #[test]
+    /// Test that DoubleHashing is the default indexing scheme
+    fn test_default_indexing() {
+        assert_eq!(IndexingScheme::default(), IndexingScheme::DoubleHashing);
+    }
+
+    #[test]
+    /// Test that every hash function is deterministic and depends on the seed
+    fn test_seeded() {
+        let hashers = [
+            HashFunction::Murmur3,
+            HashFunction::Xxh3,
+            HashFunction::SipHash(SipHasher::new(1, 2)),
+        ];
+        for hasher in hashers.iter() {
+            assert_eq!(hasher.hash128(b"abc", 7), hasher.hash128(b"abc", 7));
+            assert_ne!(hasher.hash128(b"abc", 7), hasher.hash128(b"abc", 8));
+            assert_ne!(hasher.hash128(b"abc", 7), hasher.hash128(b"abd", 7));
+        }
+    }
+
+    #[test]
+    /// Test that the SipHash digest depends on the keys
+    fn test_siphash_keys() {
+        let a = SipHasher::new(1, 2);
+        let b = SipHasher::new(2, 1);
+        assert_eq!(a.get_keys(), (1, 2));
+        assert_ne!(a.hash128(b"abc", 0), b.hash128(b"abc", 0));
+    }
+
+    #[test]
+    /// Test that HashFunction defaults to Murmur3 and dispatches to the right hasher
+    fn test_hash_function() {
+        assert_eq!(HashFunction::default(), HashFunction::Murmur3);
+        assert_eq!(
+            HashFunction::Murmur3.hash128(b"abc", 1),
+            Murmur3Hasher.hash128(b"abc", 1)
+        );
+        assert_eq!(
+            HashFunction::Xxh3.hash128(b"abc", 1),
+            Xxh3Hasher.hash128(b"abc", 1)
+        );
+    }
+}
@@ -0,0 +1,170 @@
+/*!
+ * item.rs
+ *
+ * Defines the Item trait, which gives every type that can be inserted into a bloom filter a
+ * stable byte encoding:
+ *      Item -- implemented for str, String, byte slices/arrays/vecs, bool, char, all of the
+ *              integer types, references to items, and tuples of up to four items.
+ *
+ * The encoding is what gets hashed, so it is deliberately independent of std::hash::Hash,
+ * the platform and the Rust version:
+ *      * strings and byte buffers are hashed as their raw bytes
+ *      * integers and chars are encoded little-endian; usize/isize are widened to 64 bits
+ *      * bools are a single 0/1 byte
+ *      * tuples are the concatenation of each component's encoding, each prefixed by its
+ *        length as a little-endian u64
+ */
+#![allow(clippy::needless_return)]
+
+/// A value with a stable byte encoding, which can be inserted into or checked against a filter.
+pub trait Item {
+    /// Call `f` with the stable byte encoding of this item
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R;
+}
+
+impl Item for str {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(self.as_bytes());
+    }
+}
+
+impl Item for String {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(self.as_bytes());
+    }
+}
+
+impl Item for [u8] {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(self);
+    }
+}
+
+impl Item for Vec<u8> {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(self);
+    }
+}
+
+impl<const N: usize> Item for [u8; N] {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(self);
+    }
+}
+
+impl Item for bool {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(&[*self as u8]);
+    }
+}
+
+impl Item for char {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(&(*self as u32).to_le_bytes());
+    }
+}
+
+impl Item for usize {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(&(*self as u64).to_le_bytes());
+    }
+}
+
+impl Item for isize {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return f(&(*self as i64).to_le_bytes());
+    }
+}
+
+impl<T: Item + ?Sized> Item for &T {
+    fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        return (**self).with_bytes(f);
+    }
+}
+
+/// Implement Item for fixed-width integer types, using their little-endian encoding
+macro_rules! impl_item_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl Item for $t {
+                fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+                    return f(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_item_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Append the length-prefixed encoding of a tuple component to buf
+fn encode_component<T: Item + ?Sized>(buf: &mut Vec<u8>, item: &T) {
+    item.with_bytes(|bytes| {
+        buf.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        buf.extend_from_slice(bytes);
+    });
+}
+
+/// Implement Item for tuples, by concatenating the length-prefixed encoding of each component
+macro_rules! impl_item_for_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: Item),*> Item for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+                let ($($name,)*) = self;
+                let mut buf: Vec<u8> = Vec::new();
+                $(encode_component(&mut buf, $name);)*
+                return f(&buf);
+            }
+        }
+    };
+}
+
+impl_item_for_tuple!(A, B);
+impl_item_for_tuple!(A, B, C);
+impl_item_for_tuple!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collect the encoding of an item into a Vec
+    fn encode<T: Item + ?Sized>(item: &T) -> Vec<u8> {
+        return item.with_bytes(|bytes| bytes.to_vec());
+    }
+
+    #[test]
+    /// Test that strings and byte buffers are encoded as their raw bytes
+    fn test_encode_bytes() {
+        assert_eq!(encode("abc"), b"abc".to_vec());
+        assert_eq!(encode(&"abc".to_string()), b"abc".to_vec());
+        assert_eq!(encode(&b"abc"[..]), b"abc".to_vec());
+        assert_eq!(encode(b"abc"), b"abc".to_vec());
+        assert_eq!(encode(&vec![97u8, 98, 99]), b"abc".to_vec());
+        assert_eq!(encode(&&"abc"), b"abc".to_vec());
+    }
+
+    #[test]
+    /// Test that integers are encoded little-endian, with usize/isize widened to 64 bits
+    fn test_encode_integers() {
+        assert_eq!(encode(&1u8), vec![1]);
+        assert_eq!(encode(&0x0102u16), vec![2, 1]);
+        assert_eq!(encode(&-1i32), vec![255, 255, 255, 255]);
+        assert_eq!(encode(&7usize), encode(&7u64));
+        assert_eq!(encode(&-7isize), encode(&-7i64));
+        assert_eq!(encode(&true), vec![1]);
+        assert_eq!(encode(&'a'), encode(&97u32));
+    }
+
+    #[test]
+    /// Test that tuple components are length-prefixed, so that component boundaries matter
+    fn test_encode_tuples() {
+        assert_ne!(encode(&("ab", "c")), encode(&("a", "bc")));
+        assert_eq!(
+            encode(&(1u8, "a")),
+            vec![1, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 97]
+        );
+        assert_eq!(encode(&(1u8, 2u8, 3u8)).len(), 27);
+        assert_eq!(encode(&(1u8, 2u8, 3u8, 4u8)).len(), 36);
+    }
+}
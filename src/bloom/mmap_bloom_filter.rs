@@ -0,0 +1,235 @@
+/*!
+ * mmap_bloom_filter.rs
+ *
+ * Implements a read-only bloom filter backed by a memory-mapped file:
+ *      MmapBloomFilter       -- a BloomFilter saved with BloomFilter::save(), answering checks
+ *                               straight from the mapped pages instead of copying the bits into
+ *                               memory. Processes mapping the same file share one copy of it
+ *                               through the page cache. Does not support insertion or deletion.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::persistence::{ChecksumReader, FilterKind};
+use crate::bloom::BloomFilter;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::HashFunction;
+use crate::bloom::HashIndices;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+/// Size of the trailing checksum
+const CHECKSUM_LEN: usize = 4;
+
+/// MmapBloomFilter struct:
+///    * mmap:                Read-only mapping of the whole file
+///    * payload_offset:      Offset of the bit payload in the file
+///    * len:                 Length of bit array
+///    * hash_count:          Hash count
+///    * false_positive_rate: False positive rate
+///    * expected_inserts:    Expected inserts
+///    * actual_inserts:      Actual inserts, when the filter was saved
+///    * hasher:              Hash function used to compute indices
+///    * indexing:            How indices are derived from the hash function
+pub struct MmapBloomFilter {
+    mmap: Mmap,
+    payload_offset: usize,
+    len: u64,
+    hash_count: u64,
+    false_positive_rate: f64,
+    expected_inserts: u64,
+    actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
+}
+
+/// Implementation of a read-only, memory-mapped bloom filter
+impl MmapBloomFilter {
+    /// Getter for len
+    pub fn get_len(&self) -> u64 {
+        return self.len;
+    }
+
+    /// Getter for hash_count
+    pub fn get_hash_count(&self) -> u64 {
+        return self.hash_count;
+    }
+
+    /// Getter for false_positive_rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for expected_inserts
+    pub fn get_expected_inserts(&self) -> u64 {
+        return self.expected_inserts;
+    }
+
+    /// Getter for actual_inserts
+    pub fn get_actual_inserts(&self) -> u64 {
+        return self.actual_inserts;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
+    }
+
+    /// Memory-map the file at path, which must hold a BloomFilter saved with
+    /// BloomFilter::save(). Only the header is read and validated: use verify() to also check
+    /// the checksum, which reads the whole file.
+    ///
+    /// The file must not be modified while it is mapped, by this process or any other: write
+    /// it once (eg to a temporary file, then rename it into place) and only read it after.
+    /// Changes to the mapped bytes show up in check(), and truncating the file makes the
+    /// process crash with SIGBUS on the next access to the missing pages, rather than
+    /// returning an error
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MmapBloomFilter> {
+        let file: File = File::open(path)?;
+        return MmapBloomFilter::from_file(&file);
+    }
+
+    /// Memory-map an open file. The same requirements as open() apply: the file must not be
+    /// modified or truncated while it is mapped
+    pub fn from_file(file: &File) -> Result<MmapBloomFilter> {
+        // Safety: the mapping is read-only, but its contents can still change if the file is
+        // modified. That is left to the caller, as documented on open()
+        let mmap: Mmap = unsafe { Mmap::map(file)? };
+
+        let mut remaining: &[u8] = &mmap;
+        let mut reader = ChecksumReader::new(&mut remaining, FilterKind::Bloom)?;
+        let (params, actual_inserts, hasher, indexing) = BloomFilter::read_parameters(&mut reader)?;
+        let payload_len: u64 = params.get_len().div_ceil(8);
+        reader.read_payload_len(payload_len)?;
+        let payload_offset: usize = mmap.len() - remaining.len();
+        if (remaining.len() as u64) != payload_len + CHECKSUM_LEN as u64 {
+            return Err(Error::Corrupt("file size does not match the filter length"));
+        }
+
+        return Ok(MmapBloomFilter {
+            mmap: mmap,
+            payload_offset: payload_offset,
+            len: params.get_len(),
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
+            expected_inserts: params.get_expected_inserts(),
+            actual_inserts: actual_inserts,
+            hasher: hasher,
+            indexing: indexing,
+        });
+    }
+
+    /// Check the checksum of the whole file
+    pub fn verify(&self) -> Result<()> {
+        let body_len: usize = self.mmap.len() - CHECKSUM_LEN;
+        let mut stored: [u8; CHECKSUM_LEN] = [0; CHECKSUM_LEN];
+        stored.copy_from_slice(&self.mmap[body_len..]);
+        if u32::from_le_bytes(stored) != crc32fast::hash(&self.mmap[..body_len]) {
+            return Err(Error::ChecksumMismatch);
+        }
+        return Ok(());
+    }
+
+    /// Check whether an element is probably in the filter or not
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        let payload: &[u8] = &self.mmap[self.payload_offset..];
+        return item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            for bit_index in indices {
+                // Bits are stored most significant bit first, as in BitVec::to_bytes()
+                if payload[bit_index / 8] & (0x80 >> (bit_index % 8)) == 0 {
+                    return false;
+                }
+            }
+            return true;
+        });
+    }
+
+    /// Report the bits set, the fill ratio and the estimated current false positive rate.
+    /// This reads the whole payload
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let payload_end: usize = self.mmap.len() - CHECKSUM_LEN;
+        let bits_set: u64 = self.mmap[self.payload_offset..payload_end]
+            .iter()
+            .map(|byte| byte.count_ones() as u64)
+            .sum();
+        return FillStatistics::new(self.len, self.hash_count, bits_set);
+    }
+
+    /// Copy the filter into memory, as a BloomFilter which supports insertion
+    pub fn to_bloom_filter(&self) -> Result<BloomFilter> {
+        let mut bytes: &[u8] = &self.mmap;
+        return BloomFilter::load(&mut bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, SeekFrom, Write};
+
+    /// Save a BloomFilter holding 0..inserts to a temporary file
+    fn saved_filter(inserts: u64, hasher: HashFunction, indexing: IndexingScheme) -> File {
+        let mut bf: BloomFilter = BloomFilter::with_hashing(1000, 0.01, hasher, indexing);
+        for i in 0..inserts {
+            bf.insert(&i);
+        }
+        let mut file: File = tempfile::tempfile().unwrap();
+        bf.save(&mut file).unwrap();
+        return file;
+    }
+
+    #[test]
+    /// Test that the mapped filter answers exactly like the filter it was saved from
+    fn test_check() {
+        for indexing in [IndexingScheme::PerSeed, IndexingScheme::DoubleHashing].iter() {
+            let file: File = saved_filter(500, HashFunction::Xxh3, *indexing);
+            let mbf: MmapBloomFilter = MmapBloomFilter::from_file(&file).unwrap();
+            mbf.verify().unwrap();
+            let bf: BloomFilter = mbf.to_bloom_filter().unwrap();
+            assert_eq!(mbf.get_len(), bf.get_len());
+            assert_eq!(mbf.get_hash_count(), bf.get_hash_count());
+            assert_eq!(mbf.get_actual_inserts(), 500);
+            assert_eq!(mbf.get_indexing(), *indexing);
+            for i in 0..500u64 {
+                assert!(mbf.check(&i));
+            }
+            for i in 0..5000u64 {
+                assert_eq!(mbf.check(&i), bf.check(&i));
+            }
+        }
+    }
+
+    #[test]
+    /// Test that a truncated file is rejected when opened, and a tampered one by verify()
+    fn test_corrupt_file() {
+        let mut file: File = saved_filter(10, HashFunction::Murmur3, IndexingScheme::default());
+        let size: u64 = file.metadata().unwrap().len();
+
+        file.seek(SeekFrom::Start(size - 6)).unwrap();
+        file.write_all(&[0xff]).unwrap();
+        let mbf: MmapBloomFilter = MmapBloomFilter::from_file(&file).unwrap();
+        assert_eq!(mbf.verify(), Err(Error::ChecksumMismatch));
+
+        file.set_len(size - 1).unwrap();
+        assert_eq!(
+            MmapBloomFilter::from_file(&file).err(),
+            Some(Error::Corrupt("file size does not match the filter length"))
+        );
+    }
+}
@@ -0,0 +1,741 @@
+/*!
+ * params.rs
+ *
+ * Implements the sizing math shared by every bloom filter variant, for use by the filters
+ * themselves and by callers planning capacity offline:
+ *      optimal_len                  -- the length (m) for n inserts at a false positive rate p.
+ *      optimal_hash_count           -- the hash count (k) for n inserts into m bits.
+ *      expected_false_positive_rate -- the false positive rate of (m, k) after n inserts.
+ *      capacity                     -- the number of inserts m bits can hold at a rate p.
+ *      blocked_false_positive_rate  -- the false positive rate of a blocked filter of (m, k)
+ *                                      after n inserts, accounting for uneven block loads.
+ *      optimal_blocked_hash_count   -- the hash count (k) of a blocked filter for n inserts into
+ *                                      m bits, capped for a single block.
+ *      optimal_blocked_len          -- the length (m) of a blocked filter for n inserts at a
+ *                                      false positive rate p, or an error if it can't be met.
+ *      optimal_partition_count      -- the number of slices (k) of a partitioned filter at a
+ *                                      false positive rate p.
+ *      optimal_slice_len            -- the length of each slice of a partitioned filter for n
+ *                                      inserts at a false positive rate p.
+ *      partitioned_false_positive_rate -- the false positive rate of a partitioned filter of k
+ *                                      slices after n inserts.
+ *      cuckoo_fingerprint_bits      -- the fingerprint size of a cuckoo filter at a false
+ *                                      positive rate p.
+ *      cuckoo_num_buckets           -- the number of buckets of a cuckoo filter for n inserts.
+ *      cuckoo_false_positive_rate   -- the false positive rate of a full cuckoo filter.
+ *      quotient_filter_quotient_bits -- the quotient size of a quotient filter for n inserts.
+ *      quotient_filter_remainder_bits -- the remainder size of a quotient filter for n inserts
+ *                                      at a false positive rate p.
+ *      quotient_filter_false_positive_rate -- the false positive rate of a quotient filter
+ *                                      after n inserts.
+ *      binary_fuse_segment_length   -- the segment length of a binary fuse filter of n items.
+ *      binary_fuse_segment_count    -- the number of segments of a binary fuse filter of n items.
+ *      binary_fuse_false_positive_rate -- the false positive rate of a binary fuse filter.
+ *      ribbon_upper_columns         -- the most solution columns per block of a ribbon filter at
+ *                                      a false positive rate p.
+ *      ribbon_upper_fraction        -- the fraction of the blocks of a ribbon filter using that
+ *                                      many columns, rather than one less.
+ *      ribbon_num_blocks            -- the number of blocks of a ribbon filter for n items.
+ *      ribbon_false_positive_rate   -- the false positive rate of a ribbon filter.
+ *      stable_hash_count            -- the hash count (k) of a stable filter at a false positive
+ *                                      rate p.
+ *      stable_max_cell_value        -- the maximum cell value of a stable filter at a false
+ *                                      positive rate p.
+ *      stable_decrement_count       -- the number of cells (P) a stable filter decrements per
+ *                                      insert, to converge to a false positive rate p.
+ *      stable_false_positive_rate   -- the false positive rate a stable filter converges to.
+ *      estimate_cardinality         -- the Swamidass-Baldi estimate of the number of distinct
+ *                                      items inserted, from the number of bits set.
+ *      cardinality_standard_error   -- the standard error of estimate_cardinality.
+ *      estimate_false_positive_rate -- the current false positive rate, from the fill ratio.
+ *      Rounding                     -- an enum of ways to round m to a hardware-friendly (or
+ *                                      foldable) size.
+ *
+ * See this SO answer for the formulas: https://stackoverflow.com/a/22467497
+ */
+#![allow(clippy::needless_return)]
+use crate::bloom::{Error, Result};
+
+/// Number of bits in a machine word
+pub const WORD_BITS: u64 = 64;
+/// Number of bits in a (64-byte) cache line
+pub const CACHE_LINE_BITS: u64 = 512;
+/// Most hashes the builder gives a filter sized from a memory budget. A budget far larger
+/// than the inserts need would otherwise get hundreds of thousands of hashes per item, for a
+/// false positive rate already far below 2^-64 at this many
+pub const MAX_HASH_COUNT: u64 = 64;
+/// Most hashes a blocked filter sets per item. All of them land in one CACHE_LINE_BITS block,
+/// so past this many they mostly collide, and the rate gets worse rather than better
+pub const BLOCKED_MAX_HASH_COUNT: u64 = 24;
+
+/// How to round the length (m) of a filter
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    /// Use the length as computed
+    Exact,
+    /// Round to a multiple of 64 bits
+    Words,
+    /// Round to a multiple of 512 bits (a 64-byte cache line)
+    CacheLines,
+    /// Round to a power of two
+    PowerOfTwo,
+    /// Round to a multiple of 2^times, so the filter can be folded (see BloomFilter::fold())
+    /// up to times times
+    Foldable(u32),
+}
+
+impl Default for Rounding {
+    fn default() -> Rounding {
+        return Rounding::Exact;
+    }
+}
+
+/// Given a desired false positive rate, calculate the length of the bit array required
+/// m = ceil(-n*ln(p) / (ln(2)^2))
+pub fn optimal_len(expected_inserts: u64, false_positive_rate: f64) -> u64 {
+    let two: f64 = 2.0;
+    return ((-(expected_inserts as f64) * false_positive_rate.ln()) / two.ln().powf(two)).ceil()
+        as u64;
+}
+
+/// Calculate the number of hashes required
+/// k = ceil(m/n * ln(2))
+pub fn optimal_hash_count(expected_inserts: u64, len: u64) -> u64 {
+    let two: f64 = 2.0;
+    return (((len as f64) / expected_inserts as f64) * two.ln()).ceil() as u64;
+}
+
+/// Calculate the false positive rate of a filter of length m with k hashes, after n inserts
+/// p = (1 - e^(-k*n/m))^k
+pub fn expected_false_positive_rate(len: u64, hash_count: u64, inserts: u64) -> f64 {
+    let k: f64 = hash_count as f64;
+    return (1.0 - (-k * inserts as f64 / len as f64).exp()).powf(k);
+}
+
+/// Calculate the number of inserts a filter of length m can hold at a false positive rate p,
+/// assuming the optimal hash count
+/// n = floor(-m * (ln(2)^2) / ln(p))
+pub fn capacity(len: u64, false_positive_rate: f64) -> u64 {
+    let two: f64 = 2.0;
+    return ((len as f64) * two.ln().powf(two) / -false_positive_rate.ln()).floor() as u64;
+}
+
+/// Calculate the false positive rate of a blocked filter of length m with k hashes, after n
+/// inserts (Putze, Sanders & Singler, 2007). Every item sets its k bits in one block of
+/// B = CACHE_LINE_BITS bits, so the load of a block follows a Poisson distribution of mean
+/// n*B/m, and overloaded blocks make the rate higher than that of a standard filter:
+/// p = sum over i of Poisson(i; n*B/m) * (1 - (1 - 1/B)^(k*i))^k
+pub fn blocked_false_positive_rate(len: u64, hash_count: u64, inserts: u64) -> f64 {
+    let block_bits: f64 = CACHE_LINE_BITS as f64;
+    let k: f64 = hash_count as f64;
+    let mean_load: f64 = inserts as f64 * block_bits / len as f64;
+    if mean_load == 0.0 {
+        return 0.0;
+    }
+    // The Poisson terms beyond this many standard deviations are negligible
+    let max_load: u64 = (mean_load + 12.0 * mean_load.sqrt() + 20.0).ceil() as u64;
+    let mut rate: f64 = 0.0;
+    let mut log_factorial: f64 = 0.0;
+    for load in 0..=max_load {
+        if load > 0 {
+            log_factorial += (load as f64).ln();
+        }
+        let probability: f64 = (-mean_load + load as f64 * mean_load.ln() - log_factorial).exp();
+        let block_rate: f64 = (1.0 - (1.0 - 1.0 / block_bits).powf(k * load as f64)).powf(k);
+        rate += probability * block_rate;
+    }
+    return rate.min(1.0);
+}
+
+/// Calculate the hash count of a blocked filter of length m for n inserts: the optimal hash
+/// count of a standard filter, capped at BLOCKED_MAX_HASH_COUNT
+pub fn optimal_blocked_hash_count(expected_inserts: u64, len: u64) -> u64 {
+    return optimal_hash_count(expected_inserts, len).min(BLOCKED_MAX_HASH_COUNT);
+}
+
+/// Calculate the length of a blocked filter, a multiple of CACHE_LINE_BITS, for which
+/// blocked_false_positive_rate() with optimal_blocked_hash_count() meets a desired false
+/// positive rate. This is longer than optimal_len(), by more the lower the rate.
+/// Returns an error if no length that fits in a u64 meets the rate
+pub fn optimal_blocked_len(expected_inserts: u64, false_positive_rate: f64) -> Result<u64> {
+    let blocked_rate = |blocks: u64| -> f64 {
+        let len: u64 = blocks * CACHE_LINE_BITS;
+        let hash_count: u64 = optimal_blocked_hash_count(expected_inserts, len);
+        return blocked_false_positive_rate(len, hash_count, expected_inserts);
+    };
+    let max_blocks: u64 = u64::MAX / CACHE_LINE_BITS;
+    // Search from the unblocked length, doubling until the rate is met, then bisect
+    let mut low: u64 = optimal_len(expected_inserts, false_positive_rate)
+        .div_ceil(CACHE_LINE_BITS)
+        .clamp(1, max_blocks);
+    let mut high: u64 = low;
+    while blocked_rate(high) > false_positive_rate {
+        if high == max_blocks {
+            return Err(Error::UnreachableFalsePositiveRate(false_positive_rate));
+        }
+        low = high;
+        high = high.checked_mul(2).unwrap_or(max_blocks).min(max_blocks);
+    }
+    while low < high {
+        let mid: u64 = low + (high - low) / 2;
+        if blocked_rate(mid) > false_positive_rate {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    return Ok(high * CACHE_LINE_BITS);
+}
+
+/// Calculate the number of slices of a partitioned filter, one per hash
+/// k = ceil(log2(1/p))
+pub fn optimal_partition_count(false_positive_rate: f64) -> u64 {
+    return (-false_positive_rate.log2()).ceil().max(1.0) as u64;
+}
+
+/// Calculate the length of each of the k slices of a partitioned filter, so that it meets a
+/// false positive rate p after n inserts. Each item sets exactly one bit per slice, so a slice
+/// of s bits has a fraction 1 - (1 - 1/s)^n of its bits set, which must not exceed p^(1/k):
+/// s = ceil(1 / (1 - (1 - p^(1/k))^(1/n)))
+pub fn optimal_slice_len(expected_inserts: u64, false_positive_rate: f64, hash_count: u64) -> u64 {
+    let fill_ratio: f64 = false_positive_rate.powf(1.0 / hash_count as f64);
+    // 1 - (1 - f)^(1/n), computed without cancellation for large n
+    let clear_ratio: f64 = -((-fill_ratio).ln_1p() / expected_inserts as f64).exp_m1();
+    return (1.0 / clear_ratio).ceil() as u64;
+}
+
+/// Calculate the false positive rate of a partitioned filter of k slices of s bits each,
+/// after n inserts
+/// p = (1 - (1 - 1/s)^n)^k
+pub fn partitioned_false_positive_rate(slice_len: u64, hash_count: u64, inserts: u64) -> f64 {
+    let clear_ratio: f64 = (inserts as f64 * (-1.0 / slice_len as f64).ln_1p()).exp();
+    return (1.0 - clear_ratio).powf(hash_count as f64);
+}
+
+/// Calculate the number of bits per fingerprint of a cuckoo filter with b slots per bucket.
+/// A check compares a fingerprint with the 2b slots of two buckets (Fan et al., 2014):
+/// f = ceil(log2(2b/p))
+pub fn cuckoo_fingerprint_bits(false_positive_rate: f64, bucket_size: u64) -> u32 {
+    return (2.0 * bucket_size as f64 / false_positive_rate)
+        .log2()
+        .ceil()
+        .max(1.0) as u32;
+}
+
+/// The load factor a cuckoo filter with b slots per bucket can reliably reach before
+/// insertions start failing (Fan et al., 2014)
+pub fn cuckoo_max_load_factor(bucket_size: u64) -> f64 {
+    match bucket_size {
+        1 => return 0.5,
+        2 | 3 => return 0.84,
+        4..=7 => return 0.95,
+        _ => return 0.98,
+    }
+}
+
+/// Calculate the number of buckets of a cuckoo filter with b slots per bucket to hold n items.
+/// This is a power of two, so that an item's alternate bucket can be derived from its
+/// fingerprint alone
+/// buckets = next_power_of_two(ceil(n / (b * max_load_factor)))
+pub fn cuckoo_num_buckets(expected_inserts: u64, bucket_size: u64) -> u64 {
+    let slots: f64 = expected_inserts as f64 / cuckoo_max_load_factor(bucket_size);
+    return ((slots / bucket_size as f64).ceil() as u64)
+        .max(1)
+        .next_power_of_two();
+}
+
+/// Calculate the false positive rate of a full cuckoo filter with f-bit fingerprints and b
+/// slots per bucket: the chance that one of the 2b slots checked matches by accident
+/// p = 1 - (1 - 2^-f)^(2b)
+pub fn cuckoo_false_positive_rate(fingerprint_bits: u32, bucket_size: u64) -> f64 {
+    let match_rate: f64 = 0.5f64.powi(fingerprint_bits as i32);
+    return 1.0 - (1.0 - match_rate).powf(2.0 * bucket_size as f64);
+}
+
+/// The load factor a quotient filter is sized for: past it, clusters of shifted remainders
+/// grow long and inserts and checks slow down
+pub const QUOTIENT_FILTER_MAX_LOAD: f64 = 0.75;
+
+/// Calculate the number of quotient bits of a quotient filter holding n items, so that its
+/// 2^q slots stay under QUOTIENT_FILTER_MAX_LOAD
+/// q = ceil(log2(n / max_load))
+pub fn quotient_filter_quotient_bits(expected_inserts: u64) -> u32 {
+    return (expected_inserts as f64 / QUOTIENT_FILTER_MAX_LOAD)
+        .log2()
+        .ceil()
+        .max(1.0) as u32;
+}
+
+/// Calculate the number of remainder bits of a quotient filter holding n items at a false
+/// positive rate p. With a load factor a = n / 2^q, the rate is about a * 2^-r:
+/// r = ceil(log2(a / p))
+pub fn quotient_filter_remainder_bits(expected_inserts: u64, false_positive_rate: f64) -> u32 {
+    let slots: f64 = 2f64.powi(quotient_filter_quotient_bits(expected_inserts) as i32);
+    return (expected_inserts as f64 / slots / false_positive_rate)
+        .log2()
+        .ceil()
+        .max(1.0) as u32;
+}
+
+/// Calculate the false positive rate of a quotient filter with q quotient bits and r remainder
+/// bits holding n items: the chance that one of them has the same q + r bit fingerprint
+/// p = 1 - e^(-n / 2^(q + r))
+pub fn quotient_filter_false_positive_rate(
+    quotient_bits: u32,
+    remainder_bits: u32,
+    inserts: u64,
+) -> f64 {
+    let fingerprints: f64 = 2f64.powi((quotient_bits + remainder_bits) as i32);
+    return 1.0 - (-(inserts as f64) / fingerprints).exp();
+}
+
+/// Largest segment length of a binary fuse filter
+pub const BINARY_FUSE_MAX_SEGMENT_LENGTH: u64 = 1 << 18;
+
+/// Calculate the segment length of a 3-wise binary fuse filter of n items: a power of two
+/// that grows with n, so that the three slots of an item stay close together (Graf & Lemire,
+/// 2022)
+/// segment_length = 2^floor(ln(n) / ln(3.33) + 2.25)
+pub fn binary_fuse_segment_length(items: u64) -> u64 {
+    if items == 0 {
+        return 4;
+    }
+    let exponent: f64 = ((items as f64).ln() / 3.33f64.ln() + 2.25).floor();
+    return (1u64 << exponent as u32).min(BINARY_FUSE_MAX_SEGMENT_LENGTH);
+}
+
+/// Calculate the number of segments that the slots of the items of a 3-wise binary fuse
+/// filter of n items start in. The filter has 2 more segments than that, for a total of
+/// about 1.125n slots for large n, and a bit more for small n:
+/// slots = n * max(1.125, 0.875 + 0.25 * ln(10^6) / ln(n))
+pub fn binary_fuse_segment_count(items: u64, segment_length: u64) -> u64 {
+    let size_factor: f64 = if items <= 1 {
+        0.0
+    } else {
+        (0.875 + 0.25 * 1e6f64.ln() / (items as f64).ln()).max(1.125)
+    };
+    let capacity: u64 = (items as f64 * size_factor).round() as u64;
+    let segment_count: u64 = capacity.div_ceil(segment_length);
+    return segment_count.saturating_sub(2).max(1);
+}
+
+/// Calculate the false positive rate of a binary fuse filter with f-bit fingerprints: the
+/// chance that an absent item's fingerprint matches the XOR of its three slots
+/// p = 2^-f
+pub fn binary_fuse_false_positive_rate(fingerprint_bits: u32) -> f64 {
+    return 0.5f64.powi(fingerprint_bits as i32);
+}
+
+/// Number of slots (and coefficient bits) a ribbon filter row spans, and per block
+pub const RIBBON_WIDTH: u64 = 64;
+/// Number of slots per item of a ribbon filter, enough for construction to rarely fail
+pub const RIBBON_SLOTS_PER_ITEM: f64 = 1.08;
+
+/// Calculate the number of solution columns, ie fingerprint bits, of the blocks of a ribbon
+/// filter at a false positive rate p: r = ceil(log2(1/p)). The other blocks use r - 1
+pub fn ribbon_upper_columns(false_positive_rate: f64) -> u32 {
+    return (1.0 / false_positive_rate).log2().ceil().max(1.0) as u32;
+}
+
+/// Calculate the fraction f of the blocks of a ribbon filter that use r columns rather than
+/// r - 1, so that the average false positive rate meets p:
+/// f * 2^-r + (1 - f) * 2^-(r-1) = p, ie f = 2 - p * 2^r
+pub fn ribbon_upper_fraction(false_positive_rate: f64) -> f64 {
+    let upper_columns: u32 = ribbon_upper_columns(false_positive_rate);
+    return (2.0 - false_positive_rate * 2f64.powi(upper_columns as i32)).clamp(0.0, 1.0);
+}
+
+/// Calculate the number of blocks of RIBBON_WIDTH slots of a ribbon filter for n items:
+/// blocks = ceil(n * RIBBON_SLOTS_PER_ITEM / RIBBON_WIDTH) + 1
+/// The extra block holds the end of the rows starting in the last one
+pub fn ribbon_num_blocks(items: u64) -> u64 {
+    return (items as f64 * RIBBON_SLOTS_PER_ITEM / RIBBON_WIDTH as f64).ceil() as u64 + 1;
+}
+
+/// Calculate the false positive rate of a ribbon filter where a fraction f of the queries
+/// check r fingerprint bits, and the others r - 1
+/// p = f * 2^-r + (1 - f) * 2^-(r-1)
+pub fn ribbon_false_positive_rate(upper_columns: u32, upper_fraction: f64) -> f64 {
+    let upper_rate: f64 = 0.5f64.powi(upper_columns as i32);
+    return upper_fraction * upper_rate + (1.0 - upper_fraction) * 2.0 * upper_rate;
+}
+
+/// Largest maximum cell value of a stable filter, which uses one byte per cell
+pub const STABLE_MAX_CELL_VALUE: u64 = 255;
+
+/// Calculate the hash count of a stable filter at a false positive rate p, as for a
+/// partitioned filter: k = ceil(log2(1/p))
+pub fn stable_hash_count(false_positive_rate: f64) -> u64 {
+    return optimal_partition_count(false_positive_rate);
+}
+
+/// Calculate the fraction of cells that are zero once a stable filter of m cells, k hashes,
+/// maximum cell value Max and P decrements per insert has converged (Deng & Rafiei, 2006):
+/// zeros = (1 / (1 + 1 / (P * (1/k - 1/m))))^Max
+fn stable_zero_fraction(len: u64, hash_count: u64, max_cell_value: u64, decrements: u64) -> f64 {
+    let spread: f64 = decrements as f64 * (1.0 / hash_count as f64 - 1.0 / len as f64);
+    return (1.0 / (1.0 + 1.0 / spread)).powf(max_cell_value as f64);
+}
+
+/// Calculate the false positive rate a stable filter converges to: the chance that the k
+/// cells of an absent item are all non-zero
+/// p = (1 - zeros)^k
+pub fn stable_false_positive_rate(
+    len: u64,
+    hash_count: u64,
+    max_cell_value: u64,
+    decrements: u64,
+) -> f64 {
+    let zeros: f64 = stable_zero_fraction(len, hash_count, max_cell_value, decrements);
+    return (1.0 - zeros).powf(hash_count as f64);
+}
+
+/// Calculate the number of cells a stable filter of m cells, k hashes and maximum cell value
+/// Max decrements per insert, so that it converges to a false positive rate of at most p:
+/// P = ceil(1 / ((1 / (1 - p^(1/k))^(1/Max) - 1) * (1/k - 1/m))), at least 1 and at most m
+/// (or 1, for m = 0)
+pub fn stable_decrement_count(
+    len: u64,
+    hash_count: u64,
+    max_cell_value: u64,
+    false_positive_rate: f64,
+) -> u64 {
+    let zeros: f64 = 1.0 - false_positive_rate.powf(1.0 / hash_count as f64);
+    let growth: f64 = (-zeros.ln() / max_cell_value as f64).exp_m1();
+    let spread: f64 = 1.0 / hash_count as f64 - 1.0 / len as f64;
+    return ((1.0 / (growth * spread)).ceil() as u64)
+        .max(1)
+        .min(len.max(1));
+}
+
+/// Pick the maximum cell value of a stable filter of m cells and k hashes at a false positive
+/// rate p: the smallest Max = 2^d - 1 for which rounding P up to a whole number of cells
+/// keeps the stable rate within 10% of p. Larger values spread out when an item's cells reach
+/// zero, but take more decrements per insert
+pub fn stable_max_cell_value(len: u64, hash_count: u64, false_positive_rate: f64) -> u64 {
+    let mut max_cell_value: u64 = 1;
+    while max_cell_value < STABLE_MAX_CELL_VALUE {
+        let decrements: u64 =
+            stable_decrement_count(len, hash_count, max_cell_value, false_positive_rate);
+        let rate: f64 = stable_false_positive_rate(len, hash_count, max_cell_value, decrements);
+        if rate >= 0.9 * false_positive_rate {
+            break;
+        }
+        max_cell_value = 2 * max_cell_value + 1;
+    }
+    return max_cell_value;
+}
+
+/// Estimate the number of distinct items inserted into a filter of length m with k hashes,
+/// from the number of bits set X (Swamidass & Baldi, 2007):
+/// n* = -(m/k) * ln(1 - X/m)
+/// A saturated filter (X = m) is estimated as if one bit were still clear, which gives the
+/// largest finite estimate
+pub fn estimate_cardinality(len: u64, hash_count: u64, bits_set: u64) -> f64 {
+    let m: f64 = len as f64;
+    let x: f64 = bits_set.min(len.saturating_sub(1)) as f64;
+    return -(m / hash_count as f64) * (1.0 - x / m).ln();
+}
+
+/// Calculate the standard error of the cardinality estimate of a filter of length m with k
+/// hashes holding n items (Papapetrou, Siberski & Nejdl, 2010):
+/// sigma = sqrt(m/k^2 * (e^(k*n/m) - 1 - k*n/m))
+pub fn cardinality_standard_error(len: u64, hash_count: u64, cardinality: f64) -> f64 {
+    let m: f64 = len as f64;
+    let k: f64 = hash_count as f64;
+    let load: f64 = k * cardinality.max(0.0) / m;
+    return (m / (k * k) * (load.exp() - 1.0 - load)).sqrt();
+}
+
+/// Estimate the current false positive rate of a filter with k hashes, from the fraction of
+/// its bits which are set: an item is a false positive if all of its k bits happen to be set
+/// p = (X/m)^k
+pub fn estimate_false_positive_rate(hash_count: u64, fill_ratio: f64) -> f64 {
+    return fill_ratio.powf(hash_count as f64);
+}
+
+/// Round a length up, eg so that a filter sized for a false positive rate still meets it
+pub fn round_len_up(len: u64, rounding: Rounding) -> u64 {
+    match rounding {
+        Rounding::Exact => return len,
+        Rounding::Words => return round_up_to_multiple(len, WORD_BITS),
+        Rounding::CacheLines => return round_up_to_multiple(len, CACHE_LINE_BITS),
+        Rounding::PowerOfTwo => return len.max(1).next_power_of_two(),
+        Rounding::Foldable(times) => return round_up_to_multiple(len, fold_unit(times)),
+    }
+}
+
+/// Round a length down, eg so that a filter sized from a memory budget still fits in it.
+/// Returns 0 if len is smaller than the rounding unit
+pub fn round_len_down(len: u64, rounding: Rounding) -> u64 {
+    match rounding {
+        Rounding::Exact => return len,
+        Rounding::Words => return len - len % WORD_BITS,
+        Rounding::CacheLines => return len - len % CACHE_LINE_BITS,
+        Rounding::PowerOfTwo => {
+            if len == 0 {
+                return 0;
+            }
+            return 1 << (63 - len.leading_zeros());
+        }
+        Rounding::Foldable(times) => return len - len % fold_unit(times),
+    }
+}
+
+/// The smallest length which can be folded times times, 2^times
+fn fold_unit(times: u32) -> u64 {
+    return 1 << times.min(63);
+}
+
+/// Round len up to the next multiple of unit
+fn round_up_to_multiple(len: u64, unit: u64) -> u64 {
+    return len.div_ceil(unit) * unit;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that we get the expected value for calculating the length of the
+    /// bit array using the computation:
+    /// m = ceil(-n*ln(p) / (ln(2)^2))
+    fn test_optimal_len() {
+        assert_eq!(optimal_len(100, 0.01), 959);
+        assert_eq!(optimal_len(1000, 0.001), 14378);
+        assert_eq!(optimal_len(5, 0.1), 24);
+        assert_eq!(optimal_len(10000, 0.01), 95851);
+        assert_eq!(optimal_len(216553, 0.01), 2075674);
+    }
+
+    #[test]
+    /// Test that we get the expected value for calculating the number of iterations
+    /// if hashing we need to perform
+    /// k = ceil(m/n * ln(2))
+    fn test_optimal_hash_count() {
+        assert_eq!(optimal_hash_count(100, 959), 7);
+        assert_eq!(optimal_hash_count(1000, 14378), 10);
+        assert_eq!(optimal_hash_count(5, 24), 4);
+        assert_eq!(optimal_hash_count(10000, 95851), 7);
+        assert_eq!(optimal_hash_count(216553, 2075674), 7);
+    }
+
+    #[test]
+    /// Test that the expected false positive rate and the capacity invert optimal_len
+    fn test_false_positive_rate_and_capacity() {
+        let fpr: f64 = expected_false_positive_rate(959, 7, 100);
+        assert!((fpr - 0.01).abs() < 0.0005, "{}", fpr);
+        let fpr2: f64 = expected_false_positive_rate(14378, 10, 1000);
+        assert!((fpr2 - 0.001).abs() < 0.00005, "{}", fpr2);
+        assert_eq!(expected_false_positive_rate(959, 7, 0), 0.0);
+        assert_eq!(capacity(959, 0.01), 100);
+        assert_eq!(capacity(14384, 0.001), 1000);
+        assert_eq!(capacity(1, 0.0001), 0);
+    }
+
+    #[test]
+    /// Test that blocking raises the false positive rate, and the blocked length makes up for it
+    fn test_blocked_false_positive_rate() {
+        assert_eq!(blocked_false_positive_rate(1024, 7, 0), 0.0);
+        let len: u64 = round_len_up(optimal_len(1000, 0.01), Rounding::CacheLines);
+        let standard: f64 = expected_false_positive_rate(len, 7, 1000);
+        let blocked: f64 = blocked_false_positive_rate(len, 7, 1000);
+        assert!(
+            blocked > standard && blocked < 2.0 * standard,
+            "{}",
+            blocked
+        );
+
+        let blocked_len: u64 = optimal_blocked_len(1000, 0.01).unwrap();
+        assert_eq!(blocked_len % CACHE_LINE_BITS, 0);
+        assert!(blocked_len > len);
+        let hash_count: u64 = optimal_blocked_hash_count(1000, blocked_len);
+        assert!(blocked_false_positive_rate(blocked_len, hash_count, 1000) <= 0.01);
+        let smaller: u64 = blocked_len - CACHE_LINE_BITS;
+        let smaller_hash_count: u64 = optimal_blocked_hash_count(1000, smaller);
+        assert!(blocked_false_positive_rate(smaller, smaller_hash_count, 1000) > 0.01);
+    }
+
+    #[test]
+    /// Test that blocked sizing stays within a few times the unblocked length at low rates,
+    /// with the hash count capped, and reports a rate no length can meet
+    fn test_blocked_sizing_low_rates() {
+        for rate in [1e-9, 1e-12] {
+            let blocked_len: u64 = optimal_blocked_len(1_000_000, rate).unwrap();
+            assert!(
+                blocked_len < 4 * optimal_len(1_000_000, rate),
+                "{}",
+                blocked_len
+            );
+            let hash_count: u64 = optimal_blocked_hash_count(1_000_000, blocked_len);
+            assert_eq!(hash_count, BLOCKED_MAX_HASH_COUNT);
+            assert!(blocked_false_positive_rate(blocked_len, hash_count, 1_000_000) <= rate);
+        }
+        assert_eq!(
+            optimal_blocked_len(1_000_000, 1e-100),
+            Err(Error::UnreachableFalsePositiveRate(1e-100))
+        );
+    }
+
+    #[test]
+    /// Test that a partitioned filter sized with optimal_slice_len meets its rate, and is close
+    /// to the size of an unpartitioned one
+    fn test_partitioned_sizing() {
+        assert_eq!(optimal_partition_count(0.01), 7);
+        assert_eq!(optimal_partition_count(0.5), 1);
+        assert_eq!(optimal_partition_count(0.9), 1);
+        let slice_len: u64 = optimal_slice_len(1000, 0.01, 7);
+        assert!(partitioned_false_positive_rate(slice_len, 7, 1000) <= 0.01);
+        assert!(partitioned_false_positive_rate(slice_len - 1, 7, 1000) > 0.01);
+        let len: u64 = 7 * slice_len;
+        assert!(len >= optimal_len(1000, 0.01) && len < optimal_len(1000, 0.01) + 300);
+        assert_eq!(partitioned_false_positive_rate(slice_len, 7, 0), 0.0);
+    }
+
+    #[test]
+    /// Test that the cuckoo fingerprints meet the rate, and the buckets fit the inserts
+    fn test_cuckoo_sizing() {
+        assert_eq!(cuckoo_fingerprint_bits(0.01, 4), 10);
+        assert_eq!(cuckoo_fingerprint_bits(0.001, 2), 12);
+        assert!(cuckoo_false_positive_rate(10, 4) <= 0.01);
+        assert!(cuckoo_false_positive_rate(9, 4) > 0.01);
+        assert_eq!(cuckoo_num_buckets(1000, 4), 512);
+        assert_eq!(cuckoo_num_buckets(950, 4), 256);
+        assert_eq!(cuckoo_num_buckets(1, 4), 1);
+        assert_eq!(cuckoo_num_buckets(100, 1), 256);
+    }
+
+    #[test]
+    /// Test that the quotient filter stays under its maximum load, and meets the rate
+    fn test_quotient_filter_sizing() {
+        assert_eq!(quotient_filter_quotient_bits(1000), 11);
+        assert_eq!(quotient_filter_quotient_bits(768), 10);
+        assert_eq!(quotient_filter_quotient_bits(1), 1);
+        assert_eq!(quotient_filter_remainder_bits(1000, 0.01), 6);
+        for &p in &[0.1, 0.01, 0.001] {
+            let q: u32 = quotient_filter_quotient_bits(5000);
+            let r: u32 = quotient_filter_remainder_bits(5000, p);
+            assert!(quotient_filter_false_positive_rate(q, r, 5000) <= p);
+            assert!(quotient_filter_false_positive_rate(q, r - 1, 5000) > p / 2.0);
+        }
+        assert_eq!(quotient_filter_false_positive_rate(10, 6, 0), 0.0);
+    }
+
+    #[test]
+    /// Test that a binary fuse filter uses about 1.125 slots per item for large sets
+    fn test_binary_fuse_sizing() {
+        assert_eq!(binary_fuse_segment_length(0), 4);
+        assert_eq!(binary_fuse_segment_length(1000), 128);
+        assert_eq!(
+            binary_fuse_segment_length(10_000_000_000),
+            BINARY_FUSE_MAX_SEGMENT_LENGTH
+        );
+        assert_eq!(binary_fuse_segment_count(0, 4), 1);
+        assert_eq!(binary_fuse_segment_count(1, 4), 1);
+        let segment_length: u64 = binary_fuse_segment_length(1_000_000);
+        let slots: u64 =
+            (binary_fuse_segment_count(1_000_000, segment_length) + 2) * segment_length;
+        assert!(slots > 1_125_000 && slots < 1_150_000, "{}", slots);
+        assert_eq!(binary_fuse_false_positive_rate(8), 1.0 / 256.0);
+    }
+
+    #[test]
+    /// Test that a ribbon filter's columns mix to meet rates between powers of two
+    fn test_ribbon_sizing() {
+        assert_eq!(ribbon_upper_columns(0.01), 7);
+        assert_eq!(ribbon_upper_columns(0.003), 9);
+        assert_eq!(ribbon_upper_fraction(1.0 / 256.0), 1.0);
+        assert!((ribbon_upper_fraction(0.003) - 0.464).abs() < 1e-9);
+        for &p in &[0.5, 0.3, 0.01, 0.003, 0.0001] {
+            let rate: f64 =
+                ribbon_false_positive_rate(ribbon_upper_columns(p), ribbon_upper_fraction(p));
+            assert!((rate - p).abs() < 1e-12, "{} {}", p, rate);
+        }
+        assert_eq!(ribbon_num_blocks(0), 1);
+        assert_eq!(ribbon_num_blocks(1000), 18);
+    }
+
+    #[test]
+    /// Test that a stable filter is sized to converge to the target rate, or slightly under it
+    fn test_stable_sizing() {
+        assert_eq!(stable_hash_count(0.01), 7);
+        assert_eq!(stable_max_cell_value(100000, 7, 0.01), 3);
+        assert_eq!(stable_decrement_count(100000, 7, 3, 0.01), 26);
+        for &p in &[0.1, 0.05, 0.01, 0.003, 0.001, 0.0001] {
+            let hash_count: u64 = stable_hash_count(p);
+            let max_cell_value: u64 = stable_max_cell_value(100000, hash_count, p);
+            assert!((max_cell_value + 1).is_power_of_two());
+            let decrements: u64 = stable_decrement_count(100000, hash_count, max_cell_value, p);
+            let rate: f64 =
+                stable_false_positive_rate(100000, hash_count, max_cell_value, decrements);
+            assert!(rate <= p && rate >= 0.9 * p, "{} {}", p, rate);
+        }
+        // More decrements per insert give more zeros, and a lower rate
+        assert!(
+            stable_false_positive_rate(1000, 7, 3, 30) < stable_false_positive_rate(1000, 7, 3, 26)
+        );
+        assert_eq!(stable_decrement_count(10, 7, 255, 0.01), 10);
+        assert_eq!(stable_decrement_count(0, 7, 3, 0.01), 1);
+        assert!(stable_max_cell_value(0, 7, 0.01) >= 1);
+    }
+
+    #[test]
+    /// Test that the cardinality estimate inverts the expected number of bits set
+    fn test_estimate_cardinality() {
+        assert_eq!(estimate_cardinality(959, 7, 0), 0.0);
+        // After n inserts, m * (1 - e^(-k*n/m)) bits are expected to be set
+        let bits_set: f64 = 959.0 * (1.0 - (-7.0 * 100.0 / 959.0_f64).exp());
+        let estimate: f64 = estimate_cardinality(959, 7, bits_set.round() as u64);
+        assert!((estimate - 100.0).abs() < 1.0, "{}", estimate);
+        assert!(estimate_cardinality(959, 7, 959).is_finite());
+        assert_eq!(
+            estimate_cardinality(959, 7, 959),
+            estimate_cardinality(959, 7, 958)
+        );
+    }
+
+    #[test]
+    /// Test that the standard error is zero for an empty filter, and grows with the load
+    fn test_cardinality_standard_error() {
+        assert_eq!(cardinality_standard_error(959, 7, 0.0), 0.0);
+        let error: f64 = cardinality_standard_error(959, 7, 100.0);
+        assert!(error > 2.0 && error < 4.0, "{}", error);
+        assert!(cardinality_standard_error(959, 7, 200.0) > error);
+    }
+
+    #[test]
+    /// Test that the estimated false positive rate matches the expected one at the expected
+    /// fill ratio
+    fn test_estimate_false_positive_rate() {
+        assert_eq!(estimate_false_positive_rate(7, 0.0), 0.0);
+        assert_eq!(estimate_false_positive_rate(7, 1.0), 1.0);
+        let fill_ratio: f64 = 1.0 - (-7.0 * 100.0 / 959.0_f64).exp();
+        assert_eq!(
+            estimate_false_positive_rate(7, fill_ratio),
+            expected_false_positive_rate(959, 7, 100)
+        );
+    }
+
+    #[test]
+    /// Test rounding lengths up and down to words, cache lines and powers of two
+    fn test_rounding() {
+        assert_eq!(round_len_up(959, Rounding::Exact), 959);
+        assert_eq!(round_len_up(959, Rounding::Words), 960);
+        assert_eq!(round_len_up(960, Rounding::Words), 960);
+        assert_eq!(round_len_up(959, Rounding::CacheLines), 1024);
+        assert_eq!(round_len_up(959, Rounding::PowerOfTwo), 1024);
+        assert_eq!(round_len_up(1024, Rounding::PowerOfTwo), 1024);
+        assert_eq!(round_len_down(959, Rounding::Exact), 959);
+        assert_eq!(round_len_down(959, Rounding::Words), 896);
+        assert_eq!(round_len_down(959, Rounding::CacheLines), 512);
+        assert_eq!(round_len_down(959, Rounding::PowerOfTwo), 512);
+        assert_eq!(round_len_down(1024, Rounding::PowerOfTwo), 1024);
+        assert_eq!(round_len_down(63, Rounding::Words), 0);
+        assert_eq!(round_len_down(0, Rounding::PowerOfTwo), 0);
+        assert_eq!(round_len_up(959, Rounding::Foldable(4)), 960);
+        assert_eq!(round_len_up(961, Rounding::Foldable(4)), 976);
+        assert_eq!(round_len_up(959, Rounding::Foldable(0)), 959);
+        assert_eq!(round_len_down(959, Rounding::Foldable(4)), 944);
+        assert_eq!(round_len_down(15, Rounding::Foldable(4)), 0);
+    }
+}
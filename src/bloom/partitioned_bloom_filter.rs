@@ -0,0 +1,686 @@
+/*!
+ * partitioned_bloom_filter.rs
+ *
+ * Implements a partitioned bloom filter:
+ *      PartitionedBloomFilter -- a bloom filter whose bit array is split into k equal slices,
+ *                                with hash i only indexing into slice i. Every item sets exactly
+ *                                one bit per slice, so the bit positions of the k hashes never
+ *                                collide, and the fill of each slice can be reported on its own.
+ *                                Sized with params::optimal_partition_count() and
+ *                                params::optimal_slice_len(). Does not support deletion.
+ *                                Has the set operations and estimates of a BloomFilter, and
+ *                                serde support with the "serde" feature.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::persistence::{ChecksumReader, ChecksumWriter, FilterKind};
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
+use crate::bloom::HashFunction;
+use crate::bloom::HashIndices;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use crate::bloom::SimilarityEstimate;
+use bit_vec::BitVec;
+use std::io::{Read, Write};
+
+/// PartitionedBloomFilter struct:
+///    * bit_arr:             Bit array, holding the k slices one after the other
+///    * slice_len:           Length of each slice
+///    * hash_count:          Hash count, ie number of slices
+///    * false_positive_rate: False positive rate
+///    * expected_inserts:    Expected inserts
+///    * actual_inserts:      Actual inserts
+///    * hasher:              Hash function used to compute indices
+///    * indexing:            How indices are derived from the hash function
+#[derive(Clone)]
+pub struct PartitionedBloomFilter {
+    bit_arr: BitVec,
+    slice_len: u64,
+    hash_count: u64,
+    false_positive_rate: f64,
+    expected_inserts: u64,
+    actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
+}
+
+/// Implementation of a partitioned bloom filter, using a bit array split into slices
+impl PartitionedBloomFilter {
+    /// Getter for the length of the whole bit array, ie of all slices
+    pub fn get_len(&self) -> u64 {
+        return self.slice_len * self.hash_count;
+    }
+
+    /// Getter for slice_len
+    pub fn get_slice_len(&self) -> u64 {
+        return self.slice_len;
+    }
+
+    /// Getter for hash_count
+    pub fn get_hash_count(&self) -> u64 {
+        return self.hash_count;
+    }
+
+    /// Getter for false_positive_rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for expected_inserts
+    pub fn get_expected_inserts(&self) -> u64 {
+        return self.expected_inserts;
+    }
+
+    /// Getter for actual_inserts
+    pub fn get_actual_inserts(&self) -> u64 {
+        return self.actual_inserts;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
+    }
+
+    /// Create a new PartitionedBloomFilter, using the default (Murmur3) hash function
+    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> PartitionedBloomFilter {
+        return PartitionedBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new PartitionedBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+    ) -> Result<PartitionedBloomFilter> {
+        return PartitionedBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new PartitionedBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> PartitionedBloomFilter {
+        return PartitionedBloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new PartitionedBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> PartitionedBloomFilter {
+        match PartitionedBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            hasher,
+            indexing,
+        ) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new PartitionedBloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<PartitionedBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+        } else if expected_inserts < 1 {
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
+        }
+
+        let hash_count: u64 = params::optimal_partition_count(false_positive_rate);
+        let slice_len: u64 =
+            params::optimal_slice_len(expected_inserts, false_positive_rate, hash_count);
+
+        let params: FilterParameters = FilterParameters::new(
+            slice_len * hash_count,
+            hash_count,
+            expected_inserts,
+            false_positive_rate,
+        );
+        return Ok(PartitionedBloomFilter::from_parameters(
+            &params, hasher, indexing,
+        ));
+    }
+
+    /// Create a new PartitionedBloomFilter from already validated parameters, whose length
+    /// is a multiple of the hash count
+    pub(crate) fn from_parameters(
+        params: &FilterParameters,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> PartitionedBloomFilter {
+        return PartitionedBloomFilter {
+            bit_arr: BitVec::from_elem(params.get_len() as usize, false),
+            slice_len: params.get_len() / params.get_hash_count(),
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
+            expected_inserts: params.get_expected_inserts(),
+            actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
+        };
+    }
+
+    /// Insert a new element into the PartitionedBloomFilter
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.slice_len,
+            );
+            for (slice, slice_index) in indices.enumerate() {
+                // Offset the index into the hash's own slice
+                self.bit_arr
+                    .set(slice * self.slice_len as usize + slice_index, true);
+            }
+        });
+        self.actual_inserts += 1;
+    }
+
+    /// Check whether an element is probably in the filter or not
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.slice_len,
+            );
+            for (slice, slice_index) in indices.enumerate() {
+                if !self.bit_arr[slice * self.slice_len as usize + slice_index] {
+                    return false;
+                }
+            }
+            return true;
+        });
+    }
+
+    /// Empty out the PartitionedBloomFilter
+    pub fn empty(&mut self) {
+        self.bit_arr.clear();
+        self.actual_inserts = 0;
+    }
+
+    /// Whether another PartitionedBloomFilter has the same slice length, hash count, hash
+    /// function and indexing scheme, ie whether the same item sets the same bits in both
+    pub fn is_compatible(&self, other: &PartitionedBloomFilter) -> bool {
+        return self.slice_len == other.slice_len
+            && self.hash_count == other.hash_count
+            && self.hasher == other.hasher
+            && self.indexing == other.indexing;
+    }
+
+    /// Merge another PartitionedBloomFilter into this one, so that it contains the items of
+    /// both. actual_inserts is set to the estimated number of distinct items in the union
+    pub fn union_with(&mut self, other: &PartitionedBloomFilter) -> Result<()> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        self.bit_arr.or(&other.bit_arr);
+        self.actual_inserts = self.estimate_cardinality().round() as u64;
+        return Ok(());
+    }
+
+    /// Intersect this PartitionedBloomFilter with another one, so that it (probably) contains
+    /// only the items of both. actual_inserts is set to the estimated number of distinct
+    /// items left
+    pub fn intersect_with(&mut self, other: &PartitionedBloomFilter) -> Result<()> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        self.bit_arr.and(&other.bit_arr);
+        self.actual_inserts = self.estimate_cardinality().round() as u64;
+        return Ok(());
+    }
+
+    /// Return a new PartitionedBloomFilter containing the items of both filters; see
+    /// union_with()
+    pub fn union(&self, other: &PartitionedBloomFilter) -> Result<PartitionedBloomFilter> {
+        let mut bf: PartitionedBloomFilter = self.clone();
+        bf.union_with(other)?;
+        return Ok(bf);
+    }
+
+    /// Return a new PartitionedBloomFilter (probably) containing only the items of both
+    /// filters; see intersect_with()
+    pub fn intersect(&self, other: &PartitionedBloomFilter) -> Result<PartitionedBloomFilter> {
+        let mut bf: PartitionedBloomFilter = self.clone();
+        bf.intersect_with(other)?;
+        return Ok(bf);
+    }
+
+    /// Count the bits set in each slice
+    fn count_slice_ones(&self) -> Vec<u64> {
+        return self.count_slice_ones_of(self.bit_arr.iter());
+    }
+
+    /// Count the bits set in each slice of a bit array laid out like this filter's
+    fn count_slice_ones_of<I: Iterator<Item = bool>>(&self, bits: I) -> Vec<u64> {
+        let mut counts: Vec<u64> = vec![0; self.hash_count as usize];
+        for (index, bit) in bits.enumerate() {
+            if bit {
+                counts[index / self.slice_len as usize] += 1;
+            }
+        }
+        return counts;
+    }
+
+    /// Estimate the number of distinct items from the bits set in each slice. Every item
+    /// sets one bit per slice, so each slice gives its own estimate, -s * ln(1 - X_i/s), and
+    /// they are averaged
+    fn estimate_slices_cardinality(&self, slice_ones: &[u64]) -> f64 {
+        let estimates: f64 = slice_ones
+            .iter()
+            .map(|bits_set| params::estimate_cardinality(self.slice_len, 1, *bits_set))
+            .sum();
+        return estimates / self.hash_count as f64;
+    }
+
+    /// Estimate the number of distinct items inserted, averaging the estimate of each slice
+    pub fn estimate_cardinality(&self) -> f64 {
+        return self.estimate_slices_cardinality(&self.count_slice_ones());
+    }
+
+    /// Estimate the number of distinct items in the union of two compatible filters, from
+    /// the number of bits set in each slice of either of them
+    pub fn estimate_union_cardinality(&self, other: &PartitionedBloomFilter) -> Result<f64> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        let bits_or = self
+            .bit_arr
+            .iter()
+            .zip(other.bit_arr.iter())
+            .map(|(a, b)| a || b);
+        return Ok(self.estimate_slices_cardinality(&self.count_slice_ones_of(bits_or)));
+    }
+
+    /// Estimate the number of distinct items in the intersection of two compatible filters:
+    /// |A ∩ B| = |A| + |B| - |A ∪ B|, floored at 0
+    pub fn estimate_intersection_cardinality(&self, other: &PartitionedBloomFilter) -> Result<f64> {
+        let union: f64 = self.estimate_union_cardinality(other)?;
+        let intersection: f64 = self.estimate_cardinality() + other.estimate_cardinality() - union;
+        return Ok(intersection.max(0.0));
+    }
+
+    /// Estimate the sizes of the intersection and union of the sets held by two compatible
+    /// filters, and their Jaccard similarity with an error bound. The k slices are treated
+    /// as one array of length m with k hashes, which fills like them when they fill evenly
+    pub fn estimate_similarity(
+        &self,
+        other: &PartitionedBloomFilter,
+    ) -> Result<SimilarityEstimate> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        let bits_set_or: u64 = self
+            .bit_arr
+            .storage()
+            .iter()
+            .zip(other.bit_arr.storage().iter())
+            .map(|(a, b)| (a | b).count_ones() as u64)
+            .sum();
+        return Ok(SimilarityEstimate::new(
+            self.get_len(),
+            self.hash_count,
+            self.count_slice_ones().iter().sum(),
+            other.count_slice_ones().iter().sum(),
+            bits_set_or,
+        ));
+    }
+
+    /// Report the fill statistics of each slice. The false positive rate of a slice is its
+    /// fill ratio: the chance that the one bit an item checks in it is set
+    pub fn slice_fill_statistics(&self) -> Vec<FillStatistics> {
+        return self
+            .count_slice_ones()
+            .iter()
+            .map(|bits_set| FillStatistics::new(self.slice_len, 1, *bits_set))
+            .collect();
+    }
+
+    /// Report the bits set, the fill ratio and the estimated current false positive rate,
+    /// which is the product of the fill ratios of the slices
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let slices: Vec<FillStatistics> = self.slice_fill_statistics();
+        return FillStatistics::with_false_positive_rate(
+            self.get_len(),
+            slices.iter().map(|stats| stats.get_bits_set()).sum(),
+            slices
+                .iter()
+                .map(|stats| stats.get_false_positive_rate())
+                .product(),
+        );
+    }
+
+    /// Save the PartitionedBloomFilter to a writer, in the crate's versioned binary format
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut writer = ChecksumWriter::new(writer, FilterKind::Partitioned)?;
+        writer.write_u64(self.get_len())?;
+        writer.write_u64(self.hash_count)?;
+        writer.write_hashing(self.hasher, self.indexing)?;
+        writer.write_u64(self.expected_inserts)?;
+        writer.write_u64(self.actual_inserts)?;
+        writer.write_f64(self.false_positive_rate)?;
+        writer.write_payload(&self.bit_arr.to_bytes())?;
+        return writer.finish();
+    }
+
+    /// Load a PartitionedBloomFilter saved with save(), validating its header and checksum
+    pub fn load<R: Read>(reader: &mut R) -> Result<PartitionedBloomFilter> {
+        let mut reader = ChecksumReader::new(reader, FilterKind::Partitioned)?;
+        let len: u64 = reader.read_u64()?;
+        let hash_count: u64 = reader.read_u64()?;
+        let (hasher, indexing) = reader.read_hashing()?;
+        let expected_inserts: u64 = reader.read_u64()?;
+        let actual_inserts: u64 = reader.read_u64()?;
+        let false_positive_rate: f64 = reader.read_f64()?;
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        PartitionedBloomFilter::validate_stored(&params)?;
+        let bytes: Vec<u8> = reader.read_payload(len.div_ceil(8))?;
+        reader.finish()?;
+        return PartitionedBloomFilter::from_stored(
+            &params,
+            actual_inserts,
+            hasher,
+            indexing,
+            &bytes,
+        );
+    }
+
+    /// Check that stored parameters describe a valid PartitionedBloomFilter
+    // u64::is_multiple_of() needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    fn validate_stored(params: &FilterParameters) -> Result<()> {
+        params.validate()?;
+        if params.get_len() % params.get_hash_count() != 0 {
+            return Err(Error::Corrupt("length is not a multiple of the hash count"));
+        }
+        return Ok(());
+    }
+
+    /// Restore a PartitionedBloomFilter from its stored parameters and bit array, as written
+    /// by save() or serde, checking that they are consistent
+    fn from_stored(
+        params: &FilterParameters,
+        actual_inserts: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+        bytes: &[u8],
+    ) -> Result<PartitionedBloomFilter> {
+        PartitionedBloomFilter::validate_stored(params)?;
+        if bytes.len() as u64 != params.get_len().div_ceil(8) {
+            return Err(Error::Corrupt(
+                "payload length does not match the filter length",
+            ));
+        }
+        let mut bit_arr: BitVec = BitVec::from_bytes(bytes);
+        bit_arr.truncate(params.get_len() as usize);
+        let mut bf: PartitionedBloomFilter =
+            PartitionedBloomFilter::from_parameters(params, hasher, indexing);
+        bf.bit_arr = bit_arr;
+        bf.actual_inserts = actual_inserts;
+        return Ok(bf);
+    }
+}
+
+impl Filter for PartitionedBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        PartitionedBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return PartitionedBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        PartitionedBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return PartitionedBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return PartitionedBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return PartitionedBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return PartitionedBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use crate::bloom::serialization::{ByteBuf, Bytes};
+    use serde::de;
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialized form of a PartitionedBloomFilter, validated before it becomes one
+    #[derive(Deserialize)]
+    #[serde(rename = "PartitionedBloomFilter", deny_unknown_fields)]
+    struct PartitionedBloomFilterRepr {
+        len: u64,
+        hash_count: u64,
+        false_positive_rate: f64,
+        expected_inserts: u64,
+        actual_inserts: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+        bits: ByteBuf,
+    }
+
+    impl Serialize for PartitionedBloomFilter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("PartitionedBloomFilter", 8)?;
+            state.serialize_field("len", &self.get_len())?;
+            state.serialize_field("hash_count", &self.hash_count)?;
+            state.serialize_field("false_positive_rate", &self.false_positive_rate)?;
+            state.serialize_field("expected_inserts", &self.expected_inserts)?;
+            state.serialize_field("actual_inserts", &self.actual_inserts)?;
+            state.serialize_field("hasher", &self.hasher)?;
+            state.serialize_field("indexing", &self.indexing)?;
+            state.serialize_field("bits", &Bytes(&self.bit_arr.to_bytes()))?;
+            return state.end();
+        }
+    }
+
+    impl<'de> Deserialize<'de> for PartitionedBloomFilter {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<PartitionedBloomFilter, D::Error> {
+            let repr: PartitionedBloomFilterRepr =
+                PartitionedBloomFilterRepr::deserialize(deserializer)?;
+            let params: FilterParameters = FilterParameters::new(
+                repr.len,
+                repr.hash_count,
+                repr.expected_inserts,
+                repr.false_positive_rate,
+            );
+            return PartitionedBloomFilter::from_stored(
+                &params,
+                repr.actual_inserts,
+                repr.hasher,
+                repr.indexing,
+                &repr.bits.0,
+            )
+            .map_err(de::Error::custom);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the filter is sized with the partitioned sizing math
+    fn test_new() {
+        let bf: PartitionedBloomFilter = PartitionedBloomFilter::new(1000, 0.01);
+        assert_eq!(bf.get_hash_count(), 7);
+        assert_eq!(bf.get_slice_len(), params::optimal_slice_len(1000, 0.01, 7));
+        assert_eq!(bf.get_len(), 7 * bf.get_slice_len());
+        assert_eq!(bf.bit_arr.len() as u64, bf.get_len());
+        assert_eq!(bf.get_actual_inserts(), 0);
+    }
+
+    #[test]
+    /// Test that every insert sets exactly one bit in each slice
+    fn test_insert_one_bit_per_slice() {
+        for indexing in [IndexingScheme::PerSeed, IndexingScheme::DoubleHashing].iter() {
+            let mut bf: PartitionedBloomFilter =
+                PartitionedBloomFilter::with_hashing(1000, 0.01, HashFunction::Xxh3, *indexing);
+            bf.insert(&"a");
+            assert_eq!(bf.count_slice_ones(), vec![1; 7]);
+            assert!(bf.check(&"a"));
+            assert_eq!(bf.get_actual_inserts(), 1);
+        }
+    }
+
+    #[test]
+    /// Test that the measured false positive rate meets the target, and that the slices fill
+    /// evenly
+    fn test_check() {
+        let mut bf: PartitionedBloomFilter = PartitionedBloomFilter::new(10000, 0.01);
+        for i in 0..10000 {
+            bf.insert(&i);
+        }
+        assert!((0..10000).all(|i| bf.check(&i)));
+        let false_positives: usize = (10000..110000).filter(|i| bf.check(i)).count();
+        assert!(false_positives < 1100, "{}", false_positives);
+        for stats in bf.slice_fill_statistics().iter() {
+            assert!((stats.get_fill_ratio() - 0.52).abs() < 0.02);
+        }
+        let fpr: f64 = bf.fill_statistics().get_false_positive_rate();
+        assert!((fpr - 0.01).abs() < 0.002, "{}", fpr);
+        let estimate: f64 = bf.estimate_cardinality();
+        assert!((estimate - 10000.0).abs() < 300.0, "{}", estimate);
+        bf.empty();
+        assert!(!(0..10000).any(|i| bf.check(&i)));
+    }
+
+    #[test]
+    /// Test that the union, intersection and similarity estimates agree with each other and
+    /// with the true sets
+    fn test_estimate_similarity() {
+        let mut a: PartitionedBloomFilter = PartitionedBloomFilter::new(2000, 0.01);
+        let mut b: PartitionedBloomFilter = PartitionedBloomFilter::new(2000, 0.01);
+        for i in 0..1500 {
+            a.insert(&i);
+        }
+        for i in 500..2000 {
+            b.insert(&i);
+        }
+        // |A ∩ B| = 1000, |A ∪ B| = 2000
+        let union: f64 = a.estimate_union_cardinality(&b).unwrap();
+        assert!((union - 2000.0).abs() < 100.0, "{}", union);
+        let intersection: f64 = a.estimate_intersection_cardinality(&b).unwrap();
+        assert!((intersection - 1000.0).abs() < 100.0, "{}", intersection);
+        let similarity: SimilarityEstimate = a.estimate_similarity(&b).unwrap();
+        assert!((similarity.get_union() - union).abs() < 0.01 * union);
+        assert!((similarity.get_jaccard() - 0.5).abs() < similarity.get_error_bound());
+        assert_eq!(
+            a.estimate_similarity(&PartitionedBloomFilter::new(10, 0.01))
+                .err(),
+            Some(Error::IncompatibleFilters)
+        );
+        assert_eq!(
+            a.estimate_union_cardinality(&PartitionedBloomFilter::new(10, 0.01))
+                .err(),
+            Some(Error::IncompatibleFilters)
+        );
+    }
+
+    #[test]
+    /// Test merging compatible filters
+    fn test_union_intersect() {
+        let mut a: PartitionedBloomFilter = PartitionedBloomFilter::new(1000, 0.01);
+        let mut b: PartitionedBloomFilter = PartitionedBloomFilter::new(1000, 0.01);
+        for i in 0..600 {
+            a.insert(&i);
+        }
+        for i in 400..1000 {
+            b.insert(&i);
+        }
+        let union: PartitionedBloomFilter = a.union(&b).unwrap();
+        assert!((0..1000).all(|i| union.check(&i)));
+        let intersection: PartitionedBloomFilter = a.intersect(&b).unwrap();
+        assert!((400..600).all(|i| intersection.check(&i)));
+        assert!((union.get_actual_inserts() as f64 - 1000.0).abs() < 50.0);
+        // Bits set by chance in both filters make the intersection look larger than it is
+        assert!(intersection.get_actual_inserts() >= 200);
+        assert!(intersection.get_actual_inserts() < 600);
+        assert_eq!(
+            a.union(&PartitionedBloomFilter::new(10, 0.01)).err(),
+            Some(Error::IncompatibleFilters)
+        );
+    }
+
+    #[test]
+    /// Test that a saved filter loads back identically, and corruption is reported
+    fn test_save_load() {
+        let mut bf: PartitionedBloomFilter = PartitionedBloomFilter::new(100, 0.01);
+        for i in 0..100 {
+            bf.insert(&i);
+        }
+        let mut buf: Vec<u8> = Vec::new();
+        bf.save(&mut buf).unwrap();
+        let loaded: PartitionedBloomFilter = PartitionedBloomFilter::load(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.bit_arr, bf.bit_arr);
+        assert_eq!(loaded.get_slice_len(), bf.get_slice_len());
+        assert_eq!(loaded.get_actual_inserts(), 100);
+
+        let mut corrupt: Vec<u8> = buf.clone();
+        let last: usize = corrupt.len() - 5;
+        corrupt[last] ^= 0xff;
+        assert_eq!(
+            PartitionedBloomFilter::load(&mut &corrupt[..]).err(),
+            Some(Error::ChecksumMismatch)
+        );
+    }
+}
@@ -0,0 +1,367 @@
+/*!
+ * persistence.rs
+ *
+ * Implements the building blocks of the versioned binary on-disk format shared by the filter
+ * types:
+ *      FilterKind       -- the type of filter stored in a file.
+ *      ChecksumWriter   -- wraps a std::io::Write, encoding fields little-endian and keeping a
+ *                          running CRC-32 of everything written.
+ *      ChecksumReader   -- wraps a std::io::Read, decoding fields and verifying the CRC-32.
+ *      read_generations -- reads the BloomFilter generations of a windowed filter.
+ *
+ * Every file starts with a header of:
+ *      magic:   4 bytes, "BLMF"
+ *      version: u16, the format version (FORMAT_VERSION)
+ *      kind:    u8, the FilterKind
+ * followed by a kind-specific body, and ends with the CRC-32 (u32) of all of the preceding
+ * bytes. All integers are little-endian, floats are stored as their IEEE 754 bits, and
+ * durations as their whole seconds (u64) then nanoseconds (u64).
+ *
+ * Version 2 adds the time window of a GenerationalBloomFilter, and drops the unused indexing
+ * scheme from the header of a BinaryFuseFilter. Version 1 files, which only differ there, can
+ * still be read.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::BloomFilter;
+use crate::bloom::Error;
+use crate::bloom::HashFunction;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Result;
+use crate::bloom::SipHasher;
+use crc32fast::Hasher;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// Magic bytes at the start of every file
+pub(crate) const MAGIC: [u8; 4] = *b"BLMF";
+/// Current version of the on-disk format
+pub(crate) const FORMAT_VERSION: u16 = 2;
+/// Oldest version of the on-disk format which can still be read
+pub(crate) const MIN_FORMAT_VERSION: u16 = 1;
+
+/// The type of filter stored in a file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum FilterKind {
+    Bloom = 0,
+    Generational = 1,
+    Bigenerational = 2,
+    A2Buffering = 3,
+    Partitioned = 4,
+    BinaryFuse = 5,
+}
+
+/// Writer that encodes fields and keeps a running checksum:
+///    * inner:    Underlying writer
+///    * checksum: CRC-32 of everything written so far
+pub(crate) struct ChecksumWriter<'a, W: Write> {
+    inner: &'a mut W,
+    checksum: Hasher,
+}
+
+/// Reader that decodes fields and keeps a running checksum:
+///    * inner:    Underlying reader
+///    * checksum: CRC-32 of everything read so far
+///    * version:  Format version of the file being read
+pub(crate) struct ChecksumReader<'a, R: Read> {
+    inner: &'a mut R,
+    checksum: Hasher,
+    version: u16,
+}
+
+impl<'a, W: Write> ChecksumWriter<'a, W> {
+    /// Create a new ChecksumWriter and write the header for the given kind of filter
+    pub(crate) fn new(inner: &'a mut W, kind: FilterKind) -> Result<ChecksumWriter<'a, W>> {
+        let mut writer = ChecksumWriter {
+            inner: inner,
+            checksum: Hasher::new(),
+        };
+        writer.write_bytes(&MAGIC)?;
+        writer.write_bytes(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_u8(kind as u8)?;
+        return Ok(writer);
+    }
+
+    /// Write raw bytes
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.inner.write_all(bytes)?;
+        self.checksum.update(bytes);
+        return Ok(());
+    }
+
+    pub(crate) fn write_u8(&mut self, val: u8) -> Result<()> {
+        return self.write_bytes(&[val]);
+    }
+
+    pub(crate) fn write_u64(&mut self, val: u64) -> Result<()> {
+        return self.write_bytes(&val.to_le_bytes());
+    }
+
+    pub(crate) fn write_f64(&mut self, val: f64) -> Result<()> {
+        return self.write_bytes(&val.to_bits().to_le_bytes());
+    }
+
+    pub(crate) fn write_duration(&mut self, val: Duration) -> Result<()> {
+        self.write_u64(val.as_secs())?;
+        return self.write_u64(val.subsec_nanos() as u64);
+    }
+
+    /// Write a length-prefixed byte payload
+    pub(crate) fn write_payload(&mut self, bytes: &[u8]) -> Result<()> {
+        self.write_u64(bytes.len() as u64)?;
+        return self.write_bytes(bytes);
+    }
+
+    /// Write the hash algorithm and its seed (the SipHash keys, or zeroes)
+    pub(crate) fn write_hasher(&mut self, hasher: HashFunction) -> Result<()> {
+        let (algorithm, k0, k1): (u8, u64, u64) = match hasher {
+            HashFunction::Murmur3 => (0, 0, 0),
+            HashFunction::Xxh3 => (1, 0, 0),
+            HashFunction::SipHash(sip) => {
+                let (k0, k1) = sip.get_keys();
+                (2, k0, k1)
+            }
+        };
+        self.write_u8(algorithm)?;
+        self.write_u64(k0)?;
+        return self.write_u64(k1);
+    }
+
+    /// Write the hash algorithm, its seed and the indexing scheme
+    pub(crate) fn write_hashing(
+        &mut self,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<()> {
+        self.write_hasher(hasher)?;
+        return self.write_u8(match indexing {
+            IndexingScheme::PerSeed => 0,
+            IndexingScheme::DoubleHashing => 1,
+        });
+    }
+
+    /// Write the checksum of everything written, and flush the underlying writer
+    pub(crate) fn finish(self) -> Result<()> {
+        self.inner
+            .write_all(&self.checksum.finalize().to_le_bytes())?;
+        self.inner.flush()?;
+        return Ok(());
+    }
+}
+
+impl<'a, R: Read> ChecksumReader<'a, R> {
+    /// Create a new ChecksumReader, reading and validating the header for the given kind
+    pub(crate) fn new(inner: &'a mut R, kind: FilterKind) -> Result<ChecksumReader<'a, R>> {
+        let mut reader = ChecksumReader {
+            inner: inner,
+            checksum: Hasher::new(),
+            version: FORMAT_VERSION,
+        };
+        let mut magic: [u8; 4] = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        let mut version: [u8; 2] = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version: u16 = u16::from_le_bytes(version);
+        if !(MIN_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        reader.version = version;
+        let stored_kind: u8 = reader.read_u8()?;
+        if stored_kind != kind as u8 {
+            return Err(Error::Corrupt("unexpected filter kind"));
+        }
+        return Ok(reader);
+    }
+
+    /// Getter for version
+    pub(crate) fn get_version(&self) -> u16 {
+        return self.version;
+    }
+
+    /// Fill buf with raw bytes
+    pub(crate) fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact(buf)?;
+        self.checksum.update(buf);
+        return Ok(());
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8> {
+        let mut buf: [u8; 1] = [0; 1];
+        self.read_exact(&mut buf)?;
+        return Ok(buf[0]);
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64> {
+        let mut buf: [u8; 8] = [0; 8];
+        self.read_exact(&mut buf)?;
+        return Ok(u64::from_le_bytes(buf));
+    }
+
+    pub(crate) fn read_f64(&mut self) -> Result<f64> {
+        return Ok(f64::from_bits(self.read_u64()?));
+    }
+
+    pub(crate) fn read_duration(&mut self) -> Result<Duration> {
+        let secs: u64 = self.read_u64()?;
+        let nanos: u64 = self.read_u64()?;
+        if nanos >= 1_000_000_000 {
+            return Err(Error::Corrupt("duration nanoseconds out of range"));
+        }
+        return Ok(Duration::new(secs, nanos as u32));
+    }
+
+    /// Read a length-prefixed byte payload, which must be exactly expected_len bytes long
+    pub(crate) fn read_payload(&mut self, expected_len: u64) -> Result<Vec<u8>> {
+        self.read_payload_len(expected_len)?;
+        // Read incrementally rather than allocating up front, so a corrupt length fails with
+        // an EOF instead of a huge allocation
+        let mut payload: Vec<u8> = Vec::new();
+        (&mut self.inner)
+            .take(expected_len)
+            .read_to_end(&mut payload)?;
+        if payload.len() as u64 != expected_len {
+            return Err(Error::Io(std::io::ErrorKind::UnexpectedEof));
+        }
+        self.checksum.update(&payload);
+        return Ok(payload);
+    }
+
+    /// Read the length prefix of a byte payload, which must be expected_len
+    pub(crate) fn read_payload_len(&mut self, expected_len: u64) -> Result<()> {
+        if self.read_u64()? != expected_len {
+            return Err(Error::Corrupt(
+                "payload length does not match the filter length",
+            ));
+        }
+        return Ok(());
+    }
+
+    /// Read the hash algorithm and its seed
+    pub(crate) fn read_hasher(&mut self) -> Result<HashFunction> {
+        let algorithm: u8 = self.read_u8()?;
+        let k0: u64 = self.read_u64()?;
+        let k1: u64 = self.read_u64()?;
+        let hasher: HashFunction = match algorithm {
+            0 => HashFunction::Murmur3,
+            1 => HashFunction::Xxh3,
+            2 => HashFunction::SipHash(SipHasher::new(k0, k1)),
+            _ => return Err(Error::Corrupt("unknown hash algorithm")),
+        };
+        return Ok(hasher);
+    }
+
+    /// Read an indexing scheme
+    pub(crate) fn read_indexing(&mut self) -> Result<IndexingScheme> {
+        return match self.read_u8()? {
+            0 => Ok(IndexingScheme::PerSeed),
+            1 => Ok(IndexingScheme::DoubleHashing),
+            _ => Err(Error::Corrupt("unknown indexing scheme")),
+        };
+    }
+
+    /// Read the hash algorithm, its seed and the indexing scheme
+    pub(crate) fn read_hashing(&mut self) -> Result<(HashFunction, IndexingScheme)> {
+        let hasher: HashFunction = self.read_hasher()?;
+        let indexing: IndexingScheme = self.read_indexing()?;
+        return Ok((hasher, indexing));
+    }
+
+    /// Read the stored checksum and compare it with the checksum of everything read
+    pub(crate) fn finish(self) -> Result<()> {
+        let mut stored: [u8; 4] = [0; 4];
+        self.inner.read_exact(&mut stored)?;
+        if u32::from_le_bytes(stored) != self.checksum.finalize() {
+            return Err(Error::ChecksumMismatch);
+        }
+        return Ok(());
+    }
+}
+
+/// Read count BloomFilter generations, written one after the other with write_body()
+pub(crate) fn read_generations<R: Read>(
+    reader: &mut ChecksumReader<R>,
+    count: u64,
+) -> Result<Vec<BloomFilter>> {
+    let mut generations: Vec<BloomFilter> = Vec::new();
+    for _ in 0..count {
+        generations.push(BloomFilter::read_body(reader)?);
+    }
+    return Ok(generations);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that fields round-trip, and the header and checksum are validated
+    fn test_round_trip() {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut writer = ChecksumWriter::new(&mut buf, FilterKind::Bloom).unwrap();
+        writer.write_u64(42).unwrap();
+        writer.write_f64(0.01).unwrap();
+        writer
+            .write_hashing(
+                HashFunction::SipHash(SipHasher::new(1, 2)),
+                IndexingScheme::PerSeed,
+            )
+            .unwrap();
+        writer.write_payload(&[1, 2, 3]).unwrap();
+        writer.write_duration(Duration::new(90, 500)).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(&buf[..7], b"BLMF\x02\x00\x00");
+
+        let mut slice: &[u8] = &buf;
+        let mut reader = ChecksumReader::new(&mut slice, FilterKind::Bloom).unwrap();
+        assert_eq!(reader.read_u64().unwrap(), 42);
+        assert_eq!(reader.read_f64().unwrap(), 0.01);
+        assert_eq!(
+            reader.read_hashing().unwrap(),
+            (
+                HashFunction::SipHash(SipHasher::new(1, 2)),
+                IndexingScheme::PerSeed
+            )
+        );
+        assert_eq!(reader.read_payload(3).unwrap(), vec![1, 2, 3]);
+        assert_eq!(reader.read_duration().unwrap(), Duration::new(90, 500));
+        assert_eq!(reader.get_version(), FORMAT_VERSION);
+        reader.finish().unwrap();
+        assert!(slice.is_empty());
+    }
+
+    #[test]
+    /// Test that a bad magic, version or checksum is reported
+    fn test_invalid_header_and_checksum() {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut writer = ChecksumWriter::new(&mut buf, FilterKind::Bloom).unwrap();
+        writer.write_u64(42).unwrap();
+        writer.finish().unwrap();
+
+        let mut bad_magic: Vec<u8> = buf.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            ChecksumReader::new(&mut &bad_magic[..], FilterKind::Bloom).err(),
+            Some(Error::InvalidMagic)
+        );
+        let mut bad_version: Vec<u8> = buf.clone();
+        bad_version[4] = 9;
+        assert_eq!(
+            ChecksumReader::new(&mut &bad_version[..], FilterKind::Bloom).err(),
+            Some(Error::UnsupportedVersion(9))
+        );
+        bad_version[4] = 0;
+        assert_eq!(
+            ChecksumReader::new(&mut &bad_version[..], FilterKind::Bloom).err(),
+            Some(Error::UnsupportedVersion(0))
+        );
+        let mut bad_body: Vec<u8> = buf.clone();
+        bad_body[7] = 43;
+        let mut slice: &[u8] = &bad_body;
+        let mut reader = ChecksumReader::new(&mut slice, FilterKind::Bloom).unwrap();
+        assert_eq!(reader.read_u64().unwrap(), 43);
+        assert_eq!(reader.finish().err(), Some(Error::ChecksumMismatch));
+    }
+}
@@ -0,0 +1,792 @@
+/*!
+ * quotient_filter.rs
+ *
+ * Implements a quotient filter (Bender et al., 2012):
+ *      QuotientFilter -- stores a (q + r)-bit fingerprint of each item in a table of 2^q slots.
+ *                        The high q bits (the quotient) pick the item's canonical slot, and the
+ *                        low r bits (the remainder) are stored in it, or in the next free slot,
+ *                        with three metadata bits per slot recording how remainders were shifted.
+ *                        Since the full fingerprints can be recovered from the table, it supports
+ *                        deletion, doubling in place and merging without the original items.
+ *                        Inserts fail with Error::FilterFull once every slot is used.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::BloomHasher;
+use crate::bloom::DeletableFilter;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::HashFunction;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use std::collections::VecDeque;
+
+/// Largest supported remainder size, in bits, so that a slot fits in a 64-bit word
+pub const MAX_REMAINDER_BITS: u32 = 61;
+/// Number of metadata bits per slot
+const FLAG_BITS: u32 = 3;
+/// Mask covering the metadata bits of a slot
+const FLAGS: u64 = 0b111;
+/// Set on slot i when some item has quotient i
+const OCCUPIED: u64 = 0b001;
+/// Set when the slot continues the run of remainders of the slot before it
+const CONTINUATION: u64 = 0b010;
+/// Set when the remainder is not in its canonical slot
+const SHIFTED: u64 = 0b100;
+
+/// QuotientFilter struct:
+///    * slots:               Slots, bit-packed as (remainder << 3 | metadata) each
+///    * quotient_bits:       Quotient size in bits, there are 2^quotient_bits slots
+///    * remainder_bits:      Remainder size in bits
+///    * false_positive_rate: False positive rate at expected_inserts
+///    * expected_inserts:    Expected inserts
+///    * actual_inserts:      Actual inserts, less deletions
+///    * hasher:              Hash function used to derive the fingerprints
+#[derive(Clone)]
+pub struct QuotientFilter {
+    slots: Vec<u64>,
+    quotient_bits: u32,
+    remainder_bits: u32,
+    false_positive_rate: f64,
+    expected_inserts: u64,
+    actual_inserts: u64,
+    hasher: HashFunction,
+}
+
+/// Implementation of a quotient filter
+impl QuotientFilter {
+    /// Getter for the number of slots
+    pub fn get_len(&self) -> u64 {
+        return 1 << self.quotient_bits;
+    }
+
+    /// Getter for quotient_bits
+    pub fn get_quotient_bits(&self) -> u32 {
+        return self.quotient_bits;
+    }
+
+    /// Getter for remainder_bits
+    pub fn get_remainder_bits(&self) -> u32 {
+        return self.remainder_bits;
+    }
+
+    /// Getter for the hash count: a single fingerprint per item, always 1
+    pub fn get_hash_count(&self) -> u64 {
+        return 1;
+    }
+
+    /// Getter for false_positive_rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for expected_inserts
+    pub fn get_expected_inserts(&self) -> u64 {
+        return self.expected_inserts;
+    }
+
+    /// Getter for actual_inserts
+    pub fn get_actual_inserts(&self) -> u64 {
+        return self.actual_inserts;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Create a new QuotientFilter, using the default (Murmur3) hash function
+    pub fn new(expected_inserts: u64, false_positive_rate: f64) -> QuotientFilter {
+        return QuotientFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new QuotientFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(expected_inserts: u64, false_positive_rate: f64) -> Result<QuotientFilter> {
+        return QuotientFilter::try_with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new QuotientFilter, using the given hash function
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> QuotientFilter {
+        match QuotientFilter::try_with_hasher(expected_inserts, false_positive_rate, hasher) {
+            Ok(qf) => return qf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new QuotientFilter, using the given hash function. The remainders are sized
+    /// with params::quotient_filter_remainder_bits().
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> Result<QuotientFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+        } else if expected_inserts < 1 {
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
+        }
+        let remainder_bits: u32 =
+            params::quotient_filter_remainder_bits(expected_inserts, false_positive_rate);
+        return QuotientFilter::try_with_layout(expected_inserts, remainder_bits, hasher);
+    }
+
+    /// Create a new QuotientFilter with the given remainder size, using the given hash function.
+    /// The number of slots is sized with params::quotient_filter_quotient_bits()
+    pub fn with_layout(
+        expected_inserts: u64,
+        remainder_bits: u32,
+        hasher: HashFunction,
+    ) -> QuotientFilter {
+        match QuotientFilter::try_with_layout(expected_inserts, remainder_bits, hasher) {
+            Ok(qf) => return qf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new QuotientFilter with the given remainder size, using the given hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_layout(
+        expected_inserts: u64,
+        remainder_bits: u32,
+        hasher: HashFunction,
+    ) -> Result<QuotientFilter> {
+        if expected_inserts < 1 {
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
+        }
+        let quotient_bits: u32 = params::quotient_filter_quotient_bits(expected_inserts);
+        if !(1..=MAX_REMAINDER_BITS.min(64 - quotient_bits.min(64))).contains(&remainder_bits) {
+            return Err(Error::InvalidRemainderBits(remainder_bits));
+        }
+        return Ok(QuotientFilter::from_parameters(
+            quotient_bits,
+            remainder_bits,
+            expected_inserts,
+            hasher,
+        ));
+    }
+
+    /// Create an empty QuotientFilter of 2^quotient_bits slots. The parameters must already
+    /// have been validated
+    fn from_parameters(
+        quotient_bits: u32,
+        remainder_bits: u32,
+        expected_inserts: u64,
+        hasher: HashFunction,
+    ) -> QuotientFilter {
+        let total_bits: u64 = (1 << quotient_bits) * (remainder_bits + FLAG_BITS) as u64;
+        return QuotientFilter {
+            slots: vec![0; total_bits.div_ceil(64) as usize],
+            quotient_bits: quotient_bits,
+            remainder_bits: remainder_bits,
+            false_positive_rate: params::quotient_filter_false_positive_rate(
+                quotient_bits,
+                remainder_bits,
+                expected_inserts,
+            ),
+            expected_inserts: expected_inserts,
+            actual_inserts: 0,
+            hasher: hasher,
+        };
+    }
+
+    /// Read the given slot, as (remainder << 3 | metadata)
+    fn get_slot(&self, index: u64) -> u64 {
+        let width: u64 = (self.remainder_bits + FLAG_BITS) as u64;
+        let bit: u64 = index * width;
+        let (word, offset): (usize, u64) = ((bit / 64) as usize, bit % 64);
+        let mut value: u64 = self.slots[word] >> offset;
+        if offset + width > 64 {
+            value |= self.slots[word + 1] << (64 - offset);
+        }
+        return value & self.slot_mask();
+    }
+
+    /// Write the given slot, as (remainder << 3 | metadata)
+    fn set_slot(&mut self, index: u64, value: u64) {
+        let width: u64 = (self.remainder_bits + FLAG_BITS) as u64;
+        let mask: u64 = self.slot_mask();
+        let bit: u64 = index * width;
+        let (word, offset): (usize, u64) = ((bit / 64) as usize, bit % 64);
+        self.slots[word] = (self.slots[word] & !(mask << offset)) | (value << offset);
+        if offset + width > 64 {
+            let shift: u64 = 64 - offset;
+            self.slots[word + 1] = (self.slots[word + 1] & !(mask >> shift)) | (value >> shift);
+        }
+    }
+
+    /// Mask covering the bits of a slot
+    fn slot_mask(&self) -> u64 {
+        return u64::MAX >> (64 - self.remainder_bits - FLAG_BITS);
+    }
+
+    /// The slot after the given one, wrapping around the end of the table
+    fn next(&self, index: u64) -> u64 {
+        return (index + 1) & (self.get_len() - 1);
+    }
+
+    /// Split an item's fingerprint into its quotient and its remainder
+    fn fingerprint(&self, bytes: &[u8]) -> (u64, u64) {
+        let digest_val: u64 = self.hasher.hash128(bytes, 0) as u64;
+        return (
+            (digest_val >> self.remainder_bits) & (self.get_len() - 1),
+            digest_val & ((1 << self.remainder_bits) - 1),
+        );
+    }
+
+    /// Find the start of the cluster holding the given (non-empty) slot: the nearest slot at
+    /// or before it whose remainder is in its canonical slot
+    fn cluster_start(&self, index: u64) -> u64 {
+        let mut start: u64 = index;
+        while self.get_slot(start) & SHIFTED != 0 {
+            start = (start + self.get_len() - 1) & (self.get_len() - 1);
+        }
+        return start;
+    }
+
+    /// Decode the fingerprints stored from a cluster start up to the next empty slot, as
+    /// (quotient, remainder) pairs in table order. Runs appear in the order of the occupied
+    /// bits, so each run head belongs to the oldest occupied slot without a run yet
+    fn decode(&self, start: u64) -> Vec<(u64, u64)> {
+        let mut entries: Vec<(u64, u64)> = vec![];
+        let mut pending: VecDeque<u64> = VecDeque::new();
+        let mut quotient: u64 = start;
+        let mut index: u64 = start;
+        loop {
+            let slot: u64 = self.get_slot(index);
+            if slot & FLAGS == 0 {
+                break;
+            }
+            if slot & OCCUPIED != 0 {
+                pending.push_back(index);
+            }
+            if slot & CONTINUATION == 0 {
+                if let Some(run) = pending.pop_front() {
+                    quotient = run;
+                }
+            }
+            entries.push((quotient, slot >> FLAG_BITS));
+            index = self.next(index);
+            if index == start {
+                break;
+            }
+        }
+        return entries;
+    }
+
+    /// Rewrite the len slots from a cluster start with the given entries, sorted by quotient
+    /// from start onwards. Each entry goes in the first free slot at or after its canonical
+    /// slot, and the metadata bits are set to match
+    fn layout(&mut self, start: u64, len: u64, entries: &[(u64, u64)]) {
+        let mask: u64 = self.get_len() - 1;
+        for offset in 0..len {
+            self.set_slot((start + offset) & mask, 0);
+        }
+        let mut position: u64 = 0;
+        let mut previous: Option<u64> = None;
+        for &(quotient, remainder) in entries {
+            let canonical: u64 = quotient.wrapping_sub(start) & mask;
+            let mut flags: u64 = 0;
+            if previous == Some(quotient) {
+                flags |= CONTINUATION;
+            } else {
+                position = position.max(canonical);
+            }
+            if position != canonical {
+                flags |= SHIFTED;
+            }
+            let index: u64 = (start + position) & mask;
+            let occupied: u64 = self.get_slot(index) & OCCUPIED;
+            self.set_slot(index, remainder << FLAG_BITS | flags | occupied);
+            let canonical_slot: u64 = self.get_slot(quotient);
+            self.set_slot(quotient, canonical_slot | OCCUPIED);
+            previous = Some(quotient);
+            position += 1;
+        }
+    }
+
+    /// Add a fingerprint to the table, keeping each run sorted by remainder. The table must
+    /// have a free slot
+    fn insert_fingerprint(&mut self, quotient: u64, remainder: u64) {
+        if self.get_slot(quotient) & FLAGS == 0 {
+            self.set_slot(quotient, remainder << FLAG_BITS | OCCUPIED);
+            return;
+        }
+        let start: u64 = self.cluster_start(quotient);
+        let mut entries: Vec<(u64, u64)> = self.decode(start);
+        let len: u64 = entries.len() as u64;
+        let mask: u64 = self.get_len() - 1;
+        let target: u64 = quotient.wrapping_sub(start) & mask;
+        let at: usize = entries
+            .iter()
+            .position(|&(q, r)| {
+                let offset: u64 = q.wrapping_sub(start) & mask;
+                return offset > target || (offset == target && r > remainder);
+            })
+            .unwrap_or(entries.len());
+        entries.insert(at, (quotient, remainder));
+        self.layout(start, len + 1, &entries);
+    }
+
+    /// Remove one copy of a fingerprint from the table. Returns false if it is not there
+    fn remove_fingerprint(&mut self, quotient: u64, remainder: u64) -> bool {
+        if self.get_slot(quotient) & OCCUPIED == 0 {
+            return false;
+        }
+        let start: u64 = self.cluster_start(quotient);
+        let mut entries: Vec<(u64, u64)> = self.decode(start);
+        let len: u64 = entries.len() as u64;
+        match entries.iter().position(|&e| e == (quotient, remainder)) {
+            Some(at) => {
+                entries.remove(at);
+                self.layout(start, len, &entries);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    /// Recover every stored fingerprint, as (quotient << r | remainder), by decoding each
+    /// cluster which follows an empty slot (or the whole table, if it has none)
+    fn fingerprints(&self) -> Vec<u64> {
+        let mut entries: Vec<(u64, u64)> = vec![];
+        if (0..self.get_len()).all(|index| self.get_slot(index) & FLAGS != 0) {
+            entries = self.decode(self.cluster_start(0));
+        } else {
+            let mut previous: u64 = self.get_slot(self.get_len() - 1);
+            for index in 0..self.get_len() {
+                let slot: u64 = self.get_slot(index);
+                if slot & FLAGS != 0 && previous & FLAGS == 0 {
+                    entries.extend(self.decode(index));
+                }
+                previous = slot;
+            }
+        }
+        return entries
+            .into_iter()
+            .map(|(quotient, remainder)| quotient << self.remainder_bits | remainder)
+            .collect();
+    }
+
+    /// Insert a new element into the QuotientFilter.
+    /// Panics if the filter is full, see .try_insert()
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        if let Err(e) = self.try_insert(item) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Insert a new element into the QuotientFilter, as with .insert().
+    /// Returns Error::FilterFull, leaving the filter unchanged, if every slot is used;
+    /// .double_capacity() makes room
+    pub fn try_insert<T: Item + ?Sized>(&mut self, item: &T) -> Result<()> {
+        if self.actual_inserts >= self.get_len() {
+            return Err(Error::FilterFull);
+        }
+        let (quotient, remainder): (u64, u64) = item.with_bytes(|bytes| self.fingerprint(bytes));
+        self.insert_fingerprint(quotient, remainder);
+        self.actual_inserts += 1;
+        return Ok(());
+    }
+
+    /// Check whether an element is probably in the filter or not
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return self.count(item) > 0;
+    }
+
+    /// Count how many times an element was probably inserted, ie the number of copies of its
+    /// fingerprint in the table
+    pub fn count<T: Item + ?Sized>(&self, item: &T) -> u64 {
+        let (quotient, remainder): (u64, u64) = item.with_bytes(|bytes| self.fingerprint(bytes));
+        if self.get_slot(quotient) & OCCUPIED == 0 {
+            return 0;
+        }
+        return self
+            .decode(self.cluster_start(quotient))
+            .into_iter()
+            .filter(|&e| e == (quotient, remainder))
+            .count() as u64;
+    }
+
+    /// Delete one copy of an element from the QuotientFilter. Deleting an item which was
+    /// never inserted can remove another item sharing its fingerprint
+    pub fn delete<T: Item + ?Sized>(&mut self, item: &T) {
+        if let Err(e) = self.try_delete(item) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Delete one copy of an element from the QuotientFilter, as with .delete().
+    /// Does nothing if the element is not in the filter
+    pub fn try_delete<T: Item + ?Sized>(&mut self, item: &T) -> Result<()> {
+        let (quotient, remainder): (u64, u64) = item.with_bytes(|bytes| self.fingerprint(bytes));
+        if self.remove_fingerprint(quotient, remainder) {
+            self.actual_inserts -= 1;
+        }
+        return Ok(());
+    }
+
+    /// Double the number of slots in place, by moving the top bit of each remainder into its
+    /// quotient. The fingerprints are unchanged, so the filter still holds the same items,
+    /// and expected_inserts doubles; the false positive rate at expected_inserts doubles too.
+    /// Returns an error, leaving the filter unchanged, if the remainders are a single bit
+    pub fn double_capacity(&mut self) -> Result<()> {
+        if self.remainder_bits < 2 {
+            return Err(Error::InvalidRemainderBits(self.remainder_bits - 1));
+        }
+        let mut doubled: QuotientFilter = QuotientFilter::from_parameters(
+            self.quotient_bits + 1,
+            self.remainder_bits - 1,
+            self.expected_inserts * 2,
+            self.hasher,
+        );
+        doubled.add_fingerprints(&self.fingerprints());
+        *self = doubled;
+        return Ok(());
+    }
+
+    /// Insert full fingerprints, as (quotient << r | remainder), into the table. There must
+    /// be room for them
+    fn add_fingerprints(&mut self, fingerprints: &[u64]) {
+        let remainder_mask: u64 = (1 << self.remainder_bits) - 1;
+        for fingerprint in fingerprints {
+            self.insert_fingerprint(
+                fingerprint >> self.remainder_bits,
+                fingerprint & remainder_mask,
+            );
+        }
+        self.actual_inserts += fingerprints.len() as u64;
+    }
+
+    /// Check whether another QuotientFilter can be merged into this one: the fingerprints must
+    /// be derived the same way, ie with the same hash function and the same total size.
+    /// The number of slots can differ
+    pub fn is_compatible(&self, other: &QuotientFilter) -> bool {
+        return self.quotient_bits + self.remainder_bits
+            == other.quotient_bits + other.remainder_bits
+            && self.hasher == other.hasher;
+    }
+
+    /// Merge another QuotientFilter into this one, so that it contains the items of both.
+    /// The merged table has room for the expected inserts of both, as sized by
+    /// params::quotient_filter_quotient_bits(), and at least as many slots as either.
+    /// Returns an error, leaving the filter unchanged, if the filters are incompatible or
+    /// the fingerprints are too short for a table that large
+    pub fn merge_with(&mut self, other: &QuotientFilter) -> Result<()> {
+        if !self.is_compatible(other) {
+            return Err(Error::IncompatibleFilters);
+        }
+        let fingerprint_bits: u32 = self.quotient_bits + self.remainder_bits;
+        let expected_inserts: u64 = self.expected_inserts + other.expected_inserts;
+        let quotient_bits: u32 = params::quotient_filter_quotient_bits(expected_inserts)
+            .max(self.quotient_bits)
+            .max(other.quotient_bits);
+        if quotient_bits >= fingerprint_bits {
+            return Err(Error::InvalidRemainderBits(0));
+        }
+        let mut merged: QuotientFilter = QuotientFilter::from_parameters(
+            quotient_bits,
+            fingerprint_bits - quotient_bits,
+            expected_inserts,
+            self.hasher,
+        );
+        if self.actual_inserts + other.actual_inserts > merged.get_len() {
+            return Err(Error::FilterFull);
+        }
+        merged.add_fingerprints(&self.fingerprints());
+        merged.add_fingerprints(&other.fingerprints());
+        *self = merged;
+        return Ok(());
+    }
+
+    /// Return a new QuotientFilter containing the items of both filters; see merge_with()
+    pub fn merge(&self, other: &QuotientFilter) -> Result<QuotientFilter> {
+        let mut qf: QuotientFilter = self.clone();
+        qf.merge_with(other)?;
+        return Ok(qf);
+    }
+
+    /// Empty out the QuotientFilter
+    pub fn empty(&mut self) {
+        self.slots = vec![0; self.slots.len()];
+        self.actual_inserts = 0;
+    }
+
+    /// Report the used slots, the load factor and the estimated current false positive rate,
+    /// from the number of fingerprints stored
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let used: u64 = (0..self.get_len())
+            .filter(|index| self.get_slot(*index) & FLAGS != 0)
+            .count() as u64;
+        return FillStatistics::with_false_positive_rate(
+            self.get_len(),
+            used,
+            params::quotient_filter_false_positive_rate(
+                self.quotient_bits,
+                self.remainder_bits,
+                self.actual_inserts,
+            ),
+        );
+    }
+}
+
+impl Filter for QuotientFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        QuotientFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return QuotientFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        QuotientFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return QuotientFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return QuotientFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return QuotientFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return QuotientFilter::get_actual_inserts(self);
+    }
+}
+
+impl DeletableFilter for QuotientFilter {
+    fn try_delete_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        return QuotientFilter::try_delete(self, bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the metadata invariants of every slot, and that decoding the table gives back
+    /// exactly the given fingerprints
+    fn assert_consistent(qf: &QuotientFilter, expected: &[u64]) {
+        for index in 0..qf.get_len() {
+            let slot: u64 = qf.get_slot(index);
+            if slot & CONTINUATION != 0 {
+                assert!(
+                    slot & SHIFTED != 0,
+                    "continuation in canonical slot {}",
+                    index
+                );
+            }
+        }
+        let mut fingerprints: Vec<u64> = qf.fingerprints();
+        let mut expected: Vec<u64> = expected.to_vec();
+        fingerprints.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(fingerprints, expected);
+    }
+
+    #[test]
+    /// Test that the filter is sized from (n, p)
+    fn test_new() {
+        let qf: QuotientFilter = QuotientFilter::new(1000, 0.01);
+        assert_eq!(qf.get_quotient_bits(), 11);
+        assert_eq!(qf.get_remainder_bits(), 6);
+        assert_eq!(qf.get_len(), 2048);
+        assert_eq!(qf.slots.len(), 288);
+        assert!(qf.get_false_positive_rate() <= 0.01);
+        assert_eq!(qf.get_hash_count(), 1);
+        assert_eq!(qf.get_actual_inserts(), 0);
+    }
+
+    #[test]
+    /// Test that colliding quotients are shifted into sorted runs, wrapping around the end of
+    /// the table, and that deleting shifts them back
+    fn test_runs() {
+        let mut qf: QuotientFilter = QuotientFilter::with_layout(3, 8, HashFunction::default());
+        assert_eq!(qf.get_len(), 4);
+        let mut expected: Vec<u64> = vec![];
+        for &(quotient, remainder) in &[(3, 9), (3, 2), (2, 7), (0, 5)] {
+            qf.insert_fingerprint(quotient, remainder);
+            expected.push(quotient << 8 | remainder);
+            assert_consistent(&qf, &expected);
+        }
+        assert_eq!(qf.get_slot(2) >> FLAG_BITS, 7);
+        assert_eq!(qf.get_slot(3) >> FLAG_BITS, 2);
+        assert_eq!(qf.get_slot(0) >> FLAG_BITS, 9);
+        assert_eq!(qf.get_slot(0) & FLAGS, OCCUPIED | CONTINUATION | SHIFTED);
+        assert_eq!(qf.get_slot(1) >> FLAG_BITS, 5);
+        assert_eq!(qf.get_slot(1) & FLAGS, SHIFTED);
+
+        assert!(qf.remove_fingerprint(3, 2));
+        assert!(!qf.remove_fingerprint(3, 2));
+        expected.retain(|&f| f != 3 << 8 | 2);
+        assert_consistent(&qf, &expected);
+        assert_eq!(qf.get_slot(3), 9 << FLAG_BITS | OCCUPIED);
+        assert_eq!(qf.get_slot(0), 5 << FLAG_BITS | OCCUPIED);
+        assert_eq!(qf.get_slot(1) & FLAGS, 0);
+    }
+
+    #[test]
+    /// Test insert, check, count and delete through a fixed sequence of inserts, a duplicate
+    /// insert, and deletes of half the items
+    fn test_insert_delete() {
+        let mut qf: QuotientFilter = QuotientFilter::new(1000, 0.001);
+        for i in 0..1000 {
+            qf.insert(&i);
+        }
+        assert!((0..1000).all(|i| qf.check(&i)));
+        assert_eq!(qf.get_actual_inserts(), 1000);
+        let fingerprints: Vec<u64> = qf.fingerprints();
+        assert_eq!(fingerprints.len(), 1000);
+        assert_consistent(&qf, &fingerprints);
+        assert_eq!(qf.fill_statistics().get_bits_set(), 1000);
+
+        qf.insert(&7);
+        assert_eq!(qf.count(&7), 2);
+        qf.delete(&7);
+        qf.delete(&7);
+        assert_eq!(qf.count(&7), 0);
+        for i in 0..500 {
+            qf.delete(&i);
+        }
+        assert!((500..1000).all(|i| qf.check(&i)));
+        assert!((0..500).filter(|i| qf.check(i)).count() < 5);
+        assert_eq!(qf.get_actual_inserts(), 500);
+        assert_eq!(qf.fingerprints().len(), 500);
+        qf.empty();
+        assert_eq!(qf.fill_statistics().get_bits_set(), 0);
+        assert!(!(500..1000).any(|i| qf.check(&i)));
+    }
+
+    #[test]
+    /// Test that the table stays consistent through interleaved inserts and deletes at a high
+    /// load, with long clusters wrapping around the end of the table
+    fn test_interleaved() {
+        let mut qf: QuotientFilter = QuotientFilter::with_layout(40, 4, HashFunction::default());
+        let mut expected: Vec<u64> = vec![];
+        let full_fingerprint = |qf: &QuotientFilter, i: u64| -> u64 {
+            let (quotient, remainder): (u64, u64) = i.with_bytes(|bytes| qf.fingerprint(bytes));
+            return quotient << qf.get_remainder_bits() | remainder;
+        };
+        for round in 0..20u64 {
+            for i in (round * 10)..(round * 10 + 10) {
+                if qf.try_insert(&i).is_ok() {
+                    expected.push(full_fingerprint(&qf, i));
+                }
+            }
+            for i in (round * 10..round * 10 + 10).step_by(3) {
+                let fingerprint: u64 = full_fingerprint(&qf, i);
+                if let Some(at) = expected.iter().position(|&f| f == fingerprint) {
+                    qf.delete(&i);
+                    expected.remove(at);
+                }
+            }
+            assert_consistent(&qf, &expected);
+            assert_eq!(qf.get_actual_inserts(), expected.len() as u64);
+        }
+        // The table filled up, less the deletes of the last round
+        assert!(qf.get_actual_inserts() + 4 >= qf.get_len());
+    }
+
+    #[test]
+    /// Test that every slot can be used, including when the table is full
+    fn test_full() {
+        let mut qf: QuotientFilter = QuotientFilter::with_layout(6, 10, HashFunction::default());
+        for i in 0..qf.get_len() {
+            qf.insert(&i);
+        }
+        assert_eq!(qf.try_insert(&100u64), Err(Error::FilterFull));
+        assert_eq!(qf.fingerprints().len() as u64, qf.get_len());
+        assert!((0..qf.get_len()).all(|i| qf.check(&i)));
+        qf.delete(&0u64);
+        assert!(!qf.check(&0u64));
+        assert!(qf.try_insert(&100u64).is_ok());
+        assert!(qf.check(&100u64));
+    }
+
+    #[test]
+    /// Test that doubling keeps every fingerprint, and fails once the remainder is one bit
+    fn test_double_capacity() {
+        let mut qf: QuotientFilter = QuotientFilter::with_layout(100, 3, HashFunction::default());
+        for i in 0..100 {
+            qf.insert(&i);
+        }
+        let fingerprints: Vec<u64> = qf.fingerprints();
+        qf.double_capacity().unwrap();
+        assert_eq!((qf.get_quotient_bits(), qf.get_remainder_bits()), (9, 2));
+        assert_eq!(qf.get_expected_inserts(), 200);
+        assert_eq!(qf.get_actual_inserts(), 100);
+        assert_consistent(&qf, &fingerprints);
+        assert!((0..100).all(|i| qf.check(&i)));
+        qf.double_capacity().unwrap();
+        assert_eq!(qf.double_capacity(), Err(Error::InvalidRemainderBits(0)));
+        assert_eq!(qf.get_len(), 1024);
+        assert_consistent(&qf, &fingerprints);
+    }
+
+    #[test]
+    /// Test that merging keeps the fingerprints of both filters
+    fn test_merge() {
+        let mut a: QuotientFilter = QuotientFilter::with_layout(100, 8, HashFunction::default());
+        let mut b: QuotientFilter = QuotientFilter::with_layout(400, 6, HashFunction::default());
+        for i in 0..100 {
+            a.insert(&i);
+        }
+        for i in 50..450 {
+            b.insert(&i);
+        }
+        let mut expected: Vec<u64> = a.fingerprints();
+        expected.extend(b.fingerprints());
+        let merged: QuotientFilter = a.merge(&b).unwrap();
+        assert_eq!(merged.get_quotient_bits(), 10);
+        assert_eq!(merged.get_remainder_bits(), 6);
+        assert_eq!(merged.get_actual_inserts(), 500);
+        assert_consistent(&merged, &expected);
+        assert!((0..450).all(|i| merged.check(&i)));
+        assert_eq!(merged.count(&75), 2);
+    }
+
+    #[test]
+    /// Test that invalid parameters are reported
+    fn test_invalid_parameters() {
+        assert_eq!(
+            QuotientFilter::try_new(0, 0.01).err(),
+            Some(Error::InvalidExpectedInserts(0))
+        );
+        assert_eq!(
+            QuotientFilter::try_new(10, 1.0).err(),
+            Some(Error::InvalidFalsePositiveRate(1.0))
+        );
+        assert_eq!(
+            QuotientFilter::try_with_layout(10, 0, HashFunction::default()).err(),
+            Some(Error::InvalidRemainderBits(0))
+        );
+        assert_eq!(
+            QuotientFilter::try_with_layout(10, 62, HashFunction::default()).err(),
+            Some(Error::InvalidRemainderBits(62))
+        );
+        let a: QuotientFilter = QuotientFilter::with_layout(100, 8, HashFunction::default());
+        let b: QuotientFilter = QuotientFilter::with_layout(100, 9, HashFunction::default());
+        assert_eq!(a.merge(&b).err(), Some(Error::IncompatibleFilters));
+    }
+}
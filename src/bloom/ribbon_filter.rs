@@ -0,0 +1,431 @@
+/*!
+ * ribbon_filter.rs
+ *
+ * Implements a static standard ribbon filter (Dillinger & Walzer, 2021):
+ *      RibbonFilter -- an immutable filter built in one go from a set of items. Each item gives a
+ *                      linear equation over GF(2): a 64-bit coefficient row starting at some
+ *                      slot, which must XOR the slots' solution values to the item's
+ *                      fingerprint. The equations are solved on the fly into a banded matrix,
+ *                      then back-substituted. Each block of 64 slots stores r or r - 1 solution
+ *                      columns, so fractional fingerprint sizes give false positive rates
+ *                      between powers of two, at about 1.08 * log2(1/p) bits per item.
+ *                      Does not support insertion or deletion.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::hashing::fmix64;
+use crate::bloom::params;
+use crate::bloom::BloomHasher;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::HashFunction;
+use crate::bloom::Item;
+use crate::bloom::Result;
+
+/// Number of seeds tried before construction gives up
+pub const MAX_ATTEMPTS: u32 = 100;
+/// Largest supported number of solution columns
+pub const MAX_COLUMNS: u32 = 32;
+/// Number of slots per block
+const BLOCK_SLOTS: u64 = params::RIBBON_WIDTH;
+/// Starting state of the sequence of seeds, so that the same items always build the same
+/// filter
+const SEED_STATE: u64 = 0x6a09_e667_f3bc_c908;
+
+/// RibbonFilter struct:
+///    * solution:            Solution columns, block after block, one bit per slot per word
+///    * num_blocks:          Number of blocks of 64 slots
+///    * upper_columns:       Number of solution columns of the upper blocks
+///    * upper_start_block:   First upper block. The blocks before it have one column less
+///    * seed:                Seed mixed into each item's hash, picked during construction
+///    * false_positive_rate: Target false positive rate
+///    * num_items:           Number of distinct items the filter was built from
+///    * hasher:              Hash function used to hash the items
+#[derive(Clone)]
+pub struct RibbonFilter {
+    solution: Vec<u64>,
+    num_blocks: u64,
+    upper_columns: u32,
+    upper_start_block: u64,
+    seed: u64,
+    false_positive_rate: f64,
+    num_items: u64,
+    hasher: HashFunction,
+}
+
+/// Implementation of a static standard ribbon filter
+impl RibbonFilter {
+    /// Getter for the number of slots
+    pub fn get_len(&self) -> u64 {
+        return self.num_blocks * BLOCK_SLOTS;
+    }
+
+    /// Getter for num_blocks
+    pub fn get_num_blocks(&self) -> u64 {
+        return self.num_blocks;
+    }
+
+    /// Getter for upper_columns
+    pub fn get_upper_columns(&self) -> u32 {
+        return self.upper_columns;
+    }
+
+    /// Getter for upper_start_block
+    pub fn get_upper_start_block(&self) -> u64 {
+        return self.upper_start_block;
+    }
+
+    /// Getter for false_positive_rate, the target rate. See fill_statistics() for the rate
+    /// realized by the layout
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for num_items
+    pub fn get_num_items(&self) -> u64 {
+        return self.num_items;
+    }
+
+    /// Getter for the space used per item, in bits
+    pub fn get_bits_per_item(&self) -> f64 {
+        return (self.solution.len() as u64 * 64) as f64 / self.num_items as f64;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Build a new RibbonFilter from the given items, using the default (Murmur3) hash
+    /// function
+    pub fn new<'a, T, I>(items: I, false_positive_rate: f64) -> RibbonFilter
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        return RibbonFilter::with_hasher(items, false_positive_rate, HashFunction::default());
+    }
+
+    /// Build a new RibbonFilter from the given items, using the default (Murmur3) hash
+    /// function. Returns an error if the false positive rate is invalid or construction fails
+    pub fn try_new<'a, T, I>(items: I, false_positive_rate: f64) -> Result<RibbonFilter>
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        return RibbonFilter::try_with_hasher(items, false_positive_rate, HashFunction::default());
+    }
+
+    /// Build a new RibbonFilter from the given items, using the given hash function
+    pub fn with_hasher<'a, T, I>(
+        items: I,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> RibbonFilter
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        match RibbonFilter::try_with_hasher(items, false_positive_rate, hasher) {
+            Ok(rf) => return rf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Build a new RibbonFilter from the given items, using the given hash function.
+    /// The items are read once, and only their hashes are kept. Duplicate items are allowed.
+    /// Returns an error if the false positive rate is not in (2^-32, 1), or
+    /// Error::ConstructionFailed if no seed among MAX_ATTEMPTS gives a solvable system
+    pub fn try_with_hasher<'a, T, I>(
+        items: I,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> Result<RibbonFilter>
+    where
+        T: Item + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        if !(false_positive_rate >= 0.5f64.powi(MAX_COLUMNS as i32) && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+        }
+        let mut keys: Vec<u64> = items
+            .into_iter()
+            .map(|item| item.with_bytes(|bytes| hasher.hash128(bytes, 0) as u64))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        return RibbonFilter::build(&keys, false_positive_rate, hasher, MAX_ATTEMPTS);
+    }
+
+    /// Build the filter from distinct item hashes, trying up to max_attempts seeds
+    fn build(
+        keys: &[u64],
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        max_attempts: u32,
+    ) -> Result<RibbonFilter> {
+        let num_blocks: u64 = params::ribbon_num_blocks(keys.len() as u64);
+        // The last block only holds the ends of rows, so it is left out of the split
+        let upper_blocks: u64 = ((num_blocks - 1) as f64
+            * params::ribbon_upper_fraction(false_positive_rate))
+        .ceil() as u64;
+        let mut rf: RibbonFilter = RibbonFilter {
+            solution: vec![],
+            num_blocks: num_blocks,
+            upper_columns: params::ribbon_upper_columns(false_positive_rate),
+            upper_start_block: num_blocks - 1 - upper_blocks,
+            seed: 0,
+            false_positive_rate: false_positive_rate,
+            num_items: keys.len() as u64,
+            hasher: hasher,
+        };
+        let mut seed_state: u64 = SEED_STATE;
+        for _ in 0..max_attempts {
+            rf.seed = next_seed(&mut seed_state);
+            if let Some((coefficients, results)) = rf.band(keys) {
+                rf.solve(&coefficients, &results);
+                return Ok(rf);
+            }
+        }
+        return Err(Error::ConstructionFailed(max_attempts));
+    }
+
+    /// The equation of an item's hash: its starting slot, its coefficient row (whose lowest
+    /// bit is always set) and its fingerprint, of upper_columns bits
+    fn equation(&self, key: u64) -> (u64, u64, u32) {
+        let hash: u64 = fmix64(key.wrapping_add(self.seed));
+        let num_starts: u64 = self.get_len() - BLOCK_SLOTS + 1;
+        let start: u64 = ((hash as u128 * num_starts as u128) >> 64) as u64;
+        let coefficients: u64 = fmix64(hash ^ 0x9e37_79b9_7f4a_7c15) | 1;
+        let fingerprint: u64 = fmix64(coefficients) & ((1 << self.upper_columns) - 1);
+        return (start, coefficients, fingerprint as u32);
+    }
+
+    /// Add the equations one at a time to a banded matrix, one row per slot whose lowest
+    /// coefficient is on the diagonal. An equation whose slot is taken is XORed with the row
+    /// there and moves on to its new lowest coefficient. Returns the rows, or None if an
+    /// equation reduced to 0 = 1, ie the system has no solution with this seed
+    fn band(&self, keys: &[u64]) -> Option<(Vec<u64>, Vec<u32>)> {
+        let len: usize = self.get_len() as usize;
+        let mut coefficient_rows: Vec<u64> = vec![0; len];
+        let mut result_rows: Vec<u32> = vec![0; len];
+        for key in keys {
+            let (start, mut coefficients, mut result): (u64, u64, u32) = self.equation(*key);
+            let mut slot: usize = start as usize;
+            loop {
+                if coefficient_rows[slot] == 0 {
+                    coefficient_rows[slot] = coefficients;
+                    result_rows[slot] = result;
+                    break;
+                }
+                coefficients ^= coefficient_rows[slot];
+                result ^= result_rows[slot];
+                if coefficients == 0 {
+                    if result != 0 {
+                        return None;
+                    }
+                    break;
+                }
+                let shift: u32 = coefficients.trailing_zeros();
+                slot += shift as usize;
+                coefficients >>= shift;
+            }
+        }
+        return Some((coefficient_rows, result_rows));
+    }
+
+    /// Back-substitute the banded rows from the last slot to the first, all the columns at
+    /// once, and store each block's columns. Slots without a row are free, and set to 0
+    fn solve(&mut self, coefficient_rows: &[u64], result_rows: &[u32]) {
+        let len: usize = coefficient_rows.len();
+        let mut values: Vec<u32> = vec![0; len];
+        for slot in (0..len).rev() {
+            let mut coefficients: u64 = coefficient_rows[slot] >> 1;
+            if coefficient_rows[slot] == 0 {
+                continue;
+            }
+            let mut value: u32 = result_rows[slot];
+            while coefficients != 0 {
+                value ^= values[slot + 1 + coefficients.trailing_zeros() as usize];
+                coefficients &= coefficients - 1;
+            }
+            values[slot] = value;
+        }
+
+        self.solution = vec![];
+        for block in 0..self.num_blocks {
+            let block_values: &[u32] =
+                &values[(block * BLOCK_SLOTS) as usize..((block + 1) * BLOCK_SLOTS) as usize];
+            for column in 0..self.block_columns(block) {
+                let mut word: u64 = 0;
+                for (slot, value) in block_values.iter().enumerate() {
+                    word |= (((value >> column) & 1) as u64) << slot;
+                }
+                self.solution.push(word);
+            }
+        }
+    }
+
+    /// Number of solution columns stored for a block
+    fn block_columns(&self, block: u64) -> u32 {
+        if block >= self.upper_start_block {
+            return self.upper_columns;
+        }
+        return self.upper_columns - 1;
+    }
+
+    /// Index in solution of a block's first column
+    fn block_offset(&self, block: u64) -> usize {
+        let lower_columns: u64 = (self.upper_columns - 1) as u64;
+        if block < self.upper_start_block {
+            return (block * lower_columns) as usize;
+        }
+        return (self.upper_start_block * lower_columns
+            + (block - self.upper_start_block) * self.upper_columns as u64)
+            as usize;
+    }
+
+    /// Check whether an element is probably in the filter or not. Items the filter was built
+    /// from are always found. A query checks as many fingerprint bits as its starting block
+    /// has columns, each as the parity of the coefficient row AND the column's bits
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        if self.num_items == 0 {
+            return false;
+        }
+        let key: u64 = item.with_bytes(|bytes| self.hasher.hash128(bytes, 0) as u64);
+        let (start, coefficients, fingerprint): (u64, u64, u32) = self.equation(key);
+        let block: u64 = start / BLOCK_SLOTS;
+        let offset: u64 = start % BLOCK_SLOTS;
+        let first: usize = self.block_offset(block);
+        let second: usize = self.block_offset(block + 1);
+        for column in 0..self.block_columns(block) as usize {
+            let mut parity: u32 =
+                (self.solution[first + column] & (coefficients << offset)).count_ones();
+            if offset > 0 {
+                parity +=
+                    (self.solution[second + column] & (coefficients >> (64 - offset))).count_ones();
+            }
+            if parity & 1 != (fingerprint >> column) & 1 {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Report the solution bits, the fraction of them set and the false positive rate realized
+    /// by the layout: the rate of each block's columns, weighted by the share of starting slots
+    /// in the block
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let bits_set: u64 = self
+            .solution
+            .iter()
+            .map(|word| word.count_ones() as u64)
+            .sum();
+        let num_starts: u64 = self.get_len() - BLOCK_SLOTS + 1;
+        let mut rate: f64 = 0.0;
+        for block in 0..self.num_blocks {
+            let starts: u64 = BLOCK_SLOTS.min(num_starts - block * BLOCK_SLOTS);
+            rate += starts as f64 * 0.5f64.powi(self.block_columns(block) as i32);
+        }
+        return FillStatistics::with_false_positive_rate(
+            self.solution.len() as u64 * 64,
+            bits_set,
+            rate / num_starts as f64,
+        );
+    }
+}
+
+/// Advance a SplitMix64 sequence and return its next value
+fn next_seed(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z: u64 = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    return z ^ (z >> 31);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the blocks are split between r and r - 1 columns to meet the rate
+    fn test_layout() {
+        let items: Vec<u64> = (0..10000).collect();
+        let rf: RibbonFilter = RibbonFilter::new(&items, 0.003);
+        assert_eq!(rf.get_num_blocks(), params::ribbon_num_blocks(10000));
+        assert_eq!(rf.get_upper_columns(), 9);
+        let upper_blocks: u64 = rf.get_num_blocks() - rf.get_upper_start_block();
+        assert!((upper_blocks as f64 / rf.get_num_blocks() as f64 - 0.464).abs() < 0.01);
+        assert_eq!(
+            rf.solution.len(),
+            rf.block_offset(rf.get_num_blocks() - 1) + 9
+        );
+        let realized: f64 = rf.fill_statistics().get_false_positive_rate();
+        assert!(realized <= 0.003 && realized > 0.0029, "{}", realized);
+        let fill: f64 = rf.fill_statistics().get_fill_ratio();
+        assert!(fill > 0.4 && fill < 0.5, "{}", fill);
+    }
+
+    #[test]
+    /// Test that every item is found, and the false positive rate is close to the target
+    fn test_check() {
+        let items: Vec<u64> = (0..20000).collect();
+        for &p in &[0.5, 0.1, 0.01, 0.003] {
+            let rf: RibbonFilter = RibbonFilter::new(&items, p);
+            assert!(items.iter().all(|i| rf.check(i)), "{}", p);
+            let false_positives: usize = (20000..220000u64).filter(|i| rf.check(i)).count();
+            let measured: f64 = false_positives as f64 / 200000.0;
+            assert!(
+                (measured - p).abs() < 0.1 * p + 0.0005,
+                "{} {}",
+                p,
+                measured
+            );
+        }
+    }
+
+    #[test]
+    /// Test that a system without a solution is reported after the attempts run out
+    fn test_construction_failed() {
+        // Too many equations for the slots: the system cannot be solved
+        let rf: RibbonFilter = RibbonFilter::new(&[1u64], 0.01);
+        let keys: Vec<u64> = (0..200).collect();
+        assert_eq!(
+            RibbonFilter::build(&keys[..], 0.01, rf.get_hasher(), 0).err(),
+            Some(Error::ConstructionFailed(0))
+        );
+        let mut small: RibbonFilter = rf.clone();
+        small.seed = 1;
+        assert!(small.band(&keys).is_none());
+    }
+
+    #[test]
+    /// Test that tiny and empty sets build, and duplicates are counted once
+    fn test_small() {
+        let empty: Vec<u64> = vec![];
+        let rf: RibbonFilter = RibbonFilter::new(&empty, 0.01);
+        assert_eq!(rf.get_num_items(), 0);
+        assert!(!(0..1000u64).any(|i| rf.check(&i)));
+        for n in 1..50u64 {
+            let items: Vec<u64> = (0..n).chain(0..n).collect();
+            let rf: RibbonFilter = RibbonFilter::new(&items, 0.001);
+            assert_eq!(rf.get_num_items(), n);
+            assert!(items.iter().all(|i| rf.check(i)), "{}", n);
+        }
+    }
+
+    #[test]
+    /// Test that invalid rates are reported
+    fn test_invalid_parameters() {
+        let items: Vec<u64> = (0..10).collect();
+        assert_eq!(
+            RibbonFilter::try_new(&items, 1.0).err(),
+            Some(Error::InvalidFalsePositiveRate(1.0))
+        );
+        assert_eq!(
+            RibbonFilter::try_new(&items, 1e-12).err(),
+            Some(Error::InvalidFalsePositiveRate(1e-12))
+        );
+    }
+}
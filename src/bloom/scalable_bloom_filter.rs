@@ -0,0 +1,406 @@
+/*!
+ * scalable_bloom_filter.rs
+ *
+ * Implements a scalable bloom filter (Almeida, Baquero, Preguiça & Hutchison, 2007):
+ *      ScalableBloomFilter -- a bloom filter for sets of unknown size, which is a growing list of
+ *                             BloomFilter "stages". When the newest stage is full, a larger stage
+ *                             with a tighter false positive rate is added, so that the compound
+ *                             false positive rate of all stages stays under the configured one.
+ *                             Unlike the generational filters, nothing is ever thrown away.
+ *                             Does ~not~ support manual deletion.
+ *
+ * Stage i holds n_0 * s^i items at a false positive rate of p_i = p * (1 - r) * r^i, where s is
+ * the growth factor and r the tightening ratio. An item is a false positive if it is one in any
+ * stage, so the compound rate is at most the sum of the p_i, which converges to p.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::BloomFilter;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
+use crate::bloom::HashFunction;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+
+/// ScalableBloomFilter struct:
+///    * stages:              Vec containing each stage's BloomFilter, oldest (smallest) first
+///    * growth_factor:       Factor by which each stage's capacity exceeds the previous one's
+///    * tightening_ratio:    Factor by which each stage's false positive rate is tightened
+///    * false_positive_rate: Target compound false positive rate
+///    * expected_inserts:    Expected inserts of the first stage
+///    * actual_inserts:      Actual inserts, over all stages
+///    * hasher:              Hash function used by every stage
+///    * indexing:            How indices are derived from the hash function
+pub struct ScalableBloomFilter {
+    stages: Vec<BloomFilter>,
+    growth_factor: f64,
+    tightening_ratio: f64,
+    false_positive_rate: f64,
+    expected_inserts: u64,
+    actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
+}
+
+/// Implementation of a scalable bloom filter
+impl ScalableBloomFilter {
+    /// Getter for the number of stages
+    pub fn get_num_stages(&self) -> u64 {
+        return self.stages.len() as u64;
+    }
+
+    /// Getter for growth_factor
+    pub fn get_growth_factor(&self) -> f64 {
+        return self.growth_factor;
+    }
+
+    /// Getter for tightening_ratio
+    pub fn get_tightening_ratio(&self) -> f64 {
+        return self.tightening_ratio;
+    }
+
+    /// Getter for the hash count of the current (newest) stage. Tighter stages use more hashes
+    pub fn get_hash_count(&self) -> u64 {
+        return self.current_stage().get_hash_count();
+    }
+
+    /// Getter for false_positive_rate, the target compound rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for expected_inserts, the capacity of the first stage
+    pub fn get_expected_inserts(&self) -> u64 {
+        return self.expected_inserts;
+    }
+
+    /// Getter for actual_inserts
+    pub fn get_actual_inserts(&self) -> u64 {
+        return self.actual_inserts;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
+    }
+
+    /// Create a new ScalableBloomFilter, using the default (Murmur3) hash function.
+    /// Almeida et al. suggest a growth factor of 2 (slow growth) or 4 (fast growth), and a
+    /// tightening ratio of 0.8 to 0.9
+    pub fn new(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        growth_factor: f64,
+        tightening_ratio: f64,
+    ) -> ScalableBloomFilter {
+        return ScalableBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            growth_factor,
+            tightening_ratio,
+            HashFunction::default(),
+        );
+    }
+
+    /// Create a new ScalableBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        growth_factor: f64,
+        tightening_ratio: f64,
+    ) -> Result<ScalableBloomFilter> {
+        return ScalableBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            growth_factor,
+            tightening_ratio,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new ScalableBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        growth_factor: f64,
+        tightening_ratio: f64,
+        hasher: HashFunction,
+    ) -> ScalableBloomFilter {
+        return ScalableBloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            growth_factor,
+            tightening_ratio,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new ScalableBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        growth_factor: f64,
+        tightening_ratio: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> ScalableBloomFilter {
+        match ScalableBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            growth_factor,
+            tightening_ratio,
+            hasher,
+            indexing,
+        ) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new ScalableBloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        growth_factor: f64,
+        tightening_ratio: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<ScalableBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+        } else if expected_inserts < 1 {
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
+        } else if !(growth_factor >= 1.0 && growth_factor.is_finite()) {
+            return Err(Error::InvalidGrowthFactor(growth_factor));
+        } else if !(tightening_ratio > 0.0 && tightening_ratio < 1.0) {
+            return Err(Error::InvalidTighteningRatio(tightening_ratio));
+        }
+
+        let mut sbf: ScalableBloomFilter = ScalableBloomFilter {
+            stages: Vec::new(),
+            growth_factor: growth_factor,
+            tightening_ratio: tightening_ratio,
+            false_positive_rate: false_positive_rate,
+            expected_inserts: expected_inserts,
+            actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
+        };
+        sbf.add_stage();
+        return Ok(sbf);
+    }
+
+    /// Calculate the parameters of stage i:
+    /// n_i = ceil(n_0 * s^i), p_i = p * (1 - r) * r^i
+    fn stage_parameters(&self, stage: u64) -> FilterParameters {
+        let expected_inserts: u64 =
+            (self.expected_inserts as f64 * self.growth_factor.powf(stage as f64)).ceil() as u64;
+        let false_positive_rate: f64 = self.false_positive_rate
+            * (1.0 - self.tightening_ratio)
+            * self.tightening_ratio.powf(stage as f64);
+        let len: u64 = params::optimal_len(expected_inserts, false_positive_rate);
+        let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+        return FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+    }
+
+    /// Add a new, empty stage after the current one
+    fn add_stage(&mut self) {
+        let params: FilterParameters = self.stage_parameters(self.stages.len() as u64);
+        self.stages.push(BloomFilter::from_parameters(
+            &params,
+            self.hasher,
+            self.indexing,
+        ));
+    }
+
+    /// The current stage, which receives the inserts
+    fn current_stage(&self) -> &BloomFilter {
+        match self.stages.last() {
+            Some(bf) => return bf,
+            None => panic!("Vec 'stages' is uninitialized!"),
+        }
+    }
+
+    /// Insert a new element into the current stage, adding a new stage first if it is full
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        let current: &BloomFilter = self.current_stage();
+        if current.get_actual_inserts() + 1 > current.get_expected_inserts() {
+            self.add_stage();
+        }
+        match self.stages.last_mut() {
+            Some(bf) => bf.insert(item),
+            None => panic!("Vec 'stages' is uninitialized!"),
+        }
+        self.actual_inserts += 1;
+    }
+
+    /// Check whether an element matches any stage of the ScalableBloomFilter
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        // The newest stages are the largest, so they are the most likely to hold the item
+        for bf in self.stages.iter().rev() {
+            if bf.check(item) {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Empty out the entire data structure, going back to a single empty stage
+    pub fn empty(&mut self) {
+        self.stages.clear();
+        self.add_stage();
+        self.actual_inserts = 0;
+    }
+
+    /// Calculate the compound false positive rate the stages are configured for, which is
+    /// always under false_positive_rate: p = 1 - (1 - p_0) * ... * (1 - p_i)
+    pub fn get_compound_false_positive_rate(&self) -> f64 {
+        let mut true_negative_rate: f64 = 1.0;
+        for bf in self.stages.iter() {
+            true_negative_rate *= 1.0 - bf.get_false_positive_rate();
+        }
+        return 1.0 - true_negative_rate;
+    }
+
+    /// Estimate the number of distinct items in the filter: every item is inserted into a
+    /// single stage, so this is the sum of the stage estimates
+    pub fn estimate_cardinality(&self) -> f64 {
+        return self.stages.iter().map(|bf| bf.estimate_cardinality()).sum();
+    }
+
+    /// Report the fill statistics of each stage, oldest first: the last report is for the
+    /// current stage
+    pub fn stage_fill_statistics(&self) -> Vec<FillStatistics> {
+        return self.stages.iter().map(|bf| bf.fill_statistics()).collect();
+    }
+
+    /// Report the fill statistics of all stages together. The false positive rate is the
+    /// probability that an item is a false positive in any stage
+    pub fn fill_statistics(&self) -> FillStatistics {
+        return FillStatistics::combine(&self.stage_fill_statistics());
+    }
+}
+
+impl Filter for ScalableBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        ScalableBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return ScalableBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        ScalableBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return ScalableBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return ScalableBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return ScalableBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return ScalableBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the filter starts with one stage sized for a fraction of the target rate
+    fn test_new() {
+        let sbf: ScalableBloomFilter = ScalableBloomFilter::new(100, 0.01, 2.0, 0.9);
+        assert_eq!(sbf.get_num_stages(), 1);
+        assert_eq!(sbf.get_growth_factor(), 2.0);
+        assert_eq!(sbf.get_tightening_ratio(), 0.9);
+        assert_eq!(sbf.get_expected_inserts(), 100);
+        assert_eq!(sbf.get_actual_inserts(), 0);
+        assert!((sbf.stages[0].get_false_positive_rate() - 0.001).abs() < 1e-12);
+    }
+
+    #[test]
+    /// Test that stages are added as they fill up, growing and tightening geometrically
+    fn test_stages() {
+        let mut sbf: ScalableBloomFilter = ScalableBloomFilter::new(100, 0.01, 2.0, 0.5);
+        // Stages hold 100, 200 and 400 items
+        for i in 0..700 {
+            sbf.insert(&i);
+        }
+        assert_eq!(sbf.get_num_stages(), 3);
+        assert_eq!(sbf.get_actual_inserts(), 700);
+        assert_eq!(sbf.stages[2].get_expected_inserts(), 400);
+        assert!((sbf.stages[2].get_false_positive_rate() - 0.00125).abs() < 1e-12);
+        assert!(sbf.stages[2].get_len() > 4 * sbf.stages[0].get_len());
+        assert!((0..700).all(|i| sbf.check(&i)));
+        sbf.insert(&700);
+        assert_eq!(sbf.get_num_stages(), 4);
+        assert!(sbf.get_compound_false_positive_rate() < 0.01);
+    }
+
+    #[test]
+    /// Test that the fill statistics and cardinality cover every stage
+    fn test_statistics() {
+        let mut sbf: ScalableBloomFilter = ScalableBloomFilter::new(1000, 0.01, 2.0, 0.9);
+        for i in 0..5000 {
+            sbf.insert(&i);
+        }
+        assert_eq!(sbf.stage_fill_statistics().len(), 3);
+        assert!(sbf.fill_statistics().get_false_positive_rate() < 0.01);
+        let estimate: f64 = sbf.estimate_cardinality();
+        assert!((estimate - 5000.0).abs() < 250.0, "{}", estimate);
+    }
+
+    #[test]
+    /// Test that empty goes back to a single empty stage
+    fn test_empty() {
+        let mut sbf: ScalableBloomFilter = ScalableBloomFilter::new(10, 0.01, 2.0, 0.9);
+        for i in 0..100 {
+            sbf.insert(&i);
+        }
+        assert!(sbf.get_num_stages() > 1);
+        sbf.empty();
+        assert_eq!(sbf.get_num_stages(), 1);
+        assert_eq!(sbf.get_actual_inserts(), 0);
+        assert!(!(0..100).any(|i| sbf.check(&i)));
+    }
+
+    #[test]
+    /// Test that invalid growth factors and tightening ratios are rejected
+    fn test_invalid_scaling() {
+        assert_eq!(
+            ScalableBloomFilter::try_new(100, 0.01, 0.5, 0.9).err(),
+            Some(Error::InvalidGrowthFactor(0.5))
+        );
+        assert_eq!(
+            ScalableBloomFilter::try_new(100, 0.01, 2.0, 1.0).err(),
+            Some(Error::InvalidTighteningRatio(1.0))
+        );
+        assert_eq!(
+            ScalableBloomFilter::try_new(0, 0.01, 2.0, 0.9).err(),
+            Some(Error::InvalidExpectedInserts(0))
+        );
+    }
+}
@@ -1,12 +1,23 @@
 /*!
  * scope_decay_bloom_filter.rs
  *
- * Implements a Murmur3-based scope decay bloom filter:
+ * Implements a scope decay bloom filter:
  *      ScopeDecayBloomFilter -- a scope decay bloom filter, supporting gradual resetting of bits
  *                               over time.
  */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::FilterParameters;
+use crate::bloom::HashFunction;
+use crate::bloom::HashIndices;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
 use bit_vec::BitVec;
-use fasthash::murmur3;
 use rand::distributions::{Distribution, Uniform};
 
 /// ScopeDecayBloomFilter
@@ -17,6 +28,8 @@ use rand::distributions::{Distribution, Uniform};
 ///     * bit_reset_rate:      Bit reset rate
 ///     * expected_inserts:    Expected inserts
 ///     * actual_inserts:      Actual inserts
+///     * hasher:              Hash function used to compute indices
+///     * indexing:            How indices are derived from the hash function
 pub struct ScopeDecayBloomFilter {
     bit_arr: BitVec,
     len: u64,
@@ -25,10 +38,17 @@ pub struct ScopeDecayBloomFilter {
     bit_reset_rate: f64,
     expected_inserts: u64,
     actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
 }
 
 /// Implementation of a standard bloom filter, using a bit array.
 impl ScopeDecayBloomFilter {
+    /// Getter for the length of the bit array (len)
+    pub fn get_len(&self) -> u64 {
+        return self.len;
+    }
+
     /// Getter for the hashing iteration count (hash_count)
     pub fn get_hash_count(&self) -> u64 {
         return self.hash_count;
@@ -54,92 +74,172 @@ impl ScopeDecayBloomFilter {
         return self.actual_inserts;
     }
 
-    /// Given a desired false positive rate, calculate the length of the BitVec required
-    /// See 'm' in this SO answer: https://stackoverflow.com/a/22467497
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn calculate_len(expected_inserts: f64, false_positive_rate: f64) -> u64 {
-        let two: f64 = 2.0;
-        return ((-1.0 * (expected_inserts) * false_positive_rate.ln()) / two.ln().powf(two)).ceil()
-            as u64;
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
     }
 
-    /// Calculate the number of hashes required
-    /// See 'k' in this SO answer: https://stackoverflow.com/a/22467497
-    /// k = ceil(m/n * ln(2))
-    fn calculate_hash_count(expected_inserts: f64, len: u64) -> u64 {
-        let two: f64 = 2.0;
-        return (((len as f64) / expected_inserts) * two.ln()).ceil() as u64;
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
     }
 
-    /// Return a single usize value, representing an index to be marked or checked
-    fn get_hash_index(i: u32, item: &str, len: u64) -> usize {
-        let digest_val: u128 = murmur3::hash128_with_seed(item, i); // Compute a murmur3 seeded hash
-        let bit_index: u64 = digest_val as u64 % len; // Mod the len of the BitVec
-        return bit_index as usize;
+    /// Create a new ScopeDecayBloomFilter, using the default (Murmur3) hash function
+    pub fn new(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        bit_reset_rate: f64,
+    ) -> ScopeDecayBloomFilter {
+        return ScopeDecayBloomFilter::with_hasher(
+            expected_inserts,
+            false_positive_rate,
+            bit_reset_rate,
+            HashFunction::default(),
+        );
     }
 
-    /// Create a new ScopeDecayBloomFilter
-    pub fn new(
+    /// Create a new ScopeDecayBloomFilter, using the default (Murmur3) hash function.
+    /// Returns an error if the parameters are invalid
+    pub fn try_new(
         expected_inserts: u64,
         false_positive_rate: f64,
         bit_reset_rate: f64,
+    ) -> Result<ScopeDecayBloomFilter> {
+        return ScopeDecayBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            bit_reset_rate,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new ScopeDecayBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        bit_reset_rate: f64,
+        hasher: HashFunction,
     ) -> ScopeDecayBloomFilter {
-        if false_positive_rate <= 0.0 {
-            panic!(
-                "False positive rate must be a positive number. Currently: {}",
-                false_positive_rate
-            );
+        return ScopeDecayBloomFilter::with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            bit_reset_rate,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new ScopeDecayBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        bit_reset_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> ScopeDecayBloomFilter {
+        match ScopeDecayBloomFilter::try_with_hashing(
+            expected_inserts,
+            false_positive_rate,
+            bit_reset_rate,
+            hasher,
+            indexing,
+        ) {
+            Ok(bf) => return bf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new ScopeDecayBloomFilter, using the given hash function and indexing scheme.
+    /// Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        expected_inserts: u64,
+        false_positive_rate: f64,
+        bit_reset_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<ScopeDecayBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
         } else if expected_inserts < 1 {
-            panic!(
-                "Expected number of inserts must be a positive number. Currently: {}",
-                expected_inserts
-            );
-        } else if bit_reset_rate < 0.0 || bit_reset_rate > 1.0 {
-            panic!(
-                "Bit reset rate must be a positive number between 0.0 and 1.0 inclusive. Currently: {}",
-                bit_reset_rate
-            );
+            return Err(Error::InvalidExpectedInserts(expected_inserts));
+        } else if !(0.0..=1.0).contains(&bit_reset_rate) {
+            return Err(Error::InvalidBitResetRate(bit_reset_rate));
         }
 
-        let len: u64 =
-            ScopeDecayBloomFilter::calculate_len(expected_inserts as f64, false_positive_rate);
-        let hash_count: u64 =
-            ScopeDecayBloomFilter::calculate_hash_count(expected_inserts as f64, len);
+        let len: u64 = params::optimal_len(expected_inserts, false_positive_rate);
+        let hash_count: u64 = params::optimal_hash_count(expected_inserts, len);
+
+        let params: FilterParameters =
+            FilterParameters::new(len, hash_count, expected_inserts, false_positive_rate);
+        return Ok(ScopeDecayBloomFilter::from_parameters(
+            &params,
+            bit_reset_rate,
+            hasher,
+            indexing,
+        ));
+    }
 
+    /// Create a new ScopeDecayBloomFilter from already validated parameters
+    pub(crate) fn from_parameters(
+        params: &FilterParameters,
+        bit_reset_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> ScopeDecayBloomFilter {
         ScopeDecayBloomFilter {
-            bit_arr: BitVec::from_elem(len as usize, false), // Create the whole BitVec zeroed-out
-            len: len,
-            hash_count: hash_count,
-            false_positive_rate: false_positive_rate,
+            bit_arr: BitVec::from_elem(params.get_len() as usize, false), // Create the whole BitVec zeroed-out
+            len: params.get_len(),
+            hash_count: params.get_hash_count(),
+            false_positive_rate: params.get_false_positive_rate(),
             bit_reset_rate: bit_reset_rate,
-            expected_inserts: expected_inserts,
+            expected_inserts: params.get_expected_inserts(),
             actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
         }
     }
 
     /// Insert a new element into the ScopeDecayBloomFilter
     /// Will initiate a new decay event if the actual inserts exceed the expected inserts
-    pub fn insert(&mut self, item: &str) {
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
         if self.actual_inserts > self.expected_inserts {
             self.decay();
         }
-        for i in 0..self.hash_count {
-            let bit_index: usize = ScopeDecayBloomFilter::get_hash_index(i as u32, item, self.len);
-            self.bit_arr.set(bit_index, true); // Set the relevant index to '1'
-        }
+        item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            for bit_index in indices {
+                self.bit_arr.set(bit_index, true); // Set the relevant index to '1'
+            }
+        });
         self.actual_inserts += 1;
     }
 
     /// Check whether an element is probably in the filter or not
-    pub fn check(&self, item: &str) -> bool {
-        for i in 0..self.hash_count {
-            let bit_index: usize = ScopeDecayBloomFilter::get_hash_index(i as u32, item, self.len);
-            // Check if the relevant index is set
-            if !self.bit_arr[bit_index as usize] {
-                return false;
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            for bit_index in indices {
+                // Check if the relevant index is set
+                if !self.bit_arr[bit_index] {
+                    return false;
+                }
             }
-        }
-        return true;
+            return true;
+        });
     }
 
     /// Decay n bits randomly from the bit array
@@ -163,6 +263,29 @@ impl ScopeDecayBloomFilter {
         }
     }
 
+    /// Estimate the number of distinct items still represented in the filter, from the
+    /// number of bits set (the Swamidass-Baldi estimator). Decay clears bits, so this shrinks
+    /// as old items fade out
+    pub fn estimate_cardinality(&self) -> f64 {
+        return params::estimate_cardinality(self.len, self.hash_count, self.count_ones());
+    }
+
+    /// Report the bits set, the fill ratio and the estimated current false positive rate,
+    /// which falls below the configured rate as bits decay
+    pub fn fill_statistics(&self) -> FillStatistics {
+        return FillStatistics::new(self.len, self.hash_count, self.count_ones());
+    }
+
+    /// Count the bits set in the bit array
+    fn count_ones(&self) -> u64 {
+        return self
+            .bit_arr
+            .storage()
+            .iter()
+            .map(|block| block.count_ones() as u64)
+            .sum();
+    }
+
     /// Emtpy out the ScopeDecayBloomFilter
     pub fn empty(&mut self) {
         self.bit_arr.clear();
@@ -170,9 +293,116 @@ impl ScopeDecayBloomFilter {
     }
 }
 
+impl Filter for ScopeDecayBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        ScopeDecayBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return ScopeDecayBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        ScopeDecayBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return ScopeDecayBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return ScopeDecayBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return ScopeDecayBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return ScopeDecayBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use crate::bloom::serialization::{ByteBuf, Bytes};
+    use serde::de;
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialized form of a ScopeDecayBloomFilter, validated before it becomes one
+    #[derive(Deserialize)]
+    #[serde(rename = "ScopeDecayBloomFilter", deny_unknown_fields)]
+    struct ScopeDecayBloomFilterRepr {
+        len: u64,
+        hash_count: u64,
+        false_positive_rate: f64,
+        bit_reset_rate: f64,
+        expected_inserts: u64,
+        actual_inserts: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+        bits: ByteBuf,
+    }
+
+    impl Serialize for ScopeDecayBloomFilter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("ScopeDecayBloomFilter", 9)?;
+            state.serialize_field("len", &self.len)?;
+            state.serialize_field("hash_count", &self.hash_count)?;
+            state.serialize_field("false_positive_rate", &self.false_positive_rate)?;
+            state.serialize_field("bit_reset_rate", &self.bit_reset_rate)?;
+            state.serialize_field("expected_inserts", &self.expected_inserts)?;
+            state.serialize_field("actual_inserts", &self.actual_inserts)?;
+            state.serialize_field("hasher", &self.hasher)?;
+            state.serialize_field("indexing", &self.indexing)?;
+            state.serialize_field("bits", &Bytes(&self.bit_arr.to_bytes()))?;
+            return state.end();
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ScopeDecayBloomFilter {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<ScopeDecayBloomFilter, D::Error> {
+            let repr: ScopeDecayBloomFilterRepr =
+                ScopeDecayBloomFilterRepr::deserialize(deserializer)?;
+            let params: FilterParameters = FilterParameters::new(
+                repr.len,
+                repr.hash_count,
+                repr.expected_inserts,
+                repr.false_positive_rate,
+            );
+            params.validate().map_err(de::Error::custom)?;
+            if !(0.0..=1.0).contains(&repr.bit_reset_rate) {
+                return Err(de::Error::custom(Error::InvalidBitResetRate(
+                    repr.bit_reset_rate,
+                )));
+            } else if repr.bits.0.len() as u64 != repr.len.div_ceil(8) {
+                return Err(de::Error::custom(Error::Corrupt(
+                    "payload length does not match the filter length",
+                )));
+            }
+            let mut bit_arr: BitVec = BitVec::from_bytes(&repr.bits.0);
+            bit_arr.truncate(repr.len as usize);
+            let mut sdbf: ScopeDecayBloomFilter = ScopeDecayBloomFilter::from_parameters(
+                &params,
+                repr.bit_reset_rate,
+                repr.hasher,
+                repr.indexing,
+            );
+            sdbf.bit_arr = bit_arr;
+            sdbf.actual_inserts = repr.actual_inserts;
+            return Ok(sdbf);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bloom::BloomHasher;
 
     #[test]
     /// Test that we can create a new ScopeDecayBloomFilter using ScopeDecayBloomFilter::new()
@@ -192,19 +422,31 @@ mod tests {
         let s2 = "This is another string"; // Inserted
         let s3 = "This is a third string"; // Not inserted
         let mut bf: ScopeDecayBloomFilter = ScopeDecayBloomFilter::new(2, 0.05, 0.1);
-        bf.insert(&s);
-        bf.insert(&s2);
-        assert!(bf.check(&s)); // Included
-        assert!(bf.check(&s2)); // Included
-        assert!(!bf.check(&s3)); // Not included
+        bf.insert(s);
+        bf.insert(s2);
+        assert!(bf.check(s)); // Included
+        assert!(bf.check(s2)); // Included
+        assert!(!bf.check(s3)); // Not included
     }
 
     #[test]
-    /// Test that the get_hash_index method works as expected
-    fn test_get_hash_index() {
+    /// Test that the PerSeed indexing scheme marks the same indices as earlier versions,
+    /// where index i is the low 64 bits of the murmur3 digest seeded with i, mod len
+    fn test_per_seed_indexing() {
         let s = "This is a test string";
-        let i: u32 = 32;
-        assert_eq!(ScopeDecayBloomFilter::get_hash_index(i, &s, 10), 4);
+        let mut bf: ScopeDecayBloomFilter = ScopeDecayBloomFilter::with_hashing(
+            2,
+            0.05,
+            0.1,
+            HashFunction::Murmur3,
+            IndexingScheme::PerSeed,
+        );
+        bf.insert(s);
+        for i in 0..bf.get_hash_count() {
+            let digest_val: u128 = HashFunction::Murmur3.hash128(s.as_bytes(), i as u32);
+            let index: usize = (digest_val as u64 % bf.len) as usize;
+            assert!(bf.bit_arr[index]);
+        }
     }
 
     #[test]
@@ -249,6 +491,36 @@ mod tests {
         assert!(old_count > bf.bit_arr.iter().filter(|x| *x).count());
     }
 
+    #[test]
+    /// Test that the estimate tracks distinct inserts, and shrinks as bits decay
+    fn test_estimate_cardinality() {
+        let mut bf: ScopeDecayBloomFilter = ScopeDecayBloomFilter::new(1000, 0.01, 0.1);
+        for i in 0..500 {
+            bf.insert(&i);
+            bf.insert(&i);
+        }
+        let estimate: f64 = bf.estimate_cardinality();
+        assert!((estimate - 500.0).abs() < 25.0, "{}", estimate);
+        bf.decay();
+        assert!(bf.estimate_cardinality() < estimate);
+    }
+
+    #[test]
+    /// Test that the estimated false positive rate falls as bits decay
+    fn test_fill_statistics() {
+        let mut bf: ScopeDecayBloomFilter = ScopeDecayBloomFilter::new(1000, 0.01, 0.1);
+        for i in 0..1000 {
+            bf.insert(&i);
+        }
+        let stats: FillStatistics = bf.fill_statistics();
+        assert_eq!(
+            stats.get_bits_set(),
+            bf.bit_arr.iter().filter(|x| *x).count() as u64
+        );
+        bf.decay();
+        assert!(bf.fill_statistics().get_false_positive_rate() < stats.get_false_positive_rate());
+    }
+
     #[test]
     /// Test that empty behaves like we expect
     fn test_empty() {
@@ -265,40 +537,6 @@ mod tests {
         }
     }
 
-    #[test]
-    /// Test that we get the expected value for calculating the length of the
-    /// BitVec using the computation:
-    /// m = ceil(-n*ln(p) / (ln(2)^2))
-    fn test_calculate_len() {
-        let m: u64 = ScopeDecayBloomFilter::calculate_len(100 as f64, 0.01);
-        assert_eq!(m, 959);
-        let m2: u64 = ScopeDecayBloomFilter::calculate_len(1000 as f64, 0.001);
-        assert_eq!(m2, 14378);
-        let m3: u64 = ScopeDecayBloomFilter::calculate_len(5 as f64, 0.1);
-        assert_eq!(m3, 24);
-        let m4: u64 = ScopeDecayBloomFilter::calculate_len(10000 as f64, 0.01);
-        assert_eq!(m4, 95851);
-        let m5: u64 = ScopeDecayBloomFilter::calculate_len(216553 as f64, 0.01);
-        assert_eq!(m5, 2075674);
-    }
-
-    #[test]
-    /// Test that we get the expected value for calculating the number of iterations
-    /// if hashing we need to perform
-    /// k = ceil(m/n * ln(2))
-    fn test_calculate_hash_count() {
-        let k: u64 = ScopeDecayBloomFilter::calculate_hash_count(100 as f64, 959);
-        assert_eq!(k, 7);
-        let k2: u64 = ScopeDecayBloomFilter::calculate_hash_count(1000 as f64, 14378);
-        assert_eq!(k2, 10);
-        let k3: u64 = ScopeDecayBloomFilter::calculate_hash_count(5 as f64, 24);
-        assert_eq!(k3, 4);
-        let k4: u64 = ScopeDecayBloomFilter::calculate_hash_count(10000 as f64, 95851);
-        assert_eq!(k4, 7);
-        let k5: u64 = ScopeDecayBloomFilter::calculate_hash_count(216553 as f64, 2075674);
-        assert_eq!(k5, 7);
-    }
-
     #[test]
     #[should_panic]
     /// Test that we can't pass zero as the expected_inserts value
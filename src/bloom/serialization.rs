@@ -0,0 +1,131 @@
+/*!
+ * serialization.rs
+ *
+ * Implements the helpers shared by the serde implementations of the filter types, which are
+ * only compiled with the "serde" feature:
+ *      Bytes           -- borrowed bytes, serialized as a byte string, which binary formats
+ *                         (bincode, CBOR...) store compactly.
+ *      ByteBuf         -- owned bytes, deserialized from a byte string or from a sequence of
+ *                         integers (eg a JSON array).
+ *      encode_counters -- packs counters as LEB128 varints, since most counters are small.
+ *      decode_counters -- unpacks (and validates) counters packed with encode_counters.
+ */
+#![allow(clippy::needless_return)]
+use crate::bloom::Error;
+use crate::bloom::Result;
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Borrowed bytes, serialized as a byte string
+pub(crate) struct Bytes<'a>(pub(crate) &'a [u8]);
+
+/// Owned bytes, deserialized from a byte string or a sequence of integers
+pub(crate) struct ByteBuf(pub(crate) Vec<u8>);
+
+/// Visitor accepting every representation of a byte string
+struct ByteBufVisitor;
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        return serializer.serialize_bytes(self.0);
+    }
+}
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "a byte string");
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<ByteBuf, E> {
+        return Ok(ByteBuf(v.to_vec()));
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<ByteBuf, E> {
+        return Ok(ByteBuf(v));
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<ByteBuf, A::Error> {
+        let mut bytes: Vec<u8> = Vec::new();
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        return Ok(ByteBuf(bytes));
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<ByteBuf, D::Error> {
+        return deserializer.deserialize_byte_buf(ByteBufVisitor);
+    }
+}
+
+/// Pack counters as LEB128 varints: 7 bits per byte, with the high bit set on every byte
+/// but the last of each counter
+pub(crate) fn encode_counters(counters: &[u64]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(counters.len());
+    for &counter in counters.iter() {
+        let mut val: u64 = counter;
+        while val >= 0x80 {
+            bytes.push((val as u8) | 0x80);
+            val >>= 7;
+        }
+        bytes.push(val as u8);
+    }
+    return bytes;
+}
+
+/// Unpack exactly len counters packed with encode_counters
+pub(crate) fn decode_counters(bytes: &[u8], len: u64) -> Result<Vec<u64>> {
+    let mut counters: Vec<u64> = Vec::new();
+    let mut val: u64 = 0;
+    let mut shift: u32 = 0;
+    for &byte in bytes.iter() {
+        if shift > 63 || (shift == 63 && byte > 1) {
+            return Err(Error::Corrupt("counter does not fit in 64 bits"));
+        }
+        val |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            counters.push(val);
+            val = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+    if shift != 0 {
+        return Err(Error::Corrupt("truncated counter"));
+    } else if counters.len() as u64 != len {
+        return Err(Error::Corrupt(
+            "counter count does not match the filter length",
+        ));
+    }
+    return Ok(counters);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that counters round-trip, and small counters take one byte each
+    fn test_counters_round_trip() {
+        let counters: Vec<u64> = vec![0, 1, 127, 128, 300, u64::MAX];
+        let bytes: Vec<u8> = encode_counters(&counters);
+        assert_eq!(bytes[..5], [0, 1, 127, 0x80, 0x01]);
+        assert_eq!(bytes.len(), 1 + 1 + 1 + 2 + 2 + 10);
+        assert_eq!(decode_counters(&bytes, 6).unwrap(), counters);
+    }
+
+    #[test]
+    /// Test that malformed counters are rejected
+    fn test_decode_invalid() {
+        assert!(decode_counters(&[0x80], 1).is_err());
+        assert!(decode_counters(&[1, 2], 3).is_err());
+        assert!(decode_counters(&[0xff; 11], 1).is_err());
+    }
+}
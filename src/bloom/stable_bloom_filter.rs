@@ -0,0 +1,474 @@
+/*!
+ * stable_bloom_filter.rs
+ *
+ * Implements a stable bloom filter (Deng & Rafiei, 2006):
+ *      StableBloomFilter -- a bloom filter over an unbounded stream, using small counters
+ *                           ("cells") rather than bits. Each insert decrements P random cells
+ *                           before setting the item's k cells to their maximum value, so old
+ *                           items fade out at a steady pace and the fraction of zero cells
+ *                           converges to a stable point, which gives a fixed false positive
+ *                           rate however many items are inserted. Recent items may be
+ *                           reported absent (false negatives) once their cells are decremented.
+ *                           The cells to decrement are drawn from a seeded generator, so the
+ *                           same inserts always leave the same cells behind.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+use crate::bloom::Error;
+use crate::bloom::FillStatistics;
+use crate::bloom::Filter;
+use crate::bloom::HashFunction;
+use crate::bloom::HashIndices;
+use crate::bloom::IndexingScheme;
+use crate::bloom::Item;
+use crate::bloom::Result;
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Default seed of the generator picking the cells to decrement
+const RNG_SEED: u64 = 0x510e_527f_ade6_82d1;
+
+/// StableBloomFilter struct:
+///    * cells:               Cells, one byte each
+///    * len:                 Number of cells (m)
+///    * hash_count:          Hash count (k)
+///    * max_cell_value:      Value an item's cells are set to on insert (Max)
+///    * decrements:          Number of random cells decremented per insert (P)
+///    * false_positive_rate: False positive rate the filter converges to
+///    * actual_inserts:      Actual inserts
+///    * hasher:              Hash function used to compute indices
+///    * indexing:            How indices are derived from the hash function
+///    * rng:                 Seeded generator picking the cells to decrement
+#[derive(Clone)]
+pub struct StableBloomFilter {
+    cells: Vec<u8>,
+    len: u64,
+    hash_count: u64,
+    max_cell_value: u64,
+    decrements: u64,
+    false_positive_rate: f64,
+    actual_inserts: u64,
+    hasher: HashFunction,
+    indexing: IndexingScheme,
+    rng: StdRng,
+}
+
+/// Implementation of a stable bloom filter. Uses a Vec<u8> to hold the cells
+impl StableBloomFilter {
+    /// Getter for the number of cells (len)
+    pub fn get_len(&self) -> u64 {
+        return self.len;
+    }
+
+    /// Getter for hash_count
+    pub fn get_hash_count(&self) -> u64 {
+        return self.hash_count;
+    }
+
+    /// Getter for max_cell_value
+    pub fn get_max_cell_value(&self) -> u64 {
+        return self.max_cell_value;
+    }
+
+    /// Getter for decrements
+    pub fn get_decrements(&self) -> u64 {
+        return self.decrements;
+    }
+
+    /// Getter for false_positive_rate: the target rate, or for an explicit layout the rate it
+    /// converges to
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+
+    /// Getter for the false positive rate the layout converges to, which is at most the
+    /// target rate
+    pub fn get_stable_false_positive_rate(&self) -> f64 {
+        return params::stable_false_positive_rate(
+            self.len,
+            self.hash_count,
+            self.max_cell_value,
+            self.decrements,
+        );
+    }
+
+    /// Getter for expected_inserts. A stable filter takes an unbounded stream and has no
+    /// capacity, so this is always 0
+    pub fn get_expected_inserts(&self) -> u64 {
+        return 0;
+    }
+
+    /// Getter for actual_inserts
+    pub fn get_actual_inserts(&self) -> u64 {
+        return self.actual_inserts;
+    }
+
+    /// Getter for hasher
+    pub fn get_hasher(&self) -> HashFunction {
+        return self.hasher;
+    }
+
+    /// Getter for indexing
+    pub fn get_indexing(&self) -> IndexingScheme {
+        return self.indexing;
+    }
+
+    /// Create a new StableBloomFilter of len cells, converging to the given false positive
+    /// rate, using the default (Murmur3) hash function
+    pub fn new(len: u64, false_positive_rate: f64) -> StableBloomFilter {
+        return StableBloomFilter::with_hasher(len, false_positive_rate, HashFunction::default());
+    }
+
+    /// Create a new StableBloomFilter of len cells, converging to the given false positive
+    /// rate, using the default (Murmur3) hash function. Returns an error if the parameters are
+    /// invalid
+    pub fn try_new(len: u64, false_positive_rate: f64) -> Result<StableBloomFilter> {
+        return StableBloomFilter::try_with_hashing(
+            len,
+            false_positive_rate,
+            HashFunction::default(),
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new StableBloomFilter, using the given hash function and the default
+    /// (DoubleHashing) indexing scheme
+    pub fn with_hasher(
+        len: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+    ) -> StableBloomFilter {
+        return StableBloomFilter::with_hashing(
+            len,
+            false_positive_rate,
+            hasher,
+            IndexingScheme::default(),
+        );
+    }
+
+    /// Create a new StableBloomFilter, using the given hash function and indexing scheme
+    pub fn with_hashing(
+        len: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> StableBloomFilter {
+        match StableBloomFilter::try_with_hashing(len, false_positive_rate, hasher, indexing) {
+            Ok(sbf) => return sbf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new StableBloomFilter, using the given hash function and indexing scheme.
+    /// The hash count, maximum cell value and decrements per insert are picked with
+    /// params::stable_hash_count(), params::stable_max_cell_value() and
+    /// params::stable_decrement_count(). Returns an error if the parameters are invalid
+    pub fn try_with_hashing(
+        len: u64,
+        false_positive_rate: f64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<StableBloomFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate(false_positive_rate));
+        }
+        let hash_count: u64 = params::stable_hash_count(false_positive_rate);
+        if len <= hash_count {
+            return Err(Error::InvalidLen(len));
+        }
+        let max_cell_value: u64 =
+            params::stable_max_cell_value(len, hash_count, false_positive_rate);
+        let decrements: u64 =
+            params::stable_decrement_count(len, hash_count, max_cell_value, false_positive_rate);
+        let mut sbf: StableBloomFilter = StableBloomFilter::try_with_layout(
+            len,
+            hash_count,
+            max_cell_value,
+            decrements,
+            hasher,
+            indexing,
+        )?;
+        sbf.false_positive_rate = false_positive_rate;
+        return Ok(sbf);
+    }
+
+    /// Create a new StableBloomFilter with an explicit layout: len cells, hash_count hashes,
+    /// cells set to max_cell_value on insert, and decrements cells decremented per insert
+    pub fn with_layout(
+        len: u64,
+        hash_count: u64,
+        max_cell_value: u64,
+        decrements: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> StableBloomFilter {
+        match StableBloomFilter::try_with_layout(
+            len,
+            hash_count,
+            max_cell_value,
+            decrements,
+            hasher,
+            indexing,
+        ) {
+            Ok(sbf) => return sbf,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new StableBloomFilter with an explicit layout. Returns an error if there are
+    /// no more cells than hashes, or the maximum cell value or the decrements are out of range
+    pub fn try_with_layout(
+        len: u64,
+        hash_count: u64,
+        max_cell_value: u64,
+        decrements: u64,
+        hasher: HashFunction,
+        indexing: IndexingScheme,
+    ) -> Result<StableBloomFilter> {
+        if hash_count < 1 {
+            return Err(Error::InvalidHashCount(hash_count));
+        } else if len <= hash_count {
+            return Err(Error::InvalidLen(len));
+        } else if !(1..=params::STABLE_MAX_CELL_VALUE).contains(&max_cell_value) {
+            return Err(Error::InvalidMaxCellValue(max_cell_value));
+        } else if !(1..=len).contains(&decrements) {
+            return Err(Error::InvalidDecrementCount(decrements));
+        }
+
+        return Ok(StableBloomFilter {
+            cells: vec![0; len as usize],
+            len: len,
+            hash_count: hash_count,
+            max_cell_value: max_cell_value,
+            decrements: decrements,
+            false_positive_rate: params::stable_false_positive_rate(
+                len,
+                hash_count,
+                max_cell_value,
+                decrements,
+            ),
+            actual_inserts: 0,
+            hasher: hasher,
+            indexing: indexing,
+            rng: StdRng::seed_from_u64(RNG_SEED),
+        });
+    }
+
+    /// Pick the cells to decrement with a generator seeded with the given seed, eg so that
+    /// filters fed the same stream don't forget the same items
+    pub fn with_seed(mut self, seed: u64) -> StableBloomFilter {
+        self.rng = StdRng::seed_from_u64(seed);
+        return self;
+    }
+
+    /// Insert a new element into the StableBloomFilter. Decrements P random cells (cells
+    /// already at zero stay there), then sets the item's k cells to the maximum value
+    pub fn insert<T: Item + ?Sized>(&mut self, item: &T) {
+        self.decrement();
+        let max_cell_value: u8 = self.max_cell_value as u8;
+        item.with_bytes(|bytes| {
+            let indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            for index in indices {
+                self.cells[index] = max_cell_value;
+            }
+        });
+        self.actual_inserts += 1;
+    }
+
+    /// Check whether an element is probably in the filter or not: whether all its cells are
+    /// non-zero
+    pub fn check<T: Item + ?Sized>(&self, item: &T) -> bool {
+        return item.with_bytes(|bytes| {
+            let mut indices: HashIndices = HashIndices::new(
+                &self.hasher,
+                self.indexing,
+                bytes,
+                self.hash_count,
+                self.len,
+            );
+            return indices.all(|index| self.cells[index] > 0);
+        });
+    }
+
+    /// Decrement P cells picked at random
+    fn decrement(&mut self) {
+        let die = Uniform::from(0..self.len as usize);
+        for _ in 0..self.decrements {
+            let index: usize = die.sample(&mut self.rng);
+            self.cells[index] = self.cells[index].saturating_sub(1);
+        }
+    }
+
+    /// Report the non-zero cells, their fraction and the estimated current false positive
+    /// rate, which approaches the stable rate from below as the filter fills up
+    pub fn fill_statistics(&self) -> FillStatistics {
+        let cells_set: u64 = self.cells.iter().filter(|cell| **cell > 0).count() as u64;
+        return FillStatistics::new(self.len, self.hash_count, cells_set);
+    }
+
+    /// Empty out the StableBloomFilter
+    pub fn empty(&mut self) {
+        self.cells.iter_mut().for_each(|cell| *cell = 0);
+        self.actual_inserts = 0;
+    }
+}
+
+impl Filter for StableBloomFilter {
+    fn insert_bytes(&mut self, bytes: &[u8]) {
+        StableBloomFilter::insert(self, bytes);
+    }
+
+    fn check_bytes(&mut self, bytes: &[u8]) -> bool {
+        return StableBloomFilter::check(self, bytes);
+    }
+
+    fn empty(&mut self) {
+        StableBloomFilter::empty(self);
+    }
+
+    fn get_hash_count(&self) -> u64 {
+        return StableBloomFilter::get_hash_count(self);
+    }
+
+    fn get_false_positive_rate(&self) -> f64 {
+        return StableBloomFilter::get_false_positive_rate(self);
+    }
+
+    fn get_expected_inserts(&self) -> u64 {
+        return StableBloomFilter::get_expected_inserts(self);
+    }
+
+    fn get_actual_inserts(&self) -> u64 {
+        return StableBloomFilter::get_actual_inserts(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the layout is picked from the target rate, and converges under it
+    fn test_new() {
+        let sbf: StableBloomFilter = StableBloomFilter::new(100000, 0.01);
+        assert_eq!(sbf.get_len(), 100000);
+        assert_eq!(sbf.get_hash_count(), 7);
+        assert_eq!(sbf.get_max_cell_value(), 3);
+        assert_eq!(sbf.get_decrements(), 26);
+        assert_eq!(sbf.get_false_positive_rate(), 0.01);
+        assert!(sbf.get_stable_false_positive_rate() <= 0.01);
+        assert_eq!(sbf.get_expected_inserts(), 0);
+        assert_eq!(sbf.get_actual_inserts(), 0);
+    }
+
+    #[test]
+    /// Test that each insert decrements exactly P cells, before setting the item's cells
+    fn test_insert() {
+        let mut sbf: StableBloomFilter = StableBloomFilter::with_layout(
+            1000,
+            3,
+            7,
+            5,
+            HashFunction::Xxh3,
+            IndexingScheme::default(),
+        );
+        sbf.insert("first");
+        assert!(sbf.check("first"));
+        let total: u64 = sbf.cells.iter().map(|cell| *cell as u64).sum();
+        assert!(total == 21 || total == 14, "{}", total);
+        // Every cell at the maximum: each decrement takes exactly one off the total
+        sbf.cells.iter_mut().for_each(|cell| *cell = 7);
+        sbf.decrement();
+        let total: u64 = sbf.cells.iter().map(|cell| *cell as u64).sum();
+        assert_eq!(total, 7 * 1000 - 5);
+        assert_eq!(sbf.get_actual_inserts(), 1);
+    }
+
+    #[test]
+    /// Test that the fraction of zero cells converges to the stable point over a long stream,
+    /// and that the latest items are still found
+    fn test_convergence() {
+        let mut sbf: StableBloomFilter = StableBloomFilter::new(10000, 0.01);
+        for i in 0..100000u64 {
+            sbf.insert(&i);
+        }
+        let rate: f64 = sbf.fill_statistics().get_false_positive_rate();
+        let stable: f64 = sbf.get_stable_false_positive_rate();
+        assert!((rate - stable).abs() < 0.2 * stable, "{} {}", rate, stable);
+        assert!((99990..100000u64).all(|i| sbf.check(&i)));
+        // The oldest items have long faded out
+        assert!((0..1000u64).filter(|i| sbf.check(i)).count() < 50);
+    }
+
+    #[test]
+    /// Test that the same inserts decrement the same cells, unless the seed differs
+    fn test_seed() {
+        let mut a: StableBloomFilter = StableBloomFilter::new(1000, 0.01);
+        let mut b: StableBloomFilter = StableBloomFilter::new(1000, 0.01);
+        let mut c: StableBloomFilter = StableBloomFilter::new(1000, 0.01).with_seed(7);
+        for i in 0..500 {
+            a.insert(&i);
+            b.insert(&i);
+            c.insert(&i);
+        }
+        assert_eq!(a.cells, b.cells);
+        assert_ne!(a.cells, c.cells);
+    }
+
+    #[test]
+    /// Test that empty clears every cell
+    fn test_empty() {
+        let mut sbf: StableBloomFilter = StableBloomFilter::new(1000, 0.01);
+        for i in 0..100 {
+            sbf.insert(&i);
+        }
+        sbf.empty();
+        assert!(!(0..100).any(|i| sbf.check(&i)));
+        assert_eq!(sbf.fill_statistics().get_bits_set(), 0);
+        assert_eq!(sbf.get_actual_inserts(), 0);
+    }
+
+    #[test]
+    /// Test that invalid parameters are reported
+    fn test_invalid_parameters() {
+        assert_eq!(
+            StableBloomFilter::try_new(1000, 0.0).err(),
+            Some(Error::InvalidFalsePositiveRate(0.0))
+        );
+        assert_eq!(
+            StableBloomFilter::try_new(7, 0.01).err(),
+            Some(Error::InvalidLen(7))
+        );
+        let layout = |len: u64, hash_count: u64, max_cell_value: u64, decrements: u64| {
+            return StableBloomFilter::try_with_layout(
+                len,
+                hash_count,
+                max_cell_value,
+                decrements,
+                HashFunction::default(),
+                IndexingScheme::default(),
+            )
+            .err();
+        };
+        assert_eq!(layout(100, 0, 1, 1), Some(Error::InvalidHashCount(0)));
+        assert_eq!(layout(100, 3, 0, 1), Some(Error::InvalidMaxCellValue(0)));
+        assert_eq!(
+            layout(100, 3, 256, 1),
+            Some(Error::InvalidMaxCellValue(256))
+        );
+        assert_eq!(layout(100, 3, 1, 0), Some(Error::InvalidDecrementCount(0)));
+        assert_eq!(
+            layout(100, 3, 1, 101),
+            Some(Error::InvalidDecrementCount(101))
+        );
+        assert_eq!(layout(100, 3, 255, 100), None);
+    }
+}
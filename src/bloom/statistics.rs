@@ -0,0 +1,224 @@
+/*!
+ * statistics.rs
+ *
+ * Implements reports estimated from the bits set in filters:
+ *      FillStatistics     -- the number of bits set, the fill ratio and the estimated current
+ *                            false positive rate of a filter (or of a window of generations),
+ *                            which unlike the configured rate reflects overfilling and decay.
+ *      SimilarityEstimate -- the estimated sizes of the intersection and union of the sets held
+ *                            by two filters, and their Jaccard similarity.
+ */
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+use crate::bloom::params;
+
+/// FillStatistics struct:
+///    * len:                 Number of bits (or counters) covered
+///    * bits_set:            Number of bits set (or counters above zero)
+///    * false_positive_rate: Estimated current false positive rate
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FillStatistics {
+    len: u64,
+    bits_set: u64,
+    false_positive_rate: f64,
+}
+
+/// Implementation of the fill statistics of a filter
+impl FillStatistics {
+    /// Compute the statistics of a single filter of length m with k hashes and X bits set
+    pub(crate) fn new(len: u64, hash_count: u64, bits_set: u64) -> FillStatistics {
+        return FillStatistics {
+            len: len,
+            bits_set: bits_set,
+            false_positive_rate: params::estimate_false_positive_rate(
+                hash_count,
+                bits_set as f64 / len as f64,
+            ),
+        };
+    }
+
+    /// Report statistics whose false positive rate was estimated by the filter itself, eg from
+    /// the fill of each block of a blocked filter
+    pub(crate) fn with_false_positive_rate(
+        len: u64,
+        bits_set: u64,
+        false_positive_rate: f64,
+    ) -> FillStatistics {
+        return FillStatistics {
+            len: len,
+            bits_set: bits_set,
+            false_positive_rate: false_positive_rate,
+        };
+    }
+
+    /// Combine the statistics of generations which are all checked for every item: the bits
+    /// of every generation are counted, and an item is a false positive if it is one in any
+    /// generation, p = 1 - (1 - p_1) * ... * (1 - p_g)
+    pub(crate) fn combine(generations: &[FillStatistics]) -> FillStatistics {
+        let mut len: u64 = 0;
+        let mut bits_set: u64 = 0;
+        let mut true_negative_rate: f64 = 1.0;
+        for stats in generations.iter() {
+            len += stats.len;
+            bits_set += stats.bits_set;
+            true_negative_rate *= 1.0 - stats.false_positive_rate;
+        }
+        return FillStatistics {
+            len: len,
+            bits_set: bits_set,
+            false_positive_rate: 1.0 - true_negative_rate,
+        };
+    }
+
+    /// Getter for len
+    pub fn get_len(&self) -> u64 {
+        return self.len;
+    }
+
+    /// Getter for bits_set
+    pub fn get_bits_set(&self) -> u64 {
+        return self.bits_set;
+    }
+
+    /// Fraction of the bits which are set
+    pub fn get_fill_ratio(&self) -> f64 {
+        return self.bits_set as f64 / self.len as f64;
+    }
+
+    /// Getter for false_positive_rate
+    pub fn get_false_positive_rate(&self) -> f64 {
+        return self.false_positive_rate;
+    }
+}
+
+/// SimilarityEstimate struct:
+///    * intersection: Estimated number of items in both sets, |A ∩ B|
+///    * union:        Estimated number of items in either set, |A ∪ B|
+///    * jaccard:      Estimated Jaccard similarity, |A ∩ B| / |A ∪ B|
+///    * error_bound:  Approximate error bound of the Jaccard similarity
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SimilarityEstimate {
+    intersection: f64,
+    union: f64,
+    jaccard: f64,
+    error_bound: f64,
+}
+
+/// Implementation of the similarity estimate of two filters
+impl SimilarityEstimate {
+    /// Estimate the similarity of two filters of length m with k hashes, from the bits set in
+    /// each of them and in their OR. The bits set in their AND follow, as X_A + X_B - X_OR,
+    /// and the intersection estimated from them (Papapetrou et al.) reduces to
+    /// |A| + |B| - |A ∪ B| with every term estimated by params::estimate_cardinality
+    pub(crate) fn new(
+        len: u64,
+        hash_count: u64,
+        bits_set_a: u64,
+        bits_set_b: u64,
+        bits_set_or: u64,
+    ) -> SimilarityEstimate {
+        let a: f64 = params::estimate_cardinality(len, hash_count, bits_set_a);
+        let b: f64 = params::estimate_cardinality(len, hash_count, bits_set_b);
+        let union: f64 = params::estimate_cardinality(len, hash_count, bits_set_or);
+        let intersection: f64 = (a + b - union).max(0.0);
+        if union == 0.0 {
+            // Two empty filters hold the same (empty) set
+            return SimilarityEstimate {
+                intersection: 0.0,
+                union: 0.0,
+                jaccard: 1.0,
+                error_bound: 0.0,
+            };
+        }
+
+        // Propagate one standard error of each estimate to J = (|A| + |B|) / |A ∪ B| - 1,
+        // adding them up rather than assuming the estimates are independent (they are not)
+        let error_a: f64 = params::cardinality_standard_error(len, hash_count, a);
+        let error_b: f64 = params::cardinality_standard_error(len, hash_count, b);
+        let error_union: f64 = params::cardinality_standard_error(len, hash_count, union);
+        let error_bound: f64 =
+            (error_a + error_b) / union + (a + b) * error_union / (union * union);
+        return SimilarityEstimate {
+            intersection: intersection,
+            union: union,
+            jaccard: (intersection / union).min(1.0),
+            error_bound: error_bound.min(1.0),
+        };
+    }
+
+    /// Getter for intersection
+    pub fn get_intersection(&self) -> f64 {
+        return self.intersection;
+    }
+
+    /// Getter for union
+    pub fn get_union(&self) -> f64 {
+        return self.union;
+    }
+
+    /// Getter for jaccard
+    pub fn get_jaccard(&self) -> f64 {
+        return self.jaccard;
+    }
+
+    /// Getter for error_bound: the true Jaccard similarity is usually within
+    /// jaccard ± error_bound. The bound widens as the filters fill up
+    pub fn get_error_bound(&self) -> f64 {
+        return self.error_bound;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the false positive rate is the fill ratio to the power k
+    fn test_new() {
+        let stats: FillStatistics = FillStatistics::new(1000, 3, 500);
+        assert_eq!(stats.get_len(), 1000);
+        assert_eq!(stats.get_bits_set(), 500);
+        assert_eq!(stats.get_fill_ratio(), 0.5);
+        assert_eq!(stats.get_false_positive_rate(), 0.125);
+        assert_eq!(
+            FillStatistics::new(1000, 3, 0).get_false_positive_rate(),
+            0.0
+        );
+        assert_eq!(
+            FillStatistics::new(1000, 3, 1000).get_false_positive_rate(),
+            1.0
+        );
+    }
+
+    #[test]
+    /// Test that combined generations add up their bits, and a false positive in any one of
+    /// them is a false positive of the whole
+    fn test_combine() {
+        let stats: FillStatistics = FillStatistics::combine(&[
+            FillStatistics::new(1000, 1, 500),
+            FillStatistics::new(1000, 1, 0),
+            FillStatistics::new(1000, 1, 500),
+        ]);
+        assert_eq!(stats.get_len(), 3000);
+        assert_eq!(stats.get_bits_set(), 1000);
+        assert_eq!(stats.get_false_positive_rate(), 0.75);
+    }
+
+    #[test]
+    /// Test the estimates of disjoint, identical and empty sets
+    fn test_similarity() {
+        // Identical sets: the OR sets no more bits than either filter
+        let same: SimilarityEstimate = SimilarityEstimate::new(1000, 3, 300, 300, 300);
+        assert_eq!(same.get_jaccard(), 1.0);
+        assert_eq!(same.get_intersection(), same.get_union());
+        assert!(same.get_error_bound() > 0.0 && same.get_error_bound() < 0.2);
+
+        // Disjoint sets: expected bits set in the union of two independent halves
+        let disjoint: SimilarityEstimate = SimilarityEstimate::new(1000, 3, 300, 300, 510);
+        assert!(disjoint.get_jaccard() < 0.01);
+
+        let empty: SimilarityEstimate = SimilarityEstimate::new(1000, 3, 0, 0, 0);
+        assert_eq!(empty.get_union(), 0.0);
+        assert_eq!(empty.get_jaccard(), 1.0);
+    }
+}
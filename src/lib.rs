@@ -1,17 +1,53 @@
 /*!
  * lib.rs
  *
- * Implements a Murmur3-based bloom filter variations:
+ * Implements a few bloom filter variations:
  *      BloomFilter               -- a standard bloom filter.
  *      CountingBloomFilter       -- a bloom filter which supports deleting items, using counters
  *                                   instead of bits.
  *      ScopeDecayBloomFilter     -- a scope decay bloom filter, supporting gradual resetting of bits
  *                                   over time.
- *      GenerationalBloomFilter   -- use N generations of bloom filters to support a moving window of data.
+ *      GenerationalBloomFilter   -- use N generations of bloom filters to support a moving window of data,
+ *                                   counted in inserts or, with a generation duration, in time.
  *      BigenerationalBloomFilter -- use 2 alternating generations of bloom filters to support a
  *                                   moving window of data.
  *      A2BufferingBloomFilter    -- use a segmented bloom filter, relying on the active-active
  *                                   buffering strategy to keep recent elements in the current
  *                                   segment.
+ *      ScalableBloomFilter       -- add larger, tighter bloom filter stages as the set grows, keeping
+ *                                   the false positive rate under a target for sets of unknown size.
+ *      BlockedBloomFilter        -- a bloom filter which sets all of an item's bits in one 64-byte
+ *                                   block, so each check touches a single cache line.
+ *      PartitionedBloomFilter    -- a bloom filter split into k slices, with hash i only setting
+ *                                   bits in slice i.
+ *      CuckooFilter              -- a cuckoo filter, storing short fingerprints in buckets, which
+ *                                   supports deletion and counting.
+ *      QuotientFilter            -- a quotient filter, storing fingerprints in a compact hash table,
+ *                                   which supports deletion, doubling in place and merging.
+ *      BinaryFuseFilter          -- an immutable binary fuse filter, built from a set of items,
+ *                                   which takes less space than a bloom filter.
+ *      RibbonFilter              -- an immutable ribbon filter, built from a set of items, for any
+ *                                   false positive rate at close to the minimum space.
+ *      StableBloomFilter         -- a stable bloom filter, decrementing random cells on every
+ *                                   insert so its false positive rate stays fixed over a stream.
+ *      MmapBloomFilter           -- a read-only BloomFilter, memory-mapped from a saved file.
+ *
+ * Every mutable variant implements the Filter trait (and CountingBloomFilter, CuckooFilter and
+ * QuotientFilter also implement DeletableFilter), so callers can be generic over the kind of
+ * filter in use, or choose one at runtime as a `Box<dyn Filter>`. Items are anything
+ * implementing the Item trait (strings, byte buffers, integers, tuples...), which provides a
+ * stable byte encoding to hash.
+ *
+ * Indices are computed with a pluggable hash function (see HashFunction and the BloomHasher
+ * trait): Murmur3 by default, XXH3 or keyed SipHash. Every hasher is implemented in pure Rust.
+ * The k indices of an item are derived from a single digest with enhanced double hashing by
+ * default; IndexingScheme::PerSeed keeps the one-digest-per-index scheme of earlier versions.
+ *
+ * Besides the constructors, which size a filter from the expected inserts and a false positive
+ * rate, the Builder can size any variant explicitly (m, k) or from a memory budget. The
+ * underlying sizing math is public in the params module.
+ *
+ * Time-based expiry reads time through the Clock trait: SystemClock by default, or a
+ * ManualClock which tests can advance by hand.
  */
 pub mod bloom; // Export the module defined in bloom.rs
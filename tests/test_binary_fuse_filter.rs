@@ -0,0 +1,64 @@
+/**
+ * test_binary_fuse_filter.rs
+ *
+ * Integration tests for BinaryFuseFilter.
+ */
+use bloom::bloom::params;
+use bloom::bloom::{BinaryFuseFilter, BloomFilter, Error, HashFunction};
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+
+#[test]
+/// Test that the getters are visible from outside the crate
+fn test_getters() {
+    let items: Vec<&str> = vec!["a", "b", "c", "b"];
+    let bff: BinaryFuseFilter = BinaryFuseFilter::with_hasher(items, 16, HashFunction::Xxh3);
+    assert_eq!(bff.get_fingerprint_bits(), 16);
+    assert_eq!(
+        bff.get_false_positive_rate(),
+        params::binary_fuse_false_positive_rate(16)
+    );
+    assert_eq!(bff.get_num_items(), 3);
+    assert_eq!(bff.get_hasher(), HashFunction::Xxh3);
+    assert!(bff.check("a") && bff.check("b") && bff.check("c"));
+}
+
+#[test]
+/// Test that a large set takes much less space than a BloomFilter at the same rate
+fn test_space() {
+    let items: Vec<u64> = (0..200_000).collect();
+    let bff: BinaryFuseFilter = BinaryFuseFilter::new(&items, 8);
+    assert!(items.iter().all(|i| bff.check(i)));
+    assert!(bff.get_bits_per_item() < 9.5, "{}", bff.get_bits_per_item());
+    let bloom_bits: u64 = params::optimal_len(200_000, bff.get_false_positive_rate());
+    assert!(bloom_bits as f64 / 200_000.0 > 1.2 * bff.get_bits_per_item());
+}
+
+#[test]
+/// Test that duplicate-heavy input still builds, sized for the distinct items
+fn test_duplicates() {
+    let items: Vec<u64> = (0..50_000).map(|i| i % 500).collect();
+    let bff: BinaryFuseFilter = BinaryFuseFilter::try_new(&items, 8).unwrap();
+    assert_eq!(bff.get_num_items(), 500);
+    assert!((0..500u64).all(|i| bff.check(&i)));
+}
+
+#[test]
+/// Test that a filter can be shipped as a file, and rejected as another kind of filter
+fn test_save_load_file() {
+    let items: Vec<String> = (0..10_000).map(|i| format!("blocked-{}", i)).collect();
+    let bff: BinaryFuseFilter = BinaryFuseFilter::new(&items, 16);
+    let mut file: File = tempfile::tempfile().unwrap();
+    bff.save(&mut file).unwrap();
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let loaded: BinaryFuseFilter = BinaryFuseFilter::load(&mut file).unwrap();
+    assert!(items.iter().all(|item| loaded.check(item)));
+    assert_eq!(loaded.get_len(), bff.get_len());
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    assert_eq!(
+        BloomFilter::load(&mut file).err(),
+        Some(Error::Corrupt("unexpected filter kind"))
+    );
+}
@@ -0,0 +1,36 @@
+/**
+ * test_blocked_bloom_filter.rs
+ *
+ * Integration tests for BlockedBloomFilter.
+ */
+use bloom::bloom::params;
+use bloom::bloom::{BlockedBloomFilter, BloomFilter, Filter, HashFunction};
+
+#[test]
+/// Test that the getters are visible from outside the crate
+fn test_getters() {
+    let bf: BlockedBloomFilter = BlockedBloomFilter::with_hasher(10000, 0.01, HashFunction::Xxh3);
+    assert_eq!(bf.get_len(), bf.get_num_blocks() * params::CACHE_LINE_BITS);
+    assert!(bf.get_hash_count() > 0);
+    assert_eq!(bf.get_false_positive_rate(), 0.01);
+    assert_eq!(bf.get_expected_inserts(), 10000);
+    assert_eq!(bf.get_actual_inserts(), 0);
+    assert_eq!(bf.get_hasher(), HashFunction::Xxh3);
+}
+
+#[test]
+/// Test that the blocked filter trades a little memory for one block per query, and still
+/// meets the configured false positive rate, like a BloomFilter
+fn test_compare_with_bloom_filter() {
+    let mut blocked: BlockedBloomFilter = BlockedBloomFilter::new(50000, 0.001);
+    let mut standard: BloomFilter = BloomFilter::new(50000, 0.001);
+    for i in 0..50000u64 {
+        Filter::insert(&mut blocked, &i);
+        standard.insert(&i);
+    }
+    assert!(blocked.get_len() > standard.get_len());
+    assert!(blocked.get_len() < 2 * standard.get_len());
+    assert!((0..50000u64).all(|i| blocked.check(&i)));
+    let false_positives = (50000..1050000u64).filter(|i| blocked.check(i)).count();
+    assert!(false_positives < 1150, "{}", false_positives);
+}
@@ -4,6 +4,7 @@
  * Integration tests for BloomFilter.
  */
 use bloom::bloom::BloomFilter;
+use bloom::bloom::Error;
 
 #[test]
 /// Test that the getter for hash_count is visible from outside the crate
@@ -48,3 +49,114 @@ fn test_false_positive_rate() {
     }
     assert!((false_positives as f64) < (90000.0 * 0.011));
 }
+
+#[test]
+/// Test that non-string items can be inserted and checked without converting them first
+fn test_generic_items() {
+    let mut bf: BloomFilter = BloomFilter::new(1000, 0.01);
+    for i in 0..100u64 {
+        bf.insert(&i);
+    }
+    bf.insert(&b"some bytes"[..]);
+    bf.insert(&(42u32, "tuple"));
+    for i in 0..100u64 {
+        assert!(bf.check(&i));
+    }
+    assert!(bf.check(b"some bytes"));
+    assert!(bf.check(&vec![
+        b's', b'o', b'm', b'e', b' ', b'b', b'y', b't', b'e', b's'
+    ]));
+    assert!(bf.check(&(42u32, "tuple")));
+    assert!(!bf.check(&(42u32, "other")));
+}
+
+#[test]
+/// Test that a str and its bytes are the same item
+fn test_str_and_bytes_equivalent() {
+    let mut bf: BloomFilter = BloomFilter::new(1000, 0.01);
+    bf.insert("This is a test string");
+    assert!(bf.check("This is a test string".as_bytes()));
+    assert!(bf.check(&"This is a test string".to_string()));
+}
+
+#[test]
+/// Test merging per-worker filters built with the same parameters
+fn test_merge_shards() {
+    let mut shards: Vec<BloomFilter> = (0..4).map(|_| BloomFilter::new(4000, 0.01)).collect();
+    for i in 0..4000u64 {
+        shards[(i % 4) as usize].insert(&i);
+        // Duplicates inflate the per-shard insert counts, but not the merged estimate
+        shards[(i % 4) as usize].insert(&i);
+    }
+    let mut merged: BloomFilter = shards[0].clone();
+    for shard in shards[1..].iter() {
+        merged.union_with(shard).unwrap();
+    }
+    assert!((0..4000u64).all(|i| merged.check(&i)));
+    let estimate: u64 = merged.get_actual_inserts();
+    assert!(estimate > 3800 && estimate < 4200, "{}", estimate);
+    assert_eq!(
+        merged.union(&BloomFilter::new(10, 0.01)).err(),
+        Some(Error::IncompatibleFilters)
+    );
+}
+
+#[test]
+/// Test estimating the overlap of two sets from their filters, without merging them
+fn test_estimate_overlap() {
+    let mut visitors_monday: BloomFilter = BloomFilter::new(10000, 0.01);
+    let mut visitors_tuesday: BloomFilter = BloomFilter::new(10000, 0.01);
+    for i in 0..6000u64 {
+        visitors_monday.insert(&i);
+    }
+    for i in 4000..10000u64 {
+        visitors_tuesday.insert(&i);
+    }
+    let union: f64 = visitors_monday
+        .estimate_union_cardinality(&visitors_tuesday)
+        .unwrap();
+    let returning: f64 = visitors_monday
+        .estimate_intersection_cardinality(&visitors_tuesday)
+        .unwrap();
+    assert!((union - 10000.0).abs() < 300.0, "{}", union);
+    assert!((returning - 2000.0).abs() < 300.0, "{}", returning);
+}
+
+#[test]
+/// Test that the estimated current false positive rate tracks the measured rate of an
+/// overfilled filter, rather than the configured one
+fn test_overfilled_false_positive_rate() {
+    let mut bf: BloomFilter = BloomFilter::new(1000, 0.01);
+    for i in 0..2000u64 {
+        bf.insert(&i);
+    }
+    let estimated: f64 = bf.fill_statistics().get_false_positive_rate();
+    let measured: f64 = (2000..102000u64).filter(|i| bf.check(i)).count() as f64 / 100000.0;
+    assert_eq!(bf.get_false_positive_rate(), 0.01);
+    assert!(
+        (estimated - measured).abs() < 0.02,
+        "{} {}",
+        estimated,
+        measured
+    );
+    assert!(measured > 0.1);
+}
+
+#[test]
+/// Test comparing the sets held by filters built with the same parameters, without the sets
+fn test_estimate_similarity() {
+    let mut europe: BloomFilter = BloomFilter::new(10000, 0.01);
+    let mut asia: BloomFilter = BloomFilter::new(10000, 0.01);
+    let mut america: BloomFilter = BloomFilter::new(10000, 0.01);
+    for i in 0..8000u64 {
+        europe.insert(&format!("interest-{}", i));
+        asia.insert(&format!("interest-{}", i + 2000));
+        america.insert(&format!("interest-{}", i + 20000));
+    }
+    let close = europe.estimate_similarity(&asia).unwrap();
+    let far = europe.estimate_similarity(&america).unwrap();
+    // 6000 shared interests out of 10000
+    assert!((close.get_jaccard() - 0.6).abs() < close.get_error_bound());
+    assert!(far.get_jaccard() < far.get_error_bound());
+    assert!(close.get_jaccard() > far.get_jaccard());
+}
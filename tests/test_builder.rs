@@ -0,0 +1,211 @@
+/**
+ * test_builder.rs
+ *
+ * Integration tests for the Builder and its sizing modes.
+ */
+use bloom::bloom::params::{self, Rounding};
+use bloom::bloom::{BloomFilter, Builder, Error, Filter, HashFunction, IndexingScheme, Sizing};
+
+/// Insert `inserts` items and return the number of false positives among 10000 others
+fn false_positives<F: Filter>(filter: &mut F, inserts: u64) -> u64 {
+    for i in 0..inserts {
+        filter.insert(&i);
+    }
+    for i in 0..inserts {
+        assert!(filter.check(&i));
+    }
+    (inserts..inserts + 10000)
+        .filter(|i| filter.check(i))
+        .count() as u64
+}
+
+#[test]
+/// Test that sizing by expected inserts matches the plain constructors
+fn test_expected_inserts() {
+    let builder = Builder::new(Sizing::ExpectedInserts {
+        expected_inserts: 1000,
+        false_positive_rate: 0.01,
+    });
+    let bf = builder.build_bloom_filter().unwrap();
+    let plain = bloom::bloom::BloomFilter::new(1000, 0.01);
+    assert_eq!(bf.get_len(), plain.get_len());
+    assert_eq!(bf.get_hash_count(), plain.get_hash_count());
+}
+
+#[test]
+/// Test that an explicit length and hash count are used as given
+fn test_explicit() {
+    let mut bf = Builder::new(Sizing::Explicit {
+        len: 4096,
+        hash_count: 3,
+    })
+    .build_bloom_filter()
+    .unwrap();
+    assert_eq!(bf.get_len(), 4096);
+    assert_eq!(Filter::get_hash_count(&bf), 3);
+    assert!(false_positives(&mut bf, 200) < 100);
+}
+
+#[test]
+/// Test that a memory budget plus a false positive rate gives a filter meeting that rate
+/// at the reported capacity
+fn test_memory_and_false_positive_rate() {
+    let builder = Builder::new(Sizing::MemoryAndFalsePositiveRate {
+        bytes: 2048,
+        false_positive_rate: 0.01,
+    });
+    let params = builder.parameters().unwrap();
+    assert_eq!(params.get_len(), 16384);
+    assert!(params.get_expected_inserts() > 1500);
+    let mut bf = builder.build_bloom_filter().unwrap();
+    let fp = false_positives(&mut bf, params.get_expected_inserts());
+    assert!(fp < 200, "{} false positives", fp);
+}
+
+#[test]
+/// Test that every variant can be built from a memory budget, with its extra knobs
+fn test_every_variant() {
+    let builder = Builder::new(Sizing::MemoryAndInserts {
+        bytes: 4096,
+        expected_inserts: 500,
+    })
+    .hasher(HashFunction::Xxh3)
+    .indexing(IndexingScheme::PerSeed)
+    .num_generations(4)
+    .bit_reset_rate(0.1);
+
+    let mut bf = builder.build_bloom_filter().unwrap();
+    assert_eq!(bf.get_len(), 32768);
+    assert_eq!(bf.get_hasher(), HashFunction::Xxh3);
+    assert_eq!(bf.get_indexing(), IndexingScheme::PerSeed);
+    assert!(false_positives(&mut bf, 500) < 50);
+
+    let mut cbf = builder.build_counting_bloom_filter().unwrap();
+    assert_eq!(cbf.get_len(), 512);
+    cbf.insert("hello");
+    cbf.delete("hello");
+    assert!(!cbf.check("hello"));
+
+    let mut sdbf = builder.build_scope_decay_bloom_filter().unwrap();
+    assert_eq!(sdbf.get_bit_reset_rate(), 0.1);
+    sdbf.insert("hello");
+    assert!(sdbf.check("hello"));
+
+    let mut gbf = builder.build_generational_bloom_filter().unwrap();
+    assert_eq!(gbf.get_num_generations(), 4);
+    gbf.insert("hello");
+    assert!(gbf.check("hello"));
+
+    let mut bgbf = builder.build_bigenerational_bloom_filter().unwrap();
+    bgbf.insert("hello");
+    assert!(bgbf.check("hello"));
+
+    let mut a2bf = builder.build_a2buffering_bloom_filter().unwrap();
+    a2bf.insert("hello");
+    assert!(a2bf.check("hello"));
+}
+
+#[test]
+/// Test that invalid sizings return errors
+fn test_invalid_sizing() {
+    let builder = Builder::new(Sizing::ExpectedInserts {
+        expected_inserts: 0,
+        false_positive_rate: 0.01,
+    });
+    assert_eq!(
+        builder.build_bloom_filter().err(),
+        Some(Error::InvalidExpectedInserts(0))
+    );
+    let builder = Builder::new(Sizing::MemoryAndFalsePositiveRate {
+        bytes: 4096,
+        false_positive_rate: 1.0,
+    });
+    assert_eq!(
+        builder.build_bloom_filter().err(),
+        Some(Error::InvalidFalsePositiveRate(1.0))
+    );
+}
+
+#[test]
+/// Test that a filter built from an oversized memory budget can be saved and loaded back
+fn test_save_load() {
+    let mut bf: BloomFilter = Builder::new(Sizing::MemoryAndInserts {
+        bytes: 1 << 20,
+        expected_inserts: 10,
+    })
+    .build_bloom_filter()
+    .unwrap();
+    assert_eq!(bf.get_hash_count(), params::MAX_HASH_COUNT);
+    for i in 0..10 {
+        bf.insert(&i);
+    }
+    let mut bytes: Vec<u8> = Vec::new();
+    bf.save(&mut bytes).unwrap();
+    let loaded: BloomFilter = BloomFilter::load(&mut bytes.as_slice()).unwrap();
+    assert_eq!(loaded.get_len(), bf.get_len());
+    assert_eq!(loaded.get_hash_count(), bf.get_hash_count());
+    assert_eq!(
+        loaded.get_false_positive_rate(),
+        bf.get_false_positive_rate()
+    );
+    assert!((0..10).all(|i| loaded.check(&i)));
+}
+
+#[test]
+/// Test that the public sizing math agrees with the filters built from it
+fn test_params() {
+    let len = params::optimal_len(1000, 0.01);
+    let hash_count = params::optimal_hash_count(1000, len);
+    let bf = bloom::bloom::BloomFilter::new(1000, 0.01);
+    assert_eq!(bf.get_len(), len);
+    assert_eq!(bf.get_hash_count(), hash_count);
+    assert!(params::capacity(len, 0.01) >= 1000);
+    assert!(params::expected_false_positive_rate(len, hash_count, 1000) <= 0.0101);
+
+    let rounded = Builder::new(Sizing::ExpectedInserts {
+        expected_inserts: 1000,
+        false_positive_rate: 0.01,
+    })
+    .rounding(Rounding::Words)
+    .build_bloom_filter()
+    .unwrap();
+    assert_eq!(
+        rounded.get_len(),
+        params::round_len_up(len, Rounding::Words)
+    );
+    assert_eq!(rounded.get_len() % 64, 0);
+}
+
+#[test]
+/// Test sizing a filter for peak load so that it can be folded, and shipping a folded copy
+fn test_foldable_sizing() {
+    let mut peak = Builder::new(Sizing::ExpectedInserts {
+        expected_inserts: 100000,
+        false_positive_rate: 0.001,
+    })
+    .rounding(Rounding::Foldable(4))
+    .build_bloom_filter()
+    .unwrap();
+    assert_eq!(peak.get_len() % 16, 0);
+    for i in 0..10000u64 {
+        peak.insert(&i);
+    }
+
+    let edge = peak.fold(4).unwrap();
+    assert_eq!(edge.get_len(), peak.get_len() / 16);
+    assert!((0..10000u64).all(|i| edge.check(&i)));
+    // The folded filter keeps the hash count, so it behaves like a filter of its length
+    let fpr = params::expected_false_positive_rate(edge.get_len(), edge.get_hash_count(), 10000);
+    let false_positives = (10000..110000u64).filter(|i| edge.check(i)).count() as f64;
+    assert!(
+        (false_positives / 100000.0 - fpr).abs() < 0.003,
+        "{}",
+        false_positives
+    );
+
+    let mut bytes: Vec<u8> = Vec::new();
+    edge.save(&mut bytes).unwrap();
+    let loaded = BloomFilter::load(&mut &bytes[..]).unwrap();
+    assert!((0..10000u64).all(|i| loaded.check(&i)));
+    assert_eq!(peak.fold(40).err(), Some(Error::InvalidFoldCount(40)));
+}
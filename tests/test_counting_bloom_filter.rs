@@ -48,3 +48,17 @@ fn test_false_positive_rate() {
     }
     assert!((false_positives as f64) < (90000.0 * 0.011));
 }
+
+#[test]
+/// Test that non-string items can be inserted, checked and deleted
+fn test_generic_items() {
+    let mut bf: CountingBloomFilter = CountingBloomFilter::new(1000, 0.01);
+    for i in 0..100i32 {
+        bf.insert(&i);
+    }
+    for i in 0..100i32 {
+        assert!(bf.check(&i));
+    }
+    bf.delete(&7i32);
+    assert!(!bf.check(&7i32));
+}
@@ -0,0 +1,86 @@
+/**
+ * test_cuckoo_filter.rs
+ *
+ * Integration tests for CuckooFilter.
+ */
+use bloom::bloom::params;
+use bloom::bloom::{CuckooFilter, DeletableFilter, Error, HashFunction};
+
+#[test]
+/// Test that the getters are visible from outside the crate
+fn test_getters() {
+    let cf: CuckooFilter = CuckooFilter::with_hasher(10000, 0.001, HashFunction::Xxh3);
+    assert_eq!(cf.get_bucket_size(), 4);
+    assert_eq!(
+        cf.get_fingerprint_bits(),
+        params::cuckoo_fingerprint_bits(0.001, 4)
+    );
+    assert_eq!(cf.get_num_buckets(), params::cuckoo_num_buckets(10000, 4));
+    assert_eq!(cf.get_len(), cf.get_num_buckets() * 4);
+    assert!(cf.get_false_positive_rate() <= 0.001);
+    assert_eq!(cf.get_expected_inserts(), 10000);
+    assert_eq!(cf.get_actual_inserts(), 0);
+    assert_eq!(cf.get_hasher(), HashFunction::Xxh3);
+}
+
+#[test]
+/// Test that the measured false positive rate meets the target at the expected inserts
+fn test_false_positive_rate() {
+    let mut cf: CuckooFilter = CuckooFilter::new(10000, 0.01);
+    for i in 0..10000 {
+        cf.insert(&i);
+    }
+    assert!((0..10000).all(|i| cf.check(&i)));
+    let false_positives: usize = (10000..110000).filter(|i| cf.check(i)).count();
+    assert!(false_positives < 1000, "{}", false_positives);
+    let estimate: f64 = cf.fill_statistics().get_false_positive_rate();
+    assert!((estimate - false_positives as f64 / 100000.0).abs() < 0.002);
+}
+
+#[test]
+/// Test that a custom layout can be filled close to capacity, and then reports FilterFull
+fn test_custom_layout() {
+    let mut cf: CuckooFilter = CuckooFilter::with_layout(1000, 16, 8, HashFunction::default());
+    assert_eq!(cf.get_fingerprint_bits(), 16);
+    assert_eq!(cf.get_bucket_size(), 8);
+    let mut inserted: u64 = 0;
+    let error: Error = loop {
+        match cf.try_insert(&inserted) {
+            Ok(()) => inserted += 1,
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(error, Error::FilterFull);
+    assert_eq!(cf.get_actual_inserts(), inserted);
+    assert!(inserted as f64 > 0.9 * cf.get_len() as f64, "{}", inserted);
+    assert!((0..inserted).all(|i| cf.check(&i)));
+}
+
+/// Insert every item, then delete the first half of them, generically over the filter type
+fn insert_then_delete_half<F: DeletableFilter>(filter: &mut F, items: &[u64]) {
+    for item in items {
+        filter.insert(item);
+    }
+    for item in &items[..items.len() / 2] {
+        filter.delete(item);
+    }
+}
+
+#[test]
+/// Test that deletion works through the DeletableFilter trait, and duplicates are counted
+fn test_deletable_filter() {
+    let mut cf: CuckooFilter = CuckooFilter::new(1000, 0.001);
+    let items: Vec<u64> = (0..1000).collect();
+    insert_then_delete_half(&mut cf, &items);
+    assert!(items[500..].iter().all(|i| cf.check(i)));
+    assert!(items[..500].iter().filter(|i| cf.check(*i)).count() < 5);
+    assert_eq!(cf.get_actual_inserts(), 500);
+
+    cf.insert("duplicate");
+    cf.insert("duplicate");
+    cf.insert("duplicate");
+    assert_eq!(cf.count("duplicate"), 3);
+    cf.delete("duplicate");
+    assert_eq!(cf.count("duplicate"), 2);
+    assert_eq!(cf.try_delete("never inserted"), Ok(()));
+}
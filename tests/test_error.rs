@@ -0,0 +1,118 @@
+/**
+ * test_error.rs
+ *
+ * Integration tests for the fallible constructors and mutation methods.
+ */
+use bloom::bloom::{
+    A2BufferingBloomFilter, BigenerationalBloomFilter, BloomFilter, CountingBloomFilter,
+    DeletableFilter, Error, GenerationalBloomFilter, ScopeDecayBloomFilter,
+};
+
+#[test]
+/// Test that try_new returns an error instead of panicking on an invalid false positive rate
+fn test_invalid_false_positive_rate() {
+    for fpr in [0.0, -0.03, 1.0, 1.5, f64::NAN].iter() {
+        assert!(matches!(
+            BloomFilter::try_new(100, *fpr),
+            Err(Error::InvalidFalsePositiveRate(_))
+        ));
+        assert!(matches!(
+            CountingBloomFilter::try_new(100, *fpr),
+            Err(Error::InvalidFalsePositiveRate(_))
+        ));
+        assert!(matches!(
+            ScopeDecayBloomFilter::try_new(100, *fpr, 0.1),
+            Err(Error::InvalidFalsePositiveRate(_))
+        ));
+        assert!(matches!(
+            GenerationalBloomFilter::try_new(100, *fpr, 3),
+            Err(Error::InvalidFalsePositiveRate(_))
+        ));
+        assert!(matches!(
+            BigenerationalBloomFilter::try_new(100, *fpr),
+            Err(Error::InvalidFalsePositiveRate(_))
+        ));
+        assert!(matches!(
+            A2BufferingBloomFilter::try_new(100, *fpr),
+            Err(Error::InvalidFalsePositiveRate(_))
+        ));
+    }
+}
+
+#[test]
+/// Test that try_new returns an error instead of panicking on zero expected inserts
+fn test_invalid_expected_inserts() {
+    assert_eq!(
+        BloomFilter::try_new(0, 0.01).err(),
+        Some(Error::InvalidExpectedInserts(0))
+    );
+    assert_eq!(
+        CountingBloomFilter::try_new(0, 0.01).err(),
+        Some(Error::InvalidExpectedInserts(0))
+    );
+    assert_eq!(
+        ScopeDecayBloomFilter::try_new(0, 0.01, 0.1).err(),
+        Some(Error::InvalidExpectedInserts(0))
+    );
+    assert_eq!(
+        GenerationalBloomFilter::try_new(0, 0.01, 3).err(),
+        Some(Error::InvalidExpectedInserts(0))
+    );
+    assert_eq!(
+        BigenerationalBloomFilter::try_new(0, 0.01).err(),
+        Some(Error::InvalidExpectedInserts(0))
+    );
+    assert_eq!(
+        A2BufferingBloomFilter::try_new(0, 0.01).err(),
+        Some(Error::InvalidExpectedInserts(0))
+    );
+}
+
+#[test]
+/// Test the variant-specific validation errors
+fn test_invalid_variant_parameters() {
+    assert_eq!(
+        GenerationalBloomFilter::try_new(100, 0.01, 1).err(),
+        Some(Error::InvalidNumGenerations(1))
+    );
+    assert_eq!(
+        ScopeDecayBloomFilter::try_new(100, 0.01, -0.7).err(),
+        Some(Error::InvalidBitResetRate(-0.7))
+    );
+    assert_eq!(
+        ScopeDecayBloomFilter::try_new(100, 0.01, 33.7).err(),
+        Some(Error::InvalidBitResetRate(33.7))
+    );
+}
+
+#[test]
+/// Test that valid parameters still build a working filter
+fn test_try_new_valid() {
+    let mut bf: BloomFilter = BloomFilter::try_new(100, 0.01).unwrap();
+    bf.insert("This is a test string");
+    assert!(bf.check("This is a test string"));
+    let gbf: GenerationalBloomFilter = GenerationalBloomFilter::try_new(100, 0.01, 2).unwrap();
+    assert_eq!(gbf.get_num_generations(), 2);
+}
+
+#[test]
+/// Test that the fallible mutation paths of CountingBloomFilter succeed on valid use
+fn test_counting_try_insert_delete() {
+    let mut bf: CountingBloomFilter = CountingBloomFilter::try_new(100, 0.01).unwrap();
+    assert_eq!(bf.try_insert("This is a test string"), Ok(()));
+    assert!(bf.check("This is a test string"));
+    assert_eq!(
+        DeletableFilter::try_delete(&mut bf, "This is a test string"),
+        Ok(())
+    );
+    assert!(!bf.check("This is a test string"));
+    // Deleting an item which is not present is a no-op
+    assert_eq!(bf.try_delete("This is a test string"), Ok(()));
+}
+
+#[test]
+/// Test that the error type can be used as a boxed std::error::Error
+fn test_std_error() {
+    let err: Box<dyn std::error::Error> = Box::new(BloomFilter::try_new(0, 0.01).err().unwrap());
+    assert!(err.to_string().contains("Expected number of inserts"));
+}
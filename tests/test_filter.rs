@@ -0,0 +1,116 @@
+/**
+ * test_filter.rs
+ *
+ * Integration tests for the Filter and DeletableFilter traits.
+ */
+use bloom::bloom::{
+    A2BufferingBloomFilter, BigenerationalBloomFilter, BloomFilter, CountingBloomFilter,
+    CuckooFilter, DeletableFilter, Filter, GenerationalBloomFilter, ScopeDecayBloomFilter,
+};
+
+/// Insert 0..100 into a filter and check that every item is found afterwards
+fn fill_and_check<F: Filter>(bf: &mut F) {
+    for i in 0..100 {
+        bf.insert(&i.to_string());
+    }
+    for i in 0..100 {
+        assert!(bf.check(&i.to_string()));
+    }
+    assert_eq!(bf.get_actual_inserts(), 100);
+    assert_eq!(bf.get_expected_inserts(), 1000);
+    assert_eq!(bf.get_false_positive_rate(), 0.01);
+    assert!(bf.get_hash_count() > 0);
+}
+
+#[test]
+/// Test that every filter variant can be used through the Filter trait
+fn test_generic_filter() {
+    fill_and_check(&mut BloomFilter::new(1000, 0.01));
+    fill_and_check(&mut CountingBloomFilter::new(1000, 0.01));
+    fill_and_check(&mut ScopeDecayBloomFilter::new(1000, 0.01, 0.1));
+    fill_and_check(&mut GenerationalBloomFilter::new(1000, 0.01, 3));
+    fill_and_check(&mut BigenerationalBloomFilter::new(1000, 0.01));
+    fill_and_check(&mut A2BufferingBloomFilter::new(1000, 0.01));
+}
+
+#[test]
+/// Test that the filter variant can be chosen at runtime, with a single generic call site
+fn test_configured_filter() {
+    fn dedup<F: Filter>(bf: &mut F, items: &[&str]) -> u64 {
+        let mut duplicates: u64 = 0;
+        for item in items {
+            if bf.check(item) {
+                duplicates += 1;
+            } else {
+                bf.insert(item);
+            }
+        }
+        duplicates
+    }
+    let items = ["a", "b", "a", "c", "b", "a"];
+    for kind in &["plain", "decaying", "windowed"] {
+        let duplicates = match *kind {
+            "plain" => dedup(&mut BloomFilter::new(1000, 0.01), &items),
+            "decaying" => dedup(&mut ScopeDecayBloomFilter::new(1000, 0.01, 0.1), &items),
+            _ => dedup(&mut GenerationalBloomFilter::new(1000, 0.01, 3), &items),
+        };
+        assert_eq!(duplicates, 3);
+    }
+}
+
+#[test]
+/// Test that filters chosen at runtime can be held as trait objects, through either the byte
+/// or the generic methods
+fn test_boxed_filters() {
+    let mut filters: Vec<Box<dyn Filter>> = vec![
+        Box::new(BloomFilter::new(1000, 0.01)),
+        Box::new(CountingBloomFilter::new(1000, 0.01)),
+        Box::new(GenerationalBloomFilter::new(1000, 0.01, 3)),
+        Box::new(A2BufferingBloomFilter::new(1000, 0.01)),
+        Box::new(ScopeDecayBloomFilter::new(1000, 0.01, 0.1)),
+    ];
+    for bf in filters.iter_mut() {
+        fill_and_check(bf);
+        bf.insert_bytes(b"bytes");
+        assert!(bf.check_bytes(b"bytes"));
+        assert!(bf.check(&b"bytes"[..]));
+        bf.empty();
+        assert!(!bf.check_bytes(b"bytes"));
+    }
+
+    let mut deletable: Box<dyn DeletableFilter> = Box::new(CuckooFilter::new(1000, 0.01));
+    deletable.insert("This is a test string");
+    deletable.delete("This is a test string");
+    assert!(!deletable.check("This is a test string"));
+}
+
+#[test]
+/// Test that CountingBloomFilter can be used through the DeletableFilter trait
+fn test_deletable_filter() {
+    fn insert_and_delete<F: DeletableFilter>(bf: &mut F) {
+        bf.insert("This is a test string");
+        assert!(bf.check("This is a test string"));
+        bf.delete("This is a test string");
+        assert!(!bf.check("This is a test string"));
+    }
+    insert_and_delete(&mut CountingBloomFilter::new(1000, 0.01));
+}
+
+#[test]
+/// Test that checking an A2BufferingBloomFilter through the Filter trait still copies items
+/// found in the inactive generation into the active one
+fn test_a2buffering_trait_check() {
+    fn check_all<F: Filter>(bf: &mut F, items: std::ops::Range<u64>) {
+        for i in items {
+            assert!(bf.check(&i));
+        }
+    }
+    let mut bf: A2BufferingBloomFilter = A2BufferingBloomFilter::new(500, 0.01);
+    for i in 0..700u64 {
+        bf.insert(&i);
+    }
+    let before: Vec<f64> = bf.estimate_generation_cardinalities();
+    check_all(&mut bf, 0..100);
+    let after: Vec<f64> = bf.estimate_generation_cardinalities();
+    assert!(after[1] - before[1] > 90.0, "{:?} {:?}", before, after);
+}
@@ -3,7 +3,8 @@
  *
  * Integration tests for GenerationalBloomFilter.
  */
-use bloom::bloom::GenerationalBloomFilter;
+use bloom::bloom::{Filter, GenerationalBloomFilter, ManualClock};
+use std::time::Duration;
 
 #[test]
 /// Test that we can check the number of generations via the getter
@@ -61,3 +62,29 @@ fn test_false_positive_rate() {
     }
     assert!((false_positives as f64) < (90000.0 * 0.011));
 }
+
+/// Report whether an event was seen recently, remembering it either way
+fn seen<F: Filter>(filter: &mut F, event: &str) -> bool {
+    let seen: bool = filter.check(event);
+    filter.insert(event);
+    seen
+}
+
+#[test]
+/// Test suppressing repeats within a time window, with a fake clock: 3 generations of 5
+/// minutes remember an event for at least 10 minutes, and at most 15
+fn test_time_window() {
+    let clock: ManualClock = ManualClock::new();
+    let mut bf: GenerationalBloomFilter = GenerationalBloomFilter::new(10000, 0.001, 3)
+        .with_clock(clock.clone())
+        .with_generation_duration(Duration::from_secs(300));
+    assert!(!seen(&mut bf, "event"));
+    clock.advance(Duration::from_secs(599));
+    assert!(!seen(&mut bf, "other"));
+    assert!(bf.check("event"));
+    clock.advance(Duration::from_secs(301));
+    assert!(!bf.check("event"));
+    assert!(bf.check("other"));
+    assert!(!seen(&mut bf, "event"));
+    assert!(seen(&mut bf, "event"));
+}
@@ -0,0 +1,111 @@
+/**
+ * test_hashing.rs
+ *
+ * Integration tests for the pluggable hash functions.
+ */
+use bloom::bloom::{
+    BloomFilter, GenerationalBloomFilter, HashFunction, IndexingScheme, ScopeDecayBloomFilter,
+    SipHasher,
+};
+
+/// Measure the false positive count of a BloomFilter filled with 0..10000, over 90000 probes
+fn false_positives(hasher: HashFunction) -> u64 {
+    let mut bf: BloomFilter = BloomFilter::with_hasher(10000, 0.01, hasher);
+    for i in 0..10000 {
+        bf.insert(&i.to_string());
+    }
+    for i in 0..10000 {
+        assert!(bf.check(&i.to_string()));
+    }
+    let mut false_positives: u64 = 0;
+    for i in 10000..100000 {
+        if bf.check(&i.to_string()) {
+            false_positives += 1;
+        }
+    }
+    false_positives
+}
+
+#[test]
+/// Ensure that the false positive rate is close to the target for every hash function
+fn test_false_positive_rate() {
+    let hashers = [
+        HashFunction::Murmur3,
+        HashFunction::Xxh3,
+        HashFunction::SipHash(SipHasher::new(0x0123_4567, 0x89ab_cdef)),
+    ];
+    for hasher in hashers.iter() {
+        assert!((false_positives(*hasher) as f64) < (90000.0 * 0.011));
+    }
+}
+
+#[test]
+/// Test that new() uses Murmur3 and with_hasher() records the chosen hash function
+fn test_get_hasher() {
+    let bf: BloomFilter = BloomFilter::new(100, 0.01);
+    assert_eq!(bf.get_hasher(), HashFunction::Murmur3);
+    let sdbf: ScopeDecayBloomFilter =
+        ScopeDecayBloomFilter::with_hasher(100, 0.01, 0.1, HashFunction::Xxh3);
+    assert_eq!(sdbf.get_hasher(), HashFunction::Xxh3);
+    let gbf: GenerationalBloomFilter =
+        GenerationalBloomFilter::with_hasher(100, 0.01, 3, HashFunction::Xxh3);
+    assert_eq!(gbf.get_hasher(), HashFunction::Xxh3);
+}
+
+#[test]
+/// Test that filters with different SipHash keys set different bits
+fn test_siphash_keys() {
+    let mut a: BloomFilter =
+        BloomFilter::with_hasher(10, 0.1, HashFunction::SipHash(SipHasher::new(1, 2)));
+    let mut b: BloomFilter =
+        BloomFilter::with_hasher(10, 0.1, HashFunction::SipHash(SipHasher::new(3, 4)));
+    for i in 0..10 {
+        a.insert(&i);
+        b.insert(&i);
+    }
+    let mut differences: u64 = 0;
+    for i in 10..1000 {
+        if a.check(&i) != b.check(&i) {
+            differences += 1;
+        }
+    }
+    assert!(differences > 0);
+}
+
+#[test]
+/// Ensure that both indexing schemes keep the false positive rate close to the target
+fn test_indexing_schemes() {
+    for indexing in [IndexingScheme::PerSeed, IndexingScheme::DoubleHashing].iter() {
+        let mut bf: BloomFilter =
+            BloomFilter::with_hashing(10000, 0.01, HashFunction::Murmur3, *indexing);
+        assert_eq!(bf.get_indexing(), *indexing);
+        for i in 0..10000 {
+            bf.insert(&i.to_string());
+        }
+        for i in 0..10000 {
+            assert!(bf.check(&i.to_string()));
+        }
+        let mut false_positives: u64 = 0;
+        for i in 10000..100000 {
+            if bf.check(&i.to_string()) {
+                false_positives += 1;
+            }
+        }
+        assert!((false_positives as f64) < (90000.0 * 0.011));
+    }
+}
+
+#[test]
+/// Test that new() uses DoubleHashing, and that windowed filters pass the scheme through
+fn test_get_indexing() {
+    let bf: BloomFilter = BloomFilter::new(100, 0.01);
+    assert_eq!(bf.get_indexing(), IndexingScheme::DoubleHashing);
+    let gbf: GenerationalBloomFilter = GenerationalBloomFilter::with_hashing(
+        100,
+        0.01,
+        3,
+        HashFunction::Murmur3,
+        IndexingScheme::PerSeed,
+    );
+    assert_eq!(gbf.get_indexing(), IndexingScheme::PerSeed);
+}
@@ -0,0 +1,64 @@
+/**
+ * test_mmap.rs
+ *
+ * Integration tests for the read-only, memory-mapped MmapBloomFilter.
+ */
+use bloom::bloom::{BloomFilter, Error, MmapBloomFilter};
+use std::fs::File;
+
+#[test]
+/// Test that a filter saved to a path can be mapped and queried, by several readers at once
+fn test_open_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("urls.bloom");
+    let mut bf = BloomFilter::new(10000, 0.001);
+    for i in 0..10000u64 {
+        bf.insert(&format!("https://example.com/{}", i));
+    }
+    bf.save(&mut File::create(&path).unwrap()).unwrap();
+
+    let first = MmapBloomFilter::open(&path).unwrap();
+    let second = MmapBloomFilter::open(&path).unwrap();
+    first.verify().unwrap();
+    assert_eq!(first.get_len(), bf.get_len());
+    assert_eq!(first.get_expected_inserts(), 10000);
+    assert_eq!(first.get_false_positive_rate(), 0.001);
+    for i in 0..10000u64 {
+        let url = format!("https://example.com/{}", i);
+        assert!(first.check(&url));
+        assert!(second.check(&url));
+    }
+    let false_positives = (10000..20000u64)
+        .filter(|i| first.check(&format!("https://example.com/{}", i)))
+        .count();
+    assert!(false_positives < 30, "{} false positives", false_positives);
+}
+
+#[test]
+/// Test that files which aren't saved filters are rejected
+fn test_open_invalid() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("junk");
+    std::fs::write(&path, b"definitely not a bloom filter").unwrap();
+    assert_eq!(
+        MmapBloomFilter::open(&path).err(),
+        Some(Error::InvalidMagic)
+    );
+    assert!(matches!(
+        MmapBloomFilter::open(dir.path().join("missing")),
+        Err(Error::Io(std::io::ErrorKind::NotFound))
+    ));
+}
+
+#[test]
+/// Test that the mapped filter reports the same fill statistics as the filter it was saved from
+fn test_fill_statistics() {
+    let mut bf = BloomFilter::new(1000, 0.01);
+    for i in 0..700u64 {
+        bf.insert(&i);
+    }
+    let mut file = tempfile::tempfile().unwrap();
+    bf.save(&mut file).unwrap();
+    let mbf = MmapBloomFilter::from_file(&file).unwrap();
+    assert_eq!(mbf.fill_statistics(), bf.fill_statistics());
+}
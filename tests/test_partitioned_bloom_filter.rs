@@ -0,0 +1,59 @@
+/**
+ * test_partitioned_bloom_filter.rs
+ *
+ * Integration tests for PartitionedBloomFilter.
+ */
+use bloom::bloom::params;
+use bloom::bloom::{Error, Filter, HashFunction, IndexingScheme, PartitionedBloomFilter};
+
+#[test]
+/// Test that the getters are visible from outside the crate
+fn test_getters() {
+    let bf: PartitionedBloomFilter = PartitionedBloomFilter::with_hashing(
+        10000,
+        0.001,
+        HashFunction::Xxh3,
+        IndexingScheme::PerSeed,
+    );
+    assert_eq!(bf.get_hash_count(), params::optimal_partition_count(0.001));
+    assert_eq!(bf.get_len(), bf.get_slice_len() * bf.get_hash_count());
+    assert_eq!(bf.get_false_positive_rate(), 0.001);
+    assert_eq!(bf.get_expected_inserts(), 10000);
+    assert_eq!(bf.get_actual_inserts(), 0);
+    assert_eq!(bf.get_hasher(), HashFunction::Xxh3);
+    assert_eq!(bf.get_indexing(), IndexingScheme::PerSeed);
+}
+
+#[test]
+/// Test that the per-slice diagnostics show every slice filling at the same pace
+fn test_slice_fill_statistics() {
+    let mut bf: PartitionedBloomFilter = PartitionedBloomFilter::new(5000, 0.01);
+    for i in 0..5000u64 {
+        Filter::insert(&mut bf, &i);
+    }
+    let slices = bf.slice_fill_statistics();
+    assert_eq!(slices.len() as u64, bf.get_hash_count());
+    let expected_fill: f64 = 0.01f64.powf(1.0 / bf.get_hash_count() as f64);
+    for slice in slices.iter() {
+        assert_eq!(slice.get_len(), bf.get_slice_len());
+        assert!((slice.get_fill_ratio() - expected_fill).abs() < 0.03);
+    }
+    assert!(bf.fill_statistics().get_false_positive_rate() <= 0.012);
+}
+
+#[test]
+/// Test that a saved filter round-trips and can't be loaded as another kind of filter
+fn test_save_load() {
+    let mut bf: PartitionedBloomFilter = PartitionedBloomFilter::new(1000, 0.01);
+    for i in 0..1000u64 {
+        bf.insert(&i);
+    }
+    let mut bytes: Vec<u8> = Vec::new();
+    bf.save(&mut bytes).unwrap();
+    let loaded = PartitionedBloomFilter::load(&mut &bytes[..]).unwrap();
+    assert!((0..1000u64).all(|i| loaded.check(&i)));
+    assert_eq!(
+        bloom::bloom::BloomFilter::load(&mut &bytes[..]).err(),
+        Some(Error::Corrupt("unexpected filter kind"))
+    );
+}
@@ -0,0 +1,172 @@
+/**
+ * test_persistence.rs
+ *
+ * Integration tests for saving and loading filters in the versioned binary format.
+ */
+use bloom::bloom::{
+    A2BufferingBloomFilter, BigenerationalBloomFilter, BloomFilter, Error, Filter,
+    GenerationalBloomFilter, HashFunction, IndexingScheme, ManualClock, SipHasher,
+};
+use std::io::{Seek, SeekFrom};
+use std::time::Duration;
+
+#[test]
+/// Test that a filter saved to a file answers every query identically after loading
+fn test_save_load_file() {
+    let mut bf = BloomFilter::with_hashing(
+        1000,
+        0.01,
+        HashFunction::SipHash(SipHasher::new(7, 11)),
+        IndexingScheme::DoubleHashing,
+    );
+    for i in 0..800u64 {
+        bf.insert(&i);
+    }
+    let mut file = tempfile::tempfile().unwrap();
+    bf.save(&mut file).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let loaded = BloomFilter::load(&mut file).unwrap();
+    assert_eq!(loaded.get_len(), bf.get_len());
+    assert_eq!(loaded.get_hash_count(), bf.get_hash_count());
+    assert_eq!(loaded.get_actual_inserts(), 800);
+    assert_eq!(loaded.get_hasher(), bf.get_hasher());
+    for i in 0..5000u64 {
+        assert_eq!(loaded.check(&i), bf.check(&i));
+    }
+}
+
+#[test]
+/// Test that the header is validated
+fn test_invalid_header() {
+    let bf = BloomFilter::new(10, 0.1);
+    let mut buf: Vec<u8> = Vec::new();
+    bf.save(&mut buf).unwrap();
+    assert_eq!(&buf[..4], b"BLMF");
+
+    let mut bad_magic = buf.clone();
+    bad_magic[..4].copy_from_slice(b"JUNK");
+    assert_eq!(
+        BloomFilter::load(&mut &bad_magic[..]).err(),
+        Some(Error::InvalidMagic)
+    );
+    let mut bad_version = buf.clone();
+    bad_version[4] = 3;
+    assert_eq!(
+        BloomFilter::load(&mut &bad_version[..]).err(),
+        Some(Error::UnsupportedVersion(3))
+    );
+    assert!(matches!(
+        BloomFilter::load(&mut &buf[..3]),
+        Err(Error::Io(_))
+    ));
+}
+
+/// Insert 0..inserts, save and reload with the given functions, then keep inserting into both
+/// filters (crossing a rotation) and check that they stay identical
+fn assert_resumes<F, S, L>(mut filter: F, inserts: u64, save: S, load: L)
+where
+    F: Filter,
+    S: Fn(&F, &mut Vec<u8>),
+    L: Fn(&mut &[u8]) -> F,
+{
+    for i in 0..inserts {
+        filter.insert(&i);
+    }
+    let mut buf: Vec<u8> = Vec::new();
+    save(&filter, &mut buf);
+    let mut loaded = load(&mut &buf[..]);
+    assert_eq!(loaded.get_actual_inserts(), filter.get_actual_inserts());
+    for i in inserts..inserts + 150 {
+        filter.insert(&i);
+        loaded.insert(&i);
+        assert_eq!(loaded.get_actual_inserts(), filter.get_actual_inserts());
+    }
+    for i in 0..2000u64 {
+        assert_eq!(loaded.check(&i), filter.check(&i), "item {}", i);
+    }
+}
+
+#[test]
+/// Test that a GenerationalBloomFilter resumes with the same generations after a reload
+fn test_generational_resumes() {
+    assert_resumes(
+        GenerationalBloomFilter::new(100, 0.01, 3),
+        250,
+        |f, buf| f.save(buf).unwrap(),
+        |buf| GenerationalBloomFilter::load(buf).unwrap(),
+    );
+}
+
+#[test]
+/// Test that a GenerationalBloomFilter keeps its time window across a reload, and that the
+/// time it spent saved counts towards the expiry of its generations
+fn test_generational_time_window_resumes() {
+    let clock = ManualClock::new();
+    clock.advance(Duration::from_secs(1000));
+    let mut bf = GenerationalBloomFilter::new(100, 0.01, 3)
+        .with_clock(clock.clone())
+        .with_generation_duration(Duration::from_secs(60));
+    for i in 0..10 {
+        bf.insert(&i);
+    }
+    clock.advance(Duration::from_secs(30));
+    let mut buf: Vec<u8> = Vec::new();
+    bf.save(&mut buf).unwrap();
+
+    // Reloaded 20s later, the current generation has 10s left
+    clock.advance(Duration::from_secs(20));
+    let loaded = GenerationalBloomFilter::load_with_clock(&mut &buf[..], clock.clone()).unwrap();
+    assert_eq!(
+        loaded.get_generation_duration(),
+        Some(Duration::from_secs(60))
+    );
+    assert_eq!(loaded.get_actual_inserts(), 10);
+    assert!((0..10).all(|i| loaded.check_current(&i)));
+    clock.advance(Duration::from_secs(10));
+    assert!(!loaded.check_current(&0));
+    assert!(loaded.check(&0));
+
+    // Reloaded after a long downtime, every generation has expired
+    clock.advance(Duration::from_secs(3600));
+    let loaded = GenerationalBloomFilter::load_with_clock(&mut &buf[..], clock.clone()).unwrap();
+    assert_eq!(loaded.get_actual_inserts(), 0);
+    assert!(!(0..10).any(|i| loaded.check(&i)));
+}
+
+#[test]
+/// Test that a BigenerationalBloomFilter resumes with the same active generation
+fn test_bigenerational_resumes() {
+    assert_resumes(
+        BigenerationalBloomFilter::new(100, 0.01),
+        130,
+        |f, buf| f.save(buf).unwrap(),
+        |buf| BigenerationalBloomFilter::load(buf).unwrap(),
+    );
+}
+
+#[test]
+/// Test that an A2BufferingBloomFilter resumes with the same active generation
+fn test_a2buffering_resumes() {
+    assert_resumes(
+        A2BufferingBloomFilter::new(100, 0.01),
+        130,
+        |f, buf| f.save(buf).unwrap(),
+        |buf| A2BufferingBloomFilter::load(buf).unwrap(),
+    );
+}
+
+#[test]
+/// Test that loading a file of a different filter type fails
+fn test_wrong_kind() {
+    let mut buf: Vec<u8> = Vec::new();
+    BloomFilter::new(10, 0.1).save(&mut buf).unwrap();
+    assert_eq!(
+        GenerationalBloomFilter::load(&mut &buf[..]).err(),
+        Some(Error::Corrupt("unexpected filter kind"))
+    );
+    let mut buf: Vec<u8> = Vec::new();
+    BigenerationalBloomFilter::new(10, 0.1)
+        .save(&mut buf)
+        .unwrap();
+    assert!(A2BufferingBloomFilter::load(&mut &buf[..]).is_err());
+}
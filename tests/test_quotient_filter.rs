@@ -0,0 +1,105 @@
+/**
+ * test_quotient_filter.rs
+ *
+ * Integration tests for QuotientFilter.
+ */
+use bloom::bloom::params;
+use bloom::bloom::{DeletableFilter, Error, HashFunction, QuotientFilter};
+
+#[test]
+/// Test that the getters are visible from outside the crate
+fn test_getters() {
+    let qf: QuotientFilter = QuotientFilter::with_hasher(10000, 0.001, HashFunction::Xxh3);
+    assert_eq!(
+        qf.get_quotient_bits(),
+        params::quotient_filter_quotient_bits(10000)
+    );
+    assert_eq!(
+        qf.get_remainder_bits(),
+        params::quotient_filter_remainder_bits(10000, 0.001)
+    );
+    assert_eq!(qf.get_len(), 1 << qf.get_quotient_bits());
+    assert!(qf.get_false_positive_rate() <= 0.001);
+    assert_eq!(qf.get_expected_inserts(), 10000);
+    assert_eq!(qf.get_actual_inserts(), 0);
+    assert_eq!(qf.get_hasher(), HashFunction::Xxh3);
+}
+
+#[test]
+/// Test that the measured false positive rate meets the target at the expected inserts
+fn test_false_positive_rate() {
+    let mut qf: QuotientFilter = QuotientFilter::new(10000, 0.01);
+    for i in 0..10000 {
+        qf.insert(&i);
+    }
+    assert!((0..10000).all(|i| qf.check(&i)));
+    let false_positives: usize = (10000..110000).filter(|i| qf.check(i)).count();
+    assert!(false_positives < 1000, "{}", false_positives);
+    let estimate: f64 = qf.fill_statistics().get_false_positive_rate();
+    assert!((estimate - false_positives as f64 / 100000.0).abs() < 0.002);
+}
+
+#[test]
+/// Test that a full filter can be doubled in place, keeping its items, to take more
+fn test_double_capacity() {
+    let mut qf: QuotientFilter = QuotientFilter::new(100, 0.01);
+    let len: u64 = qf.get_len();
+    for i in 0..len {
+        qf.insert(&i);
+    }
+    assert_eq!(qf.try_insert(&len), Err(Error::FilterFull));
+    qf.double_capacity().unwrap();
+    assert_eq!(qf.get_len(), 2 * len);
+    assert!((0..len).all(|i| qf.check(&i)));
+    for i in len..2 * len {
+        qf.insert(&i);
+    }
+    assert!((0..2 * len).all(|i| qf.check(&i)));
+}
+
+#[test]
+/// Test that two filters built separately merge into one holding the items of both
+fn test_merge() {
+    let mut a: QuotientFilter = QuotientFilter::new(1000, 0.01);
+    let mut b: QuotientFilter = QuotientFilter::new(1000, 0.01);
+    for i in 0..1000 {
+        a.insert(&i);
+        b.insert(&(i + 1000));
+    }
+    a.merge_with(&b).unwrap();
+    assert_eq!(a.get_expected_inserts(), 2000);
+    assert_eq!(a.get_actual_inserts(), 2000);
+    assert!((0..2000).all(|i| a.check(&i)));
+    assert!((2000..12000).filter(|i| a.check(i)).count() < 150);
+
+    let c: QuotientFilter = QuotientFilter::with_hasher(1000, 0.01, HashFunction::Xxh3);
+    assert_eq!(a.merge(&c).err(), Some(Error::IncompatibleFilters));
+}
+
+/// Insert every item, then delete the first half of them, generically over the filter type
+fn insert_then_delete_half<F: DeletableFilter>(filter: &mut F, items: &[u64]) {
+    for item in items {
+        filter.insert(item);
+    }
+    for item in &items[..items.len() / 2] {
+        filter.delete(item);
+    }
+}
+
+#[test]
+/// Test that deletion works through the DeletableFilter trait, and duplicates are counted
+fn test_deletable_filter() {
+    let mut qf: QuotientFilter = QuotientFilter::new(1000, 0.001);
+    let items: Vec<u64> = (0..1000).collect();
+    insert_then_delete_half(&mut qf, &items);
+    assert!(items[500..].iter().all(|i| qf.check(i)));
+    assert!(items[..500].iter().filter(|i| qf.check(*i)).count() < 5);
+    assert_eq!(qf.get_actual_inserts(), 500);
+
+    qf.insert("duplicate");
+    qf.insert("duplicate");
+    assert_eq!(qf.count("duplicate"), 2);
+    qf.delete("duplicate");
+    assert_eq!(qf.count("duplicate"), 1);
+    assert_eq!(qf.try_delete("never inserted"), Ok(()));
+}
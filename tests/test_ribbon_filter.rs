@@ -0,0 +1,55 @@
+/**
+ * test_ribbon_filter.rs
+ *
+ * Integration tests for RibbonFilter.
+ */
+use bloom::bloom::params;
+use bloom::bloom::{BloomFilter, HashFunction, RibbonFilter};
+
+#[test]
+/// Test that the getters are visible from outside the crate
+fn test_getters() {
+    let items: Vec<u64> = (0..10000).collect();
+    let rf: RibbonFilter = RibbonFilter::with_hasher(&items, 0.01, HashFunction::Xxh3);
+    assert_eq!(rf.get_num_items(), 10000);
+    assert_eq!(rf.get_num_blocks(), params::ribbon_num_blocks(10000));
+    assert_eq!(rf.get_len(), rf.get_num_blocks() * 64);
+    assert_eq!(rf.get_upper_columns(), 7);
+    assert_eq!(rf.get_false_positive_rate(), 0.01);
+    assert_eq!(rf.get_hasher(), HashFunction::Xxh3);
+}
+
+#[test]
+/// Test that a rate between powers of two is met, in less space than a Bloom filter
+fn test_false_positive_rate() {
+    let items: Vec<String> = (0..100000).map(|i| format!("key-{}", i)).collect();
+    let rf: RibbonFilter = RibbonFilter::new(&items, 0.003);
+    assert!(items.iter().all(|i| rf.check(i)));
+    let false_positives: usize = (0..1000000)
+        .filter(|i| rf.check(&format!("other-{}", i)))
+        .count();
+    let measured: f64 = false_positives as f64 / 1000000.0;
+    let realized: f64 = rf.fill_statistics().get_false_positive_rate();
+    assert!(realized <= 0.003);
+    assert!(
+        (measured - realized).abs() < 0.0003,
+        "{} {}",
+        measured,
+        realized
+    );
+
+    let bits_per_item: f64 = rf.get_bits_per_item();
+    let bloom: BloomFilter = BloomFilter::new(100000, 0.003);
+    assert!(bits_per_item < 9.2, "{}", bits_per_item);
+    assert!(bloom.get_len() as f64 / 100000.0 > 1.3 * bits_per_item);
+}
+
+#[test]
+/// Test that a large set builds, with little overhead over the fingerprint bits
+fn test_large() {
+    let items: Vec<u64> = (0..1000000).collect();
+    let rf: RibbonFilter = RibbonFilter::new(&items, 0.01);
+    assert!(items.iter().all(|i| rf.check(i)));
+    let overhead: f64 = rf.get_bits_per_item() / (1.0 / 0.01f64).log2();
+    assert!(overhead < 1.1, "{}", overhead);
+}
@@ -0,0 +1,59 @@
+/**
+ * test_scalable_bloom_filter.rs
+ *
+ * Integration tests for ScalableBloomFilter.
+ */
+use bloom::bloom::{Error, Filter, ScalableBloomFilter};
+
+#[test]
+/// Test that the getters are visible from outside the crate
+fn test_getters() {
+    let bf: ScalableBloomFilter = ScalableBloomFilter::new(10000, 0.01, 2.0, 0.9);
+    assert_eq!(bf.get_num_stages(), 1);
+    assert_eq!(bf.get_growth_factor(), 2.0);
+    assert_eq!(bf.get_tightening_ratio(), 0.9);
+    assert!(bf.get_hash_count() > 0);
+    assert_eq!(bf.get_false_positive_rate(), 0.01);
+    assert_eq!(bf.get_expected_inserts(), 10000);
+    assert_eq!(bf.get_actual_inserts(), 0);
+}
+
+#[test]
+/// Test that a filter sized far too small keeps every item, and keeps its measured false
+/// positive rate under the target, where a BloomFilter would degrade
+fn test_unbounded_growth() {
+    let mut bf: ScalableBloomFilter = ScalableBloomFilter::new(1000, 0.01, 2.0, 0.8);
+    for i in 0..100000u64 {
+        bf.insert(&i);
+    }
+    assert!(bf.get_num_stages() >= 6);
+    assert!((0..100000u64).all(|i| bf.check(&i)));
+    assert!(bf.get_compound_false_positive_rate() < 0.01);
+    let false_positives = (100000..200000u64).filter(|i| bf.check(i)).count();
+    assert!(false_positives < 1000, "{}", false_positives);
+}
+
+#[test]
+/// Test that the filter can be used through the Filter trait
+fn test_filter_trait() {
+    let mut bf: ScalableBloomFilter = ScalableBloomFilter::new(10, 0.01, 4.0, 0.9);
+    for i in 0..100u64 {
+        Filter::insert(&mut bf, &i);
+    }
+    assert!(Filter::check(&mut bf, &50u64));
+    Filter::empty(&mut bf);
+    assert_eq!(Filter::get_actual_inserts(&bf), 0);
+}
+
+#[test]
+/// Test that invalid scaling parameters are reported
+fn test_invalid_parameters() {
+    assert_eq!(
+        ScalableBloomFilter::try_new(100, 0.01, 0.9, 0.9).err(),
+        Some(Error::InvalidGrowthFactor(0.9))
+    );
+    assert_eq!(
+        ScalableBloomFilter::try_new(100, 0.01, 2.0, 0.0).err(),
+        Some(Error::InvalidTighteningRatio(0.0))
+    );
+}
@@ -0,0 +1,115 @@
+#![cfg(feature = "serde")]
+/**
+ * test_serde.rs
+ *
+ * Integration tests for the optional serde implementations. Run with `--features serde`.
+ */
+use bloom::bloom::{
+    A2BufferingBloomFilter, BigenerationalBloomFilter, BloomFilter, CountingBloomFilter, Filter,
+    GenerationalBloomFilter, HashFunction, IndexingScheme, PartitionedBloomFilter,
+    ScopeDecayBloomFilter, SipHasher,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Round-trip a filter through JSON, bincode and CBOR, and check that every copy answers
+/// queries like the original
+fn assert_round_trips<F: Filter + Serialize + DeserializeOwned>(filter: &mut F) {
+    for i in 0..300u64 {
+        filter.insert(&i);
+    }
+    let json: String = serde_json::to_string(filter).unwrap();
+    let bin: Vec<u8> = bincode::serialize(filter).unwrap();
+    let mut cbor: Vec<u8> = Vec::new();
+    ciborium::ser::into_writer(filter, &mut cbor).unwrap();
+
+    let mut copies: Vec<F> = vec![
+        serde_json::from_str(&json).unwrap(),
+        bincode::deserialize(&bin).unwrap(),
+        ciborium::de::from_reader(&cbor[..]).unwrap(),
+    ];
+    let expected: Vec<bool> = (0..1000u64).map(|i| filter.check(&i)).collect();
+    for copy in copies.iter_mut() {
+        assert_eq!(copy.get_actual_inserts(), filter.get_actual_inserts());
+        assert_eq!(copy.get_hash_count(), filter.get_hash_count());
+        let answers: Vec<bool> = (0..1000u64).map(|i| copy.check(&i)).collect();
+        assert_eq!(answers, expected);
+    }
+}
+
+#[test]
+/// Test that every filter type round-trips through serde
+fn test_round_trip_every_type() {
+    assert_round_trips(&mut BloomFilter::with_hashing(
+        200,
+        0.01,
+        HashFunction::SipHash(SipHasher::new(3, 4)),
+        IndexingScheme::PerSeed,
+    ));
+    assert_round_trips(&mut CountingBloomFilter::new(200, 0.01));
+    assert_round_trips(&mut ScopeDecayBloomFilter::new(200, 0.01, 0.1));
+    assert_round_trips(&mut GenerationalBloomFilter::new(100, 0.01, 3));
+    assert_round_trips(
+        &mut GenerationalBloomFilter::new(100, 0.01, 3)
+            .with_generation_duration(Duration::from_secs(3600)),
+    );
+    assert_round_trips(&mut BigenerationalBloomFilter::new(100, 0.01));
+    assert_round_trips(&mut A2BufferingBloomFilter::new(100, 0.01));
+    assert_round_trips(&mut PartitionedBloomFilter::new(200, 0.01));
+}
+
+#[test]
+/// Test that the bit array is stored as a byte string, not as one value per bit
+fn test_compact_bits() {
+    let bf = BloomFilter::new(10000, 0.01);
+    let bin: Vec<u8> = bincode::serialize(&bf).unwrap();
+    assert!(bin.len() < (bf.get_len() / 8 + 200) as usize);
+    let cbf = CountingBloomFilter::new(10000, 0.01);
+    let bin: Vec<u8> = bincode::serialize(&cbf).unwrap();
+    assert!(bin.len() < (cbf.get_len() + 200) as usize);
+}
+
+#[test]
+/// Test that a payload whose len doesn't match its storage is rejected
+fn test_invalid_payload() {
+    let bf = BloomFilter::new(100, 0.01);
+    let mut json: serde_json::Value = serde_json::to_value(&bf).unwrap();
+    json["len"] = serde_json::json!(100000);
+    let err = serde_json::from_value::<BloomFilter>(json.clone())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("payload length"), "{}", err);
+    json["len"] = serde_json::json!(0);
+    assert!(serde_json::from_value::<BloomFilter>(json).is_err());
+
+    let cbf = CountingBloomFilter::new(100, 0.01);
+    let mut json: serde_json::Value = serde_json::to_value(&cbf).unwrap();
+    json["len"] = serde_json::json!(101);
+    assert!(serde_json::from_value::<CountingBloomFilter>(json).is_err());
+
+    let sdbf = ScopeDecayBloomFilter::new(100, 0.01, 0.1);
+    let mut json: serde_json::Value = serde_json::to_value(&sdbf).unwrap();
+    json["bit_reset_rate"] = serde_json::json!(1.5);
+    assert!(serde_json::from_value::<ScopeDecayBloomFilter>(json).is_err());
+
+    let pbf = PartitionedBloomFilter::new(100, 0.01);
+    let mut json: serde_json::Value = serde_json::to_value(&pbf).unwrap();
+    json["hash_count"] = serde_json::json!(pbf.get_hash_count() + 1);
+    let err = serde_json::from_value::<PartitionedBloomFilter>(json)
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().contains("multiple of the hash count"),
+        "{}",
+        err
+    );
+
+    let gbf = GenerationalBloomFilter::new(100, 0.01, 3);
+    let mut json: serde_json::Value = serde_json::to_value(&gbf).unwrap();
+    json["generations"][1] = serde_json::to_value(BloomFilter::new(50, 0.01)).unwrap();
+    let err = serde_json::from_value::<GenerationalBloomFilter>(json)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("different parameters"), "{}", err);
+}
@@ -0,0 +1,77 @@
+/**
+ * test_stable_bloom_filter.rs
+ *
+ * Integration tests for StableBloomFilter.
+ */
+use bloom::bloom::params;
+use bloom::bloom::{Filter, HashFunction, IndexingScheme, StableBloomFilter};
+
+#[test]
+/// Test that the getters are visible from outside the crate, and match the sizing helpers
+fn test_getters() {
+    let sbf: StableBloomFilter = StableBloomFilter::with_hasher(50000, 0.003, HashFunction::Xxh3);
+    let hash_count: u64 = params::stable_hash_count(0.003);
+    let max_cell_value: u64 = params::stable_max_cell_value(50000, hash_count, 0.003);
+    assert_eq!(sbf.get_len(), 50000);
+    assert_eq!(sbf.get_hash_count(), hash_count);
+    assert_eq!(sbf.get_max_cell_value(), max_cell_value);
+    assert_eq!(
+        sbf.get_decrements(),
+        params::stable_decrement_count(50000, hash_count, max_cell_value, 0.003)
+    );
+    assert!(sbf.get_stable_false_positive_rate() <= 0.003);
+    assert_eq!(sbf.get_hasher(), HashFunction::Xxh3);
+    assert_eq!(sbf.get_indexing(), IndexingScheme::default());
+}
+
+/// Insert a stream of items through the Filter trait, and measure the false positive rate on
+/// items never inserted
+fn stream_false_positive_rate<F: Filter>(filter: &mut F, start: u64, len: u64) -> f64 {
+    for i in start..start + len {
+        filter.insert(&i);
+    }
+    let false_positives: usize = (0..200000u64)
+        .filter(|i| filter.check(&format!("absent-{}", i)))
+        .count();
+    false_positives as f64 / 200000.0
+}
+
+#[test]
+/// Test that the false positive rate levels off at the stable rate over an unbounded stream,
+/// rather than growing with the number of inserts
+fn test_stable_false_positive_rate() {
+    let mut sbf: StableBloomFilter = StableBloomFilter::new(20000, 0.01);
+    let stable: f64 = sbf.get_stable_false_positive_rate();
+    let mut start: u64 = 0;
+    for _ in 0..4 {
+        let rate: f64 = stream_false_positive_rate(&mut sbf, start, 50000);
+        assert!((rate - stable).abs() < 0.25 * stable, "{} {}", rate, stable);
+        start += 50000;
+    }
+    assert_eq!(sbf.get_actual_inserts(), 200000);
+}
+
+#[test]
+/// Test that an explicit layout converges close to the rate computed for it
+fn test_explicit_layout() {
+    let mut sbf: StableBloomFilter = StableBloomFilter::with_layout(
+        20000,
+        4,
+        1,
+        10,
+        HashFunction::Murmur3,
+        IndexingScheme::PerSeed,
+    );
+    let stable: f64 = params::stable_false_positive_rate(20000, 4, 1, 10);
+    assert_eq!(sbf.get_false_positive_rate(), stable);
+    let rate: f64 = stream_false_positive_rate(&mut sbf, 0, 100000);
+    let estimate: f64 = sbf.fill_statistics().get_false_positive_rate();
+    assert!(
+        (rate - estimate).abs() < 0.15 * estimate,
+        "{} {}",
+        rate,
+        estimate
+    );
+    // The stable point assumes independent cells, so it is only close
+    assert!((rate - stable).abs() < 0.2 * stable, "{} {}", rate, stable);
+}